@@ -0,0 +1,298 @@
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use defguard_wireguard_rs::host::{Host, Peer};
+use defguard_wireguard_rs::key::Key;
+use defguard_wireguard_rs::net::IpAddrMask;
+use defguard_wireguard_rs::{InterfaceConfiguration, WGApi, WireguardInterfaceApi};
+
+use crate::data::config::{AppConfig, WireGuardBackendKind};
+use crate::data::wireguard_data::WireGuardData;
+use crate::error::AppError;
+use crate::wireguard;
+
+const REKEY_INTERVAL: Duration = Duration::from_secs(120);
+const HANDSHAKE_INITIATION_BYTES: u64 = 148;
+const HANDSHAKE_RESPONSE_BYTES: u64 = 92;
+
+// everything the panel does with the tunnel, so that it can run against a fake without root
+pub trait WireGuardBackend: Send + Sync {
+    // listen port and peers with their statistics as the interface currently has them
+    fn read_interface_data(&self) -> Result<Host, AppError>;
+    fn is_up(&self) -> bool;
+    // the data is only used by backends that do not read the config file written for wg-quick
+    fn start(&self, data: &WireGuardData) -> Result<(), io::Error>;
+    fn stop(&self) -> Result<(), io::Error>;
+    // applies changed peers without dropping the interface or the statistics of unchanged peers,
+    // and a changed private key or listen port
+    fn reload(&self, data: &WireGuardData) -> Result<(), io::Error>;
+}
+
+pub fn create_backend(config: &AppConfig) -> Result<Arc<dyn WireGuardBackend>, AppError> {
+    Ok(match config.wireguard_backend {
+        WireGuardBackendKind::Kernel => Arc::new(WgQuickBackend {
+            wg_api: WGApi::new(config.wireguard_interface.to_owned(), false)?,
+            interface: config.wireguard_interface.to_owned(),
+            userspace: false,
+        }),
+        // the library talks to the userspace implementation through its UAPI socket
+        WireGuardBackendKind::Userspace => Arc::new(WgQuickBackend {
+            wg_api: WGApi::new(config.wireguard_interface.to_owned(), true)?,
+            interface: config.wireguard_interface.to_owned(),
+            userspace: true,
+        }),
+        WireGuardBackendKind::Memory => Arc::new(MemoryBackend::default()),
+    })
+}
+
+// an interface brought up by wg-quick and read through netlink or the userspace socket
+pub struct WgQuickBackend {
+    wg_api: WGApi,
+    interface: String,
+    userspace: bool,
+}
+
+impl WgQuickBackend {
+    // only the first server address is configured, like wg-quick does for a changed one
+    fn has_address(&self, address: &str) -> bool {
+        let address = match IpAddrMask::from_str(address) {
+            Ok(address) => address,
+            Err(_) => return true,
+        };
+        netdev::get_interfaces()
+            .iter()
+            .filter(|interface| interface.name == self.interface)
+            .any(|interface| {
+                interface
+                    .ipv4
+                    .iter()
+                    .any(|net| IpAddr::V4(net.addr) == address.ip && net.prefix_len == address.cidr)
+                    || interface.ipv6.iter().any(|net| {
+                        IpAddr::V6(net.addr) == address.ip && net.prefix_len == address.cidr
+                    })
+            })
+    }
+}
+
+impl WireGuardBackend for WgQuickBackend {
+    fn read_interface_data(&self) -> Result<Host, AppError> {
+        Ok(self.wg_api.read_interface_data()?)
+    }
+
+    fn is_up(&self) -> bool {
+        netdev::get_interfaces()
+            .iter()
+            .any(|interface| interface.name == self.interface)
+    }
+
+    // wg-quick fails on an interface that is already up or down, like the memory backend
+    // starting and stopping are no-ops then
+    fn start(&self, _data: &WireGuardData) -> Result<(), io::Error> {
+        if self.is_up() {
+            return Ok(());
+        }
+        match self.userspace {
+            true => wireguard::start_wireguard_userspace(&self.interface),
+            false => wireguard::start_wireguard(&self.interface),
+        }
+    }
+
+    fn stop(&self) -> Result<(), io::Error> {
+        if !self.is_up() {
+            return Ok(());
+        }
+        wireguard::stop_wireguard(&self.interface)
+    }
+
+    // like wg syncconf, peers are updated in place so unchanged ones keep their sessions
+    // a new private key or listen port ends every session anyway, so the interface is then
+    // configured as a whole
+    fn reload(&self, data: &WireGuardData) -> Result<(), io::Error> {
+        let host = self
+            .wg_api
+            .read_interface_data()
+            .map_err(io::Error::other)?;
+        let peers = configured_peers(data)?;
+        let (private_key, listen_port) = server_identity(data)?;
+        let address = data
+            .server
+            .as_ref()
+            .and_then(|server| server.address.first())
+            .cloned()
+            .unwrap_or_default();
+        if host.private_key.as_ref() != Some(&private_key)
+            || host.listen_port != listen_port
+            || !self.has_address(&address)
+        {
+            return self
+                .wg_api
+                .configure_interface(&InterfaceConfiguration {
+                    name: self.interface.to_owned(),
+                    prvkey: private_key.to_string(),
+                    address,
+                    port: u32::from(listen_port),
+                    peers,
+                })
+                .map_err(io::Error::other);
+        }
+        for peer in &peers {
+            // leaving a field out keeps its old value, zero is how a key or keepalive is cleared
+            let peer = Peer {
+                preshared_key: Some(
+                    peer.preshared_key
+                        .clone()
+                        .unwrap_or_else(|| Key::new([0; 32])),
+                ),
+                persistent_keepalive_interval: Some(
+                    peer.persistent_keepalive_interval.unwrap_or(0),
+                ),
+                ..peer.clone()
+            };
+            self.wg_api
+                .configure_peer(&peer)
+                .map_err(io::Error::other)?;
+        }
+        for key in host
+            .peers
+            .keys()
+            .filter(|key| !peers.iter().any(|peer| &peer.public_key == *key))
+        {
+            self.wg_api.remove_peer(key).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+// keeps the interface in memory, every peer completes a handshake as soon as it is added
+#[derive(Default)]
+pub struct MemoryBackend {
+    host: Mutex<Option<Host>>,
+}
+
+impl MemoryBackend {
+    fn build_host(data: &WireGuardData, previous: Option<&Host>) -> Result<Host, io::Error> {
+        let (private_key, listen_port) = server_identity(data)?;
+        let mut host = Host::new(listen_port, private_key);
+        let previous_peers = previous.map(|host| &host.peers);
+
+        for configured in configured_peers(data)? {
+            let peer = match previous_peers.and_then(|peers| peers.get(&configured.public_key)) {
+                Some(previous) => Peer {
+                    endpoint: previous.endpoint,
+                    last_handshake: previous.last_handshake,
+                    tx_bytes: previous.tx_bytes,
+                    rx_bytes: previous.rx_bytes,
+                    ..configured
+                },
+                None => Peer {
+                    last_handshake: Some(SystemTime::now()),
+                    ..configured
+                },
+            };
+            host.peers.insert(peer.public_key.clone(), peer);
+        }
+        Ok(host)
+    }
+}
+
+impl WireGuardBackend for MemoryBackend {
+    // peers renew their handshake like real ones do every two minutes, counting its bytes as traffic
+    fn read_interface_data(&self) -> Result<Host, AppError> {
+        let mut host = lock(&self.host);
+        let host = host.as_mut().ok_or_else(|| {
+            AppError::IO(io::Error::new(
+                io::ErrorKind::NotFound,
+                "The interface is not up",
+            ))
+        })?;
+        let now = SystemTime::now();
+        for peer in host.peers.values_mut() {
+            let stale = peer.last_handshake.is_none_or(|last_handshake| {
+                now.duration_since(last_handshake).unwrap_or_default() >= REKEY_INTERVAL
+            });
+            if stale {
+                peer.last_handshake = Some(now);
+                peer.tx_bytes += HANDSHAKE_INITIATION_BYTES;
+                peer.rx_bytes += HANDSHAKE_RESPONSE_BYTES;
+            }
+        }
+        Ok(host.clone())
+    }
+
+    fn is_up(&self) -> bool {
+        lock(&self.host).is_some()
+    }
+
+    fn start(&self, data: &WireGuardData) -> Result<(), io::Error> {
+        let mut host = lock(&self.host);
+        if host.is_none() {
+            *host = Some(Self::build_host(data, None)?);
+        }
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), io::Error> {
+        *lock(&self.host) = None;
+        Ok(())
+    }
+
+    fn reload(&self, data: &WireGuardData) -> Result<(), io::Error> {
+        let mut host = lock(&self.host);
+        if let Some(previous) = host.as_ref() {
+            *host = Some(Self::build_host(data, Some(previous))?);
+        }
+        Ok(())
+    }
+}
+
+// the private key and listen port the interface should have
+fn server_identity(data: &WireGuardData) -> Result<(Key, u16), io::Error> {
+    let server = data
+        .server
+        .as_ref()
+        .ok_or_else(|| invalid_data("No server has been created yet".to_string()))?;
+    let private_key = Key::from_str(&server.private_key)
+        .map_err(|_| invalid_data("Invalid server private key".to_string()))?;
+    Ok((private_key, server.listen_port))
+}
+
+// the peers the interface should have, without any statistics
+fn configured_peers(data: &WireGuardData) -> Result<Vec<Peer>, io::Error> {
+    data.clients
+        .iter()
+        .filter(|client| client.enabled && !client.is_expired())
+        .map(|client| {
+            let key = Key::from_str(&client.public_key).map_err(|_| {
+                invalid_data(format!("Invalid public key of client {}", client.name))
+            })?;
+            let preshared_key = match &client.preshared_key {
+                Some(preshared_key) => Some(Key::from_str(preshared_key).map_err(|_| {
+                    invalid_data(format!("Invalid preshared key of client {}", client.name))
+                })?),
+                None => None,
+            };
+            Ok(Peer {
+                preshared_key,
+                persistent_keepalive_interval: client.persistent_keep_alive,
+                allowed_ips: client
+                    .server_allowed_ips
+                    .iter()
+                    .filter_map(|address| IpAddrMask::from_str(address).ok())
+                    .collect(),
+                ..Peer::new(key)
+            })
+        })
+        .collect()
+}
+
+fn lock(host: &Mutex<Option<Host>>) -> std::sync::MutexGuard<'_, Option<Host>> {
+    // the host is replaced as a whole, so a panic can never leave it half updated
+    host.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,585 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use qrcode::render::unicode;
+use qrcode::QrCode;
+use uuid::Uuid;
+
+use crate::data::audit;
+use crate::data::client_config::{self, ConfigFormat};
+use crate::data::client_transfer::{self, TransferFormat};
+use crate::data::config::{AppConfig, ConfigOverrides};
+use crate::data::data_manager::{self, DataPaths};
+use crate::data::external_import::{self, ExternalSource};
+use crate::data::secrets::{self, StagedMasterKey};
+use crate::data::time::current_time_millis;
+use crate::data::wireguard_client::WireGuardOptionalClientData;
+use crate::data::wireguard_data::WireGuardData;
+use crate::data::wireguard_server::WireGuardOptionalServerData;
+use crate::error::{AppError, RestAPIError};
+use crate::logging::LogFormat;
+use crate::{backend, firewall, preflight, validation};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Manages a WireGuard server through a web API or the command line"
+)]
+pub struct Cli {
+    /// Path of the config file [default: config.yaml in the data directory]
+    #[arg(long, env = "PANEL_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+    /// Directory holding data.json and audit.log
+    #[arg(long, env = "PANEL_DATA_DIR", global = true, default_value = ".")]
+    pub data_dir: PathBuf,
+    #[command(flatten)]
+    pub overrides: ConfigOverrides,
+    #[arg(
+        long,
+        env = "PANEL_LOG_FORMAT",
+        global = true,
+        value_enum,
+        default_value = "text"
+    )]
+    pub log_format: LogFormat,
+    /// Minimum level of log messages, RUST_LOG directives take precedence
+    #[arg(long, env = "PANEL_LOG_LEVEL", global = true, default_value = "info")]
+    pub log_level: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Default, Subcommand)]
+pub enum Command {
+    /// Run the web API (default)
+    #[default]
+    Serve,
+    /// Manage clients
+    #[command(subcommand)]
+    Client(ClientCommand),
+    /// Manage the server
+    #[command(subcommand)]
+    Server(ServerCommand),
+    /// Write the WireGuard config, refresh the firewall and reload a running interface
+    Apply,
+    /// Import clients from a JSON or CSV export, or from wg-easy and wireguard-ui
+    Import {
+        path: PathBuf,
+        /// Format of an export of this app [default: from the file extension]
+        #[arg(long, value_enum, conflicts_with = "source")]
+        format: Option<TransferFormat>,
+        /// Import from another WireGuard panel instead
+        #[arg(long, value_enum)]
+        source: Option<ExternalSource>,
+        /// Endpoint to use if the source does not know it
+        #[arg(long, requires = "source")]
+        endpoint: Option<String>,
+        /// Replace the server with the one from the source
+        #[arg(long, requires = "source")]
+        import_server: bool,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export all clients
+    Export {
+        #[arg(long, value_enum, default_value = "json")]
+        format: TransferFormat,
+        /// Include private and preshared keys, only allowed with allow_secret_export
+        #[arg(long)]
+        include_secrets: bool,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Copy the config, data, audit log and admin token into a new timestamped directory
+    Backup {
+        #[arg(default_value = "backups")]
+        directory: PathBuf,
+    },
+    /// Check the config and data files without starting anything
+    CheckConfig,
+    /// Check that the host is prepared to run WireGuard
+    Preflight {
+        /// Enable IP forwarding now and across reboots
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Re-encrypt the data file with a new master key
+    Rekey,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ClientCommand {
+    /// Create a client with generated keys and the next free address
+    Add {
+        name: String,
+        #[arg(long)]
+        group: Option<String>,
+        #[arg(long)]
+        address: Option<String>,
+        /// Public key of a client that keeps its private key to itself
+        #[arg(long)]
+        public_key: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        dns: Option<Vec<String>>,
+        #[arg(long, value_delimiter = ',')]
+        allowed_ips: Option<Vec<String>>,
+        #[arg(long)]
+        disabled: bool,
+    },
+    /// List all clients
+    List,
+    /// Show a client by name or uuid
+    Show { client: String },
+    /// Remove a client by name or uuid
+    Rm { client: String },
+    /// Print the config file of a client
+    Config { client: String },
+    /// Print the config of a client as a QR code
+    Qr {
+        client: String,
+        /// Write an SVG image to this file instead of printing to the terminal
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ServerCommand {
+    /// Create the server with a new keypair
+    Init {
+        #[arg(long)]
+        endpoint: String,
+        #[arg(long, value_delimiter = ',')]
+        address: Option<Vec<String>>,
+        #[arg(long)]
+        listen_port: Option<u16>,
+        #[arg(long, value_delimiter = ',')]
+        dns: Option<Vec<String>>,
+        /// Replace an existing server
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the server
+    Show,
+}
+
+impl Cli {
+    pub fn paths(&self) -> DataPaths {
+        DataPaths {
+            config: self
+                .config
+                .clone()
+                .unwrap_or_else(|| self.data_dir.join("config.yaml")),
+            data_dir: self.data_dir.clone(),
+        }
+    }
+}
+
+impl Command {
+    // commands that write data.json, which a running server would overwrite
+    fn changes_data(&self) -> bool {
+        match self {
+            Command::Client(command) => {
+                matches!(
+                    command,
+                    ClientCommand::Add { .. } | ClientCommand::Rm { .. }
+                )
+            }
+            Command::Server(command) => matches!(command, ServerCommand::Init { .. }),
+            Command::Import { dry_run, .. } => !dry_run,
+            Command::Rekey => true,
+            _ => false,
+        }
+    }
+}
+
+// runs every command except serve, which needs the async runtime
+pub fn run(command: Command) -> Result<(), AppError> {
+    let _data_lock = command
+        .changes_data()
+        .then(data_manager::lock_data_dir)
+        .transpose()?;
+    let (config, mut data) = load()?;
+    match command {
+        Command::Serve => unreachable!("serve is started from main"),
+        Command::Client(command) => run_client(command, &config, &mut data),
+        Command::Server(command) => run_server(command, &config, &mut data),
+        Command::Apply => apply(&config, &data),
+        Command::Import {
+            path,
+            format,
+            source,
+            endpoint,
+            import_server,
+            dry_run,
+        } => import(
+            &config,
+            &mut data,
+            path,
+            format,
+            source,
+            endpoint,
+            import_server,
+            dry_run,
+        ),
+        Command::Export {
+            format,
+            include_secrets,
+            output,
+        } => {
+            // the same policy as the API, which the command line must not get around
+            if include_secrets && !config.allow_secret_export {
+                return Err(AppError::SecretExportDisabled);
+            }
+            let export = client_transfer::export_clients(format, &data.clients, include_secrets)?;
+            if include_secrets {
+                audit::record(
+                    "clients.secrets_exported",
+                    "cli",
+                    None,
+                    Some(format!("{} clients", data.clients.len())),
+                )?;
+            }
+            match output {
+                Some(output) => fs::write(output, export)?,
+                None => println!("{export}"),
+            }
+            Ok(())
+        }
+        Command::Backup { directory } => backup(directory),
+        Command::CheckConfig => check_config(&config, &data),
+        Command::Preflight { fix } => run_preflight(&config, &data, fix),
+        Command::Rekey => {
+            println!("Re-encrypting data file with a new master key");
+            let staged_key = secrets::rekey(&config)?;
+            if let Err(error) = data_manager::save_json_file(&data) {
+                secrets::discard_rekey(&staged_key);
+                return Err(error);
+            }
+            secrets::finish_rekey(&staged_key)?;
+            match &staged_key {
+                StagedMasterKey::File { path, .. } => {
+                    println!("Replaced {path}, the previous key is in {path}.old")
+                }
+                StagedMasterKey::Env { variable, path } => println!(
+                    "The new master key is in {path}, move it into {variable} before the next start"
+                ),
+                StagedMasterKey::Passphrase => {}
+            }
+            Ok(())
+        }
+    }
+}
+
+fn load() -> Result<(AppConfig, WireGuardData), AppError> {
+    let config = data_manager::read_config_file()?;
+    let mut data = data_manager::read_json_file()?;
+    secrets::install(secrets::init(&config, &mut data)?);
+    data_manager::upgrade_data(&config, &mut data);
+    Ok((config, data))
+}
+
+// only reached while no server holds the data directory
+fn save(data: &mut WireGuardData) -> Result<(), AppError> {
+    data.increment_revision();
+    data_manager::save_json_file(data)?;
+    println!("Saved");
+    Ok(())
+}
+
+fn run_client(
+    command: ClientCommand,
+    config: &AppConfig,
+    data: &mut WireGuardData,
+) -> Result<(), AppError> {
+    match command {
+        ClientCommand::Add {
+            name,
+            group,
+            address,
+            public_key,
+            dns,
+            allowed_ips,
+            disabled,
+        } => {
+            let client = WireGuardOptionalClientData {
+                name: Some(name),
+                enabled: Some(!disabled),
+                group,
+                address,
+                public_key,
+                dns,
+                client_allowed_ips: allowed_ips,
+                ..Default::default()
+            }
+            .to_wireguard_client_data(None, config, data)?;
+            check_errors(validation::validate_client(&client, data))?;
+            println!(
+                "Created client {} with address {}",
+                client.uuid, client.address
+            );
+            data.clients.push(client);
+            save(data)
+        }
+        ClientCommand::List => {
+            for client in &data.clients {
+                println!(
+                    "{}  {:<24}  {:<18}  {}",
+                    client.uuid.simple(),
+                    client.name,
+                    client.address,
+                    match (client.enabled, client.is_expired()) {
+                        (_, true) => "expired",
+                        (true, false) => "enabled",
+                        (false, false) => "disabled",
+                    }
+                );
+            }
+            Ok(())
+        }
+        ClientCommand::Show { client } => {
+            let client = &data.clients[find_client(data, &client)?];
+            println!("{}", serde_json::to_string_pretty(client)?);
+            Ok(())
+        }
+        ClientCommand::Rm { client } => {
+            let client = data.clients.remove(find_client(data, &client)?);
+            println!("Removed client {} ({})", client.name, client.uuid);
+            save(data)
+        }
+        ClientCommand::Config { client } => {
+            let config = render(data, &client, ConfigFormat::Conf)?;
+            println!("{config}");
+            Ok(())
+        }
+        ClientCommand::Qr { client, output } => match output {
+            Some(output) => Ok(fs::write(output, render(data, &client, ConfigFormat::Qr)?)?),
+            None => {
+                let config = render(data, &client, ConfigFormat::Conf)?;
+                let code = QrCode::new(config.as_bytes())?;
+                println!("{}", code.render::<unicode::Dense1x2>().build());
+                Ok(())
+            }
+        },
+    }
+}
+
+fn run_server(
+    command: ServerCommand,
+    config: &AppConfig,
+    data: &mut WireGuardData,
+) -> Result<(), AppError> {
+    match command {
+        ServerCommand::Init {
+            endpoint,
+            address,
+            listen_port,
+            dns,
+            force,
+        } => {
+            if data.server.is_some() && !force {
+                println!("The server already exists, use --force to replace it");
+                return Ok(());
+            }
+            let server = WireGuardOptionalServerData {
+                endpoint: Some(endpoint),
+                address,
+                dns,
+                listen_port,
+                private_key: None,
+                pre_up: None,
+                post_up: None,
+                pre_down: None,
+                post_down: None,
+                table: None,
+                mtu: None,
+            }
+            .to_wireguard_server_data(None, config)?;
+            check_errors(validation::validate_server(&server))?;
+            println!("Created server with public key {}", server.public_key);
+            data.server = Some(server);
+            save(data)
+        }
+        ServerCommand::Show => {
+            let server = data.server.as_ref().ok_or(AppError::ServerNotFound)?;
+            println!("{}", serde_json::to_string_pretty(server)?);
+            Ok(())
+        }
+    }
+}
+
+fn apply(config: &AppConfig, data: &WireGuardData) -> Result<(), AppError> {
+    if config.manages_host() {
+        data_manager::save_wireguard_config(data, config)?;
+        println!("Wrote {}", config.wireguard_config_path);
+    }
+    firewall::refresh_firewall(config, data)?;
+    let backend = backend::create_backend(config)?;
+    if backend.is_up() {
+        backend.reload(data)?;
+        println!("Reloaded {}", config.wireguard_interface);
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn import(
+    config: &AppConfig,
+    data: &mut WireGuardData,
+    path: PathBuf,
+    format: Option<TransferFormat>,
+    source: Option<ExternalSource>,
+    endpoint: Option<String>,
+    import_server: bool,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let mut imported = data.clone();
+    let rows = match source {
+        Some(source) => {
+            let mut import = external_import::read_external(source, &path.to_string_lossy())?;
+            if import_server || imported.server.is_none() {
+                if endpoint.is_some() {
+                    import.server.endpoint = endpoint;
+                }
+                let server = import.server.to_wireguard_server_data(
+                    imported
+                        .server
+                        .as_ref()
+                        .map(|server| server.endpoint.to_owned()),
+                    config,
+                )?;
+                check_errors(validation::validate_server(&server))?;
+                println!("Importing server with public key {}", server.public_key);
+                imported.server = Some(server);
+            }
+            import.rows
+        }
+        None => {
+            let format = format.unwrap_or(
+                match path.extension().is_some_and(|extension| extension == "csv") {
+                    true => TransferFormat::Csv,
+                    false => TransferFormat::Json,
+                },
+            );
+            client_transfer::parse_import(format, &fs::read_to_string(&path)?)?
+        }
+    };
+
+    let (imported, results) = client_transfer::import_clients(&rows, config, &imported);
+    for result in &results {
+        match result.success {
+            true => println!("[{}] ok", result.index),
+            false => println!(
+                "[{}] failed: {}",
+                result.index,
+                result.error.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+    match imported {
+        Some(_) if dry_run => {
+            println!("Dry run, nothing was saved");
+            Ok(())
+        }
+        Some(imported) => {
+            *data = imported;
+            save(data)
+        }
+        None => {
+            println!("Nothing was imported because of the failed rows");
+            Ok(())
+        }
+    }
+}
+
+fn backup(directory: PathBuf) -> Result<(), AppError> {
+    let paths = data_manager::paths();
+    let target = directory.join(format!("backup-{}", current_time_millis()));
+    fs::create_dir_all(&target)?;
+    // data.json is copied as stored, so secrets stay encrypted if a master key is configured
+    for file in [
+        paths.config.clone(),
+        data_manager::data_file(),
+        data_manager::audit_log_file(),
+        data_manager::admin_token_file(),
+    ] {
+        if file.exists() {
+            let name = file.file_name().unwrap_or_default();
+            fs::copy(&file, target.join(name))?;
+        }
+    }
+    println!("Backup written to {}", target.display());
+    Ok(())
+}
+
+fn check_config(config: &AppConfig, data: &WireGuardData) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+    if let Some(server) = &data.server {
+        errors.extend(validation::validate_server(server));
+    }
+    errors.extend(validation::validate_clients(
+        &data.clients,
+        data.server.as_ref(),
+    ));
+    for error in &errors {
+        println!("{}: {}", error.field, error.message);
+    }
+    if let Err(error) = config.get_network_interface_name() {
+        println!("network_interface: {error}");
+    }
+    if let Err(error) = config.get_wireguard_network_interface() {
+        println!("wireguard_interface: {error} (fine if the interface is not up yet)");
+    }
+    match errors.is_empty() {
+        true => {
+            println!("Config and data are valid");
+            Ok(())
+        }
+        false => Err(AppError::RestAPI(RestAPIError::ValidationFailed(errors))),
+    }
+}
+
+fn run_preflight(config: &AppConfig, data: &WireGuardData, fix: bool) -> Result<(), AppError> {
+    if fix {
+        preflight::fix_sysctls(data)?;
+        println!("Enabled IP forwarding");
+    }
+    let backend = backend::create_backend(config).ok();
+    let report = preflight::run_preflight(config, data, backend.as_deref());
+    for check in &report.checks {
+        println!("{:?}: {} - {}", check.status, check.name, check.message);
+        if let Some(fix) = &check.fix {
+            println!("    {fix}");
+        }
+    }
+    match report.passed {
+        true => Ok(()),
+        false => Err(AppError::PreflightFailed),
+    }
+}
+
+fn check_errors(errors: Vec<validation::FieldError>) -> Result<(), AppError> {
+    for error in &errors {
+        println!("{}: {}", error.field, error.message);
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(AppError::RestAPI(RestAPIError::ValidationFailed(errors))),
+    }
+}
+
+fn find_client(data: &WireGuardData, client: &str) -> Result<usize, AppError> {
+    let uuid = Uuid::parse_str(client).ok();
+    data.clients
+        .iter()
+        .position(|existing| Some(existing.uuid) == uuid || existing.name == client)
+        .ok_or_else(|| AppError::ClientNotFound(client.to_string()))
+}
+
+fn render(data: &WireGuardData, client: &str, format: ConfigFormat) -> Result<String, AppError> {
+    let client = &data.clients[find_client(data, client)?];
+    let server = data.server.as_ref().ok_or(AppError::ServerNotFound)?;
+    Ok(client_config::render_client_config(client, server, format)?.body)
+}
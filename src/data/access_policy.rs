@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AccessPolicy {
+    #[serde(default)]
+    pub isolation: IsolationMode,
+    #[serde(default = "HashMap::new")]
+    pub group_isolation: HashMap<String, IsolationMode>,
+    #[serde(default = "Vec::new")]
+    pub rules: Vec<AccessRule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationMode {
+    #[default]
+    AllowAll,
+    AllowWithinGroup,
+    IsolateAll,
+}
+
+// allows traffic between clients that the isolation mode would drop, traffic leaving the
+// tunnel for other networks is always forwarded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessRule {
+    pub source: AccessTarget,
+    pub destination: AccessTarget,
+    pub protocol: Option<Protocol>,
+    #[serde(default = "Vec::new")]
+    pub ports: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessTarget {
+    Client(Uuid),
+    Group(String),
+    Cidr(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl AccessPolicy {
+    pub fn isolation_for(&self, group: Option<&String>) -> IsolationMode {
+        group
+            .and_then(|group| self.group_isolation.get(group))
+            .copied()
+            .unwrap_or(self.isolation)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.isolation == IsolationMode::AllowAll
+            && self
+                .group_isolation
+                .values()
+                .all(|mode| mode == &IsolationMode::AllowAll)
+    }
+}
@@ -0,0 +1,59 @@
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::data_manager;
+use crate::data::time::current_time_millis;
+use crate::error::AppError;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    pub action: String,
+    pub actor: String,
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+// appends one JSON line per event so that the log never has to be rewritten
+pub fn record(
+    action: &str,
+    actor: &str,
+    target: Option<String>,
+    details: Option<String>,
+) -> Result<(), AppError> {
+    let event = AuditEvent {
+        timestamp: current_time_millis(),
+        action: action.to_string(),
+        actor: actor.to_string(),
+        target,
+        details,
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data_manager::audit_log_file())?;
+    writeln!(file, "{}", serde_json::to_string(&event)?)?;
+    Ok(())
+}
+
+pub fn read_events() -> Result<Vec<AuditEvent>, AppError> {
+    let file = match OpenOptions::new()
+        .read(true)
+        .open(data_manager::audit_log_file())
+    {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut events = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            events.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(events)
+}
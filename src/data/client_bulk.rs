@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::data::config::AppConfig;
+use crate::data::wireguard_client::{WireGuardClientData, WireGuardOptionalClientData};
+use crate::data::wireguard_data::WireGuardData;
+use crate::validation;
+use crate::validation::FieldError;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkClientRequest {
+    pub operations: Vec<BulkClientOperation>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkClientOperation {
+    Create {
+        client: WireGuardOptionalClientData,
+    },
+    Update {
+        uuid: Uuid,
+        client: WireGuardOptionalClientData,
+    },
+    Enable {
+        uuid: Uuid,
+    },
+    Disable {
+        uuid: Uuid,
+    },
+    Delete {
+        uuid: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkClientResult {
+    pub index: usize,
+    pub uuid: Option<Uuid>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<WireGuardClientData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkClientResponse {
+    pub applied: bool,
+    pub results: Vec<BulkClientResult>,
+}
+
+impl BulkClientResult {
+    fn success(index: usize, uuid: Uuid, client: Option<WireGuardClientData>) -> Self {
+        BulkClientResult {
+            index,
+            uuid: Some(uuid),
+            success: true,
+            client,
+            error: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn failure(
+        index: usize,
+        uuid: Option<Uuid>,
+        error: String,
+        errors: Vec<FieldError>,
+    ) -> Self {
+        BulkClientResult {
+            index,
+            uuid,
+            success: false,
+            client: None,
+            error: Some(error),
+            errors,
+        }
+    }
+}
+
+// runs every operation against a copy of `data`, later operations see the
+// results of earlier ones so that address allocation stays consistent,
+// the copy is only returned if every operation succeeded
+pub fn apply_bulk_operations(
+    operations: &[BulkClientOperation],
+    config: &AppConfig,
+    data: &WireGuardData,
+) -> (Option<WireGuardData>, Vec<BulkClientResult>) {
+    let mut working = data.clone();
+    let results: Vec<BulkClientResult> = operations
+        .iter()
+        .enumerate()
+        .map(|(index, operation)| apply_operation(index, operation, config, &mut working))
+        .collect();
+    if results.iter().all(|result| result.success) {
+        (Some(working), results)
+    } else {
+        (None, results)
+    }
+}
+
+fn apply_operation(
+    index: usize,
+    operation: &BulkClientOperation,
+    config: &AppConfig,
+    data: &mut WireGuardData,
+) -> BulkClientResult {
+    let uuid = match operation {
+        BulkClientOperation::Create { client } => client.uuid,
+        BulkClientOperation::Update { uuid, .. }
+        | BulkClientOperation::Enable { uuid }
+        | BulkClientOperation::Disable { uuid }
+        | BulkClientOperation::Delete { uuid } => Some(*uuid),
+    };
+
+    if let BulkClientOperation::Create { client } = operation {
+        let new_client = match client.to_wireguard_client_data(None, config, data) {
+            Ok(client) => client,
+            Err(error) => {
+                return BulkClientResult::failure(
+                    index,
+                    uuid,
+                    format!("Could not create client: {error}"),
+                    Vec::new(),
+                )
+            }
+        };
+        if data.get_client_config(&new_client.uuid).is_some() {
+            return BulkClientResult::failure(
+                index,
+                uuid,
+                format!("Client with uuid {} already exists", new_client.uuid),
+                Vec::new(),
+            );
+        }
+        let errors = validation::validate_client(&new_client, data);
+        if !errors.is_empty() {
+            return BulkClientResult::failure(index, uuid, "Validation failed".to_string(), errors);
+        }
+        data.clients.push(new_client.clone());
+        return BulkClientResult::success(index, new_client.uuid, Some(new_client));
+    }
+
+    let uuid = uuid.unwrap_or_default();
+    let client_index = match data.clients.iter().position(|client| client.uuid == uuid) {
+        Some(client_index) => client_index,
+        None => {
+            return BulkClientResult::failure(
+                index,
+                Some(uuid),
+                format!("Client config for uuid {} not found", uuid),
+                Vec::new(),
+            )
+        }
+    };
+
+    let mut client = data.clients[client_index].clone();
+    match operation {
+        BulkClientOperation::Update { client: update, .. } => {
+            client = match update.merge_into(&client, data) {
+                Ok(client) => client,
+                Err(error) => {
+                    return BulkClientResult::failure(
+                        index,
+                        Some(uuid),
+                        format!("Could not update client: {error}"),
+                        Vec::new(),
+                    )
+                }
+            };
+            let errors = validation::validate_client(&client, data);
+            if !errors.is_empty() {
+                return BulkClientResult::failure(
+                    index,
+                    Some(uuid),
+                    "Validation failed".to_string(),
+                    errors,
+                );
+            }
+        }
+        BulkClientOperation::Enable { .. } => client.set_enabled_by_admin(true),
+        BulkClientOperation::Disable { .. } => client.set_enabled_by_admin(false),
+        BulkClientOperation::Delete { .. } => {
+            data.clients.remove(client_index);
+            return BulkClientResult::success(index, uuid, None);
+        }
+        // handled above
+        BulkClientOperation::Create { .. } => {}
+    }
+    // every operation of a request leads to the same revision of the data
+    client.revision = data.revision + 1;
+    data.clients[client_index] = client.clone();
+    BulkClientResult::success(index, uuid, Some(client))
+}
@@ -0,0 +1,52 @@
+use qrcode::render::svg;
+use qrcode::QrCode;
+use serde::Deserialize;
+
+use crate::data::wireguard_client::WireGuardClientData;
+use crate::data::wireguard_server::WireGuardServerData;
+use crate::error::AppError;
+
+const QR_CODE_SIZE: u32 = 320;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    #[default]
+    Conf,
+    Qr,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConfigQuery {
+    #[serde(default)]
+    pub format: ConfigFormat,
+}
+
+pub struct RenderedConfig {
+    pub content_type: &'static str,
+    pub file_name: String,
+    pub body: String,
+}
+
+pub fn render_client_config(
+    client: &WireGuardClientData,
+    server: &WireGuardServerData,
+    format: ConfigFormat,
+) -> Result<RenderedConfig, AppError> {
+    let config = client.get_client_config(server.client_public_key(), &server.endpoint);
+    Ok(match format {
+        ConfigFormat::Conf => RenderedConfig {
+            content_type: "text/plain",
+            file_name: format!("{}.conf", client.name),
+            body: config,
+        },
+        ConfigFormat::Qr => RenderedConfig {
+            content_type: "image/svg+xml",
+            file_name: format!("{}.svg", client.name),
+            body: QrCode::new(config.as_bytes())?
+                .render::<svg::Color>()
+                .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+                .build(),
+        },
+    })
+}
@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+// a peer counts as online if it completed a handshake within this window,
+// WireGuard re-handshakes every two minutes while traffic is flowing
+const ONLINE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(180);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ClientQuery {
+    pub search: Option<String>,
+    pub enabled: Option<bool>,
+    pub group: Option<String>,
+    pub tag: Option<String>,
+    pub online: Option<bool>,
+    pub sort: Option<ClientSortKey>,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientSortKey {
+    Name,
+    Address,
+    Enabled,
+    LastHandshake,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// the fields of a client or peer that queries can look at
+pub struct ClientQueryItem<'a> {
+    pub name: &'a str,
+    pub address: &'a str,
+    pub public_key: &'a str,
+    pub enabled: bool,
+    pub group: Option<&'a String>,
+    pub tags: &'a [String],
+    pub last_handshake: Option<SystemTime>,
+}
+
+impl ClientQueryItem<'_> {
+    pub fn is_online(&self) -> bool {
+        self.last_handshake.is_some_and(|last_handshake| {
+            last_handshake
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed <= ONLINE_HANDSHAKE_TIMEOUT)
+        })
+    }
+}
+
+pub struct ClientQueryPage<'a, T> {
+    pub items: Vec<&'a T>,
+    pub total: usize,
+}
+
+impl ClientQuery {
+    pub fn needs_handshakes(&self) -> bool {
+        self.online.is_some() || self.sort == Some(ClientSortKey::LastHandshake)
+    }
+
+    pub fn apply<'a, T>(
+        &self,
+        items: &'a [T],
+        view: impl Fn(&'a T) -> ClientQueryItem<'a>,
+    ) -> ClientQueryPage<'a, T> {
+        let search = self.search.as_ref().map(|search| search.to_lowercase());
+        let mut matching: Vec<(&'a T, ClientQueryItem<'a>)> = items
+            .iter()
+            .map(|item| (item, view(item)))
+            .filter(|(_, item)| {
+                search.as_ref().is_none_or(|search| {
+                    item.name.to_lowercase().contains(search)
+                        || item.address.to_lowercase().contains(search)
+                        || item.public_key.to_lowercase().contains(search)
+                }) && self.enabled.is_none_or(|enabled| item.enabled == enabled)
+                    && self
+                        .group
+                        .as_ref()
+                        .is_none_or(|group| item.group == Some(group))
+                    && self.tag.as_ref().is_none_or(|tag| item.tags.contains(tag))
+                    && self.online.is_none_or(|online| item.is_online() == online)
+            })
+            .collect();
+
+        if let Some(sort) = self.sort {
+            matching.sort_by(|(_, a), (_, b)| {
+                let ordering = compare(sort, a, b);
+                match self.order {
+                    SortOrder::Asc => ordering,
+                    SortOrder::Desc => ordering.reverse(),
+                }
+            });
+        }
+
+        let total = matching.len();
+        let items = matching
+            .into_iter()
+            .map(|(item, _)| item)
+            .skip(self.offset.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        ClientQueryPage { items, total }
+    }
+}
+
+fn compare(sort: ClientSortKey, a: &ClientQueryItem, b: &ClientQueryItem) -> Ordering {
+    match sort {
+        ClientSortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        ClientSortKey::Address => match (parse_address(a.address), parse_address(b.address)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a.address.cmp(b.address),
+        },
+        ClientSortKey::Enabled => a.enabled.cmp(&b.enabled),
+        ClientSortKey::LastHandshake => a.last_handshake.cmp(&b.last_handshake),
+    }
+}
+
+fn parse_address(address: &str) -> Option<IpAddr> {
+    let ip = address
+        .rsplit_once('/')
+        .map(|(ip, _)| ip)
+        .unwrap_or(address);
+    IpAddr::from_str(ip).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Client {
+        name: &'static str,
+        address: &'static str,
+        enabled: bool,
+        group: Option<String>,
+        tags: Vec<String>,
+        last_handshake: Option<SystemTime>,
+    }
+
+    fn clients() -> Vec<Client> {
+        let now = SystemTime::now();
+        vec![
+            Client {
+                name: "phone",
+                address: "10.8.0.10/32",
+                enabled: true,
+                group: Some("staff".to_string()),
+                tags: vec!["mobile".to_string()],
+                last_handshake: Some(now),
+            },
+            Client {
+                name: "Laptop",
+                address: "10.8.0.9/32",
+                enabled: false,
+                group: Some("staff".to_string()),
+                tags: Vec::new(),
+                last_handshake: Some(now - Duration::from_secs(600)),
+            },
+            Client {
+                name: "tablet",
+                address: "10.8.0.2/32",
+                enabled: true,
+                group: None,
+                tags: vec!["mobile".to_string()],
+                last_handshake: None,
+            },
+        ]
+    }
+
+    fn names(query: ClientQuery) -> (Vec<&'static str>, usize) {
+        let clients = clients();
+        let page = query.apply(&clients, |client| ClientQueryItem {
+            name: client.name,
+            address: client.address,
+            public_key: "",
+            enabled: client.enabled,
+            group: client.group.as_ref(),
+            tags: &client.tags,
+            last_handshake: client.last_handshake,
+        });
+        (
+            page.items.iter().map(|client| client.name).collect(),
+            page.total,
+        )
+    }
+
+    fn query(json: serde_json::Value) -> ClientQuery {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn filters_are_combined() {
+        assert_eq!(
+            names(query(serde_json::json!({ "search": "LAP" }))),
+            (vec!["Laptop"], 1)
+        );
+        assert_eq!(
+            names(query(serde_json::json!({ "search": "10.8.0.2/" }))),
+            (vec!["tablet"], 1)
+        );
+        assert_eq!(
+            names(query(
+                serde_json::json!({ "group": "staff", "enabled": true })
+            )),
+            (vec!["phone"], 1)
+        );
+        assert_eq!(
+            names(query(serde_json::json!({ "tag": "mobile" }))),
+            (vec!["phone", "tablet"], 2)
+        );
+        assert_eq!(
+            names(query(serde_json::json!({ "online": false }))),
+            (vec!["Laptop", "tablet"], 2)
+        );
+    }
+
+    #[test]
+    fn sorting_compares_names_case_insensitively_and_addresses_numerically() {
+        assert_eq!(
+            names(query(serde_json::json!({ "sort": "name" }))).0,
+            ["Laptop", "phone", "tablet"]
+        );
+        assert_eq!(
+            names(query(serde_json::json!({ "sort": "address" }))).0,
+            ["tablet", "Laptop", "phone"]
+        );
+        assert_eq!(
+            names(query(
+                serde_json::json!({ "sort": "last_handshake", "order": "desc" })
+            ))
+            .0,
+            ["phone", "Laptop", "tablet"]
+        );
+        // without a sort key the stored order is kept
+        assert_eq!(
+            names(ClientQuery::default()).0,
+            ["phone", "Laptop", "tablet"]
+        );
+    }
+
+    #[test]
+    fn pages_count_every_match() {
+        let sorted = |offset: usize, limit: usize| {
+            query(serde_json::json!({ "sort": "name", "offset": offset, "limit": limit }))
+        };
+        assert_eq!(names(sorted(1, 1)), (vec!["phone"], 3));
+        assert_eq!(names(sorted(2, 5)), (vec!["tablet"], 3));
+        assert_eq!(names(sorted(3, 1)), (vec![], 3));
+        assert_eq!(names(sorted(usize::MAX, 1)), (vec![], 3));
+        assert_eq!(names(sorted(0, 0)), (vec![], 3));
+    }
+
+    #[test]
+    fn unknown_sort_keys_are_rejected() {
+        assert!(
+            serde_json::from_value::<ClientQuery>(serde_json::json!({ "sort": "uuid" })).is_err()
+        );
+        assert!(
+            serde_json::from_value::<ClientQuery>(serde_json::json!({ "order": "sideways" }))
+                .is_err()
+        );
+    }
+}
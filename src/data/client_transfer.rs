@@ -0,0 +1,380 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::data::client_bulk;
+use crate::data::client_bulk::{BulkClientOperation, BulkClientResult};
+use crate::data::config::AppConfig;
+use crate::data::wireguard_client::{WireGuardClientData, WireGuardOptionalClientData};
+use crate::data::wireguard_data::WireGuardData;
+use crate::error::AppError;
+
+// separator for list values inside a single CSV cell
+const CSV_LIST_SEPARATOR: char = ';';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub format: TransferFormat,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: TransferFormat,
+    #[serde(default)]
+    pub include_secrets: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientImportResponse {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub results: Vec<BulkClientResult>,
+}
+
+// the columns of a CSV import and export, lists are joined into a single cell
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ClientCsvRow {
+    name: String,
+    uuid: Option<Uuid>,
+    enabled: Option<bool>,
+    group: Option<String>,
+    tags: Option<String>,
+    address: Option<String>,
+    private_key: Option<String>,
+    public_key: Option<String>,
+    preshared_key: Option<String>,
+    generate_preshared_key: Option<bool>,
+    dns: Option<String>,
+    client_allowed_ips: Option<String>,
+    expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientExportRow {
+    pub name: String,
+    pub uuid: Uuid,
+    pub enabled: bool,
+    pub group: Option<String>,
+    pub tags: Vec<String>,
+    pub address: String,
+    pub public_key: String,
+    pub private_key: Option<String>,
+    pub preshared_key: Option<String>,
+    // false for clients without a preshared key, so that importing them does not generate one
+    pub generate_preshared_key: Option<bool>,
+    pub dns: Vec<String>,
+    pub client_allowed_ips: Vec<String>,
+    pub expires_at: Option<u64>,
+}
+
+// a row either becomes a create operation or fails on its own without stopping the others
+pub enum ParsedImportRow {
+    Operation(Box<BulkClientOperation>),
+    Invalid(String),
+}
+
+pub fn parse_import(format: TransferFormat, body: &str) -> Result<Vec<ParsedImportRow>, AppError> {
+    match format {
+        TransferFormat::Json => {
+            let clients: Vec<WireGuardOptionalClientData> = serde_json::from_str(body)?;
+            Ok(clients
+                .into_iter()
+                .map(|client| {
+                    ParsedImportRow::Operation(Box::new(BulkClientOperation::Create { client }))
+                })
+                .collect())
+        }
+        TransferFormat::Csv => Ok(csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(body.as_bytes())
+            .deserialize::<ClientCsvRow>()
+            .map(|row| match row {
+                Ok(row) => ParsedImportRow::Operation(Box::new(BulkClientOperation::Create {
+                    client: row.into_optional_client_data(),
+                })),
+                Err(error) => ParsedImportRow::Invalid(csv_row_error(error)),
+            })
+            .collect()),
+    }
+}
+
+// invalid rows are reported alongside the results of the create operations,
+// the new data is only returned if every row could be imported
+pub fn import_clients(
+    rows: &[ParsedImportRow],
+    config: &AppConfig,
+    data: &WireGuardData,
+) -> (Option<WireGuardData>, Vec<BulkClientResult>) {
+    let operations: Vec<BulkClientOperation> = rows
+        .iter()
+        .filter_map(|row| match row {
+            ParsedImportRow::Operation(operation) => Some(operation.as_ref().clone()),
+            ParsedImportRow::Invalid(_) => None,
+        })
+        .collect();
+    let (imported, operation_results) =
+        client_bulk::apply_bulk_operations(&operations, config, data);
+    let mut operation_results = operation_results.into_iter();
+    let results: Vec<BulkClientResult> = rows
+        .iter()
+        .enumerate()
+        .filter_map(|(index, row)| match row {
+            ParsedImportRow::Operation(_) => operation_results.next().map(|mut result| {
+                result.index = index;
+                result
+            }),
+            ParsedImportRow::Invalid(error) => Some(BulkClientResult::failure(
+                index,
+                None,
+                error.to_owned(),
+                Vec::new(),
+            )),
+        })
+        .collect();
+    let valid = results.iter().all(|result| result.success);
+    (imported.filter(|_| valid), results)
+}
+
+pub fn export_clients(
+    format: TransferFormat,
+    clients: &[WireGuardClientData],
+    include_secrets: bool,
+) -> Result<String, AppError> {
+    let rows: Vec<ClientExportRow> = clients
+        .iter()
+        .map(|client| ClientExportRow::from_client(client, include_secrets))
+        .collect();
+    match format {
+        TransferFormat::Json => Ok(serde_json::to_string_pretty(&rows)?),
+        TransferFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for row in rows {
+                writer
+                    .serialize(ClientCsvRow::from(row))
+                    .map_err(io_error)?;
+            }
+            let bytes = writer
+                .into_inner()
+                .map_err(|error| io_error(error.into_error()))?;
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+}
+
+impl ClientCsvRow {
+    fn into_optional_client_data(self) -> WireGuardOptionalClientData {
+        WireGuardOptionalClientData {
+            name: Some(self.name),
+            uuid: self.uuid,
+            enabled: self.enabled.or(Some(true)),
+            group: self.group.filter(|group| !group.is_empty()),
+            tags: self.tags.map(|tags| split_list(&tags)),
+            expires_at: self.expires_at,
+            generate_preshared_key: self.generate_preshared_key,
+            preshared_key: self.preshared_key.filter(|key| !key.is_empty()),
+            server_allowed_ips: None,
+            persistent_keep_alive: None,
+            private_key: self.private_key.filter(|key| !key.is_empty()),
+            public_key: self.public_key.filter(|key| !key.is_empty()),
+            address: self.address.filter(|address| !address.is_empty()),
+            client_allowed_ips: self
+                .client_allowed_ips
+                .map(|ips| split_list(&ips))
+                .filter(|ips| !ips.is_empty()),
+            dns: self
+                .dns
+                .map(|dns| split_list(&dns))
+                .filter(|dns| !dns.is_empty()),
+            mtu: None,
+            keys_rotated_at: None,
+        }
+    }
+}
+
+impl ClientExportRow {
+    fn from_client(client: &WireGuardClientData, include_secrets: bool) -> Self {
+        ClientExportRow {
+            name: client.name.to_owned(),
+            uuid: client.uuid,
+            enabled: client.enabled,
+            group: client.group.to_owned(),
+            tags: client.tags.to_owned(),
+            address: client.address.to_owned(),
+            public_key: client.public_key.to_owned(),
+            private_key: client.private_key.to_owned().filter(|_| include_secrets),
+            preshared_key: client.preshared_key.to_owned().filter(|_| include_secrets),
+            generate_preshared_key: (include_secrets && client.preshared_key.is_none())
+                .then_some(false),
+            dns: client.dns.to_owned(),
+            client_allowed_ips: client.client_allowed_ips.to_owned(),
+            expires_at: client.expires_at,
+        }
+    }
+}
+
+impl From<ClientExportRow> for ClientCsvRow {
+    fn from(row: ClientExportRow) -> Self {
+        ClientCsvRow {
+            name: row.name,
+            uuid: Some(row.uuid),
+            enabled: Some(row.enabled),
+            group: row.group,
+            tags: Some(join_list(&row.tags)),
+            address: Some(row.address),
+            private_key: row.private_key,
+            public_key: Some(row.public_key),
+            preshared_key: row.preshared_key,
+            generate_preshared_key: row.generate_preshared_key,
+            dns: Some(join_list(&row.dns)),
+            client_allowed_ips: Some(join_list(&row.client_allowed_ips)),
+            expires_at: row.expires_at,
+        }
+    }
+}
+
+fn csv_row_error(error: csv::Error) -> String {
+    let line = error.position().map(|position| position.line());
+    let message = match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+        _ => error.to_string(),
+    };
+    match line {
+        Some(line) => format!("Invalid CSV row on line {line}: {message}"),
+        None => format!("Invalid CSV row: {message}"),
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(CSV_LIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn join_list(values: &[String]) -> String {
+    values.join(&CSV_LIST_SEPARATOR.to_string())
+}
+
+fn io_error(error: impl Into<std::io::Error>) -> AppError {
+    AppError::IO(error.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::wireguard_server::WireGuardOptionalServerData;
+
+    fn config() -> AppConfig {
+        serde_yaml::from_str("{}").unwrap()
+    }
+
+    fn data() -> WireGuardData {
+        let server: WireGuardOptionalServerData = serde_json::from_value(serde_json::json!({
+            "endpoint": "vpn.example.com:51820",
+            "address": ["10.8.0.1/24"],
+        }))
+        .unwrap();
+        WireGuardData {
+            server: Some(server.to_wireguard_server_data(None, &config()).unwrap()),
+            ..Default::default()
+        }
+    }
+
+    // two clients that use every exported field, one of them holding its own private key
+    fn exported_data() -> WireGuardData {
+        let clients: Vec<WireGuardOptionalClientData> = serde_json::from_value(serde_json::json!([
+            {
+                "name": "phone",
+                "enabled": true,
+                "tags": ["mobile", "personal"],
+                "dns": ["1.1.1.1", "1.0.0.1"],
+                "client_allowed_ips": ["10.8.0.0/24", "192.168.1.0/24"],
+                "expires_at": 4102444800000u64,
+            },
+            {
+                "name": "laptop",
+                "enabled": false,
+                "public_key": "BqvHrNdoiKzcmUEVvH4xC6huaxY2wRnKfVdW+0Uw61c=",
+                "generate_preshared_key": false,
+            },
+        ]))
+        .unwrap();
+        let rows: Vec<ParsedImportRow> = clients
+            .into_iter()
+            .map(|client| {
+                ParsedImportRow::Operation(Box::new(BulkClientOperation::Create { client }))
+            })
+            .collect();
+        import_clients(&rows, &config(), &data()).0.unwrap()
+    }
+
+    #[test]
+    fn exports_import_into_identical_clients() {
+        let exported = exported_data();
+        for format in [TransferFormat::Json, TransferFormat::Csv] {
+            let export = export_clients(format, &exported.clients, true).unwrap();
+            let rows = parse_import(format, &export).unwrap();
+            let (imported, results) = import_clients(&rows, &config(), &data());
+            assert!(results.iter().all(|result| result.success), "{format:?}");
+            let imported = imported.unwrap();
+            assert_eq!(
+                export_clients(format, &imported.clients, true).unwrap(),
+                export,
+                "{format:?}"
+            );
+            for (imported, exported) in imported.clients.iter().zip(&exported.clients) {
+                assert_eq!(imported.private_key, exported.private_key);
+                assert_eq!(imported.preshared_key, exported.preshared_key);
+                assert_eq!(imported.server_allowed_ips, exported.server_allowed_ips);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_csv_rows_are_reported_with_their_line() {
+        let csv = "name,enabled,address,expires_at\n\
+                   phone,true,10.8.0.2/32,\n\
+                   laptop,maybe,10.8.0.3/32,\n\
+                   tablet,true,10.8.0.4/32,tomorrow\n";
+        let rows = parse_import(TransferFormat::Csv, csv).unwrap();
+        let (imported, results) = import_clients(&rows, &config(), &data());
+        assert!(imported.is_none());
+        let errors: Vec<(usize, Option<String>)> = results
+            .into_iter()
+            .map(|result| (result.index, result.error))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (0, None),
+                (
+                    1,
+                    Some(
+                        "Invalid CSV row on line 3: field 1: provided string was not `true` or `false`"
+                            .to_string()
+                    )
+                ),
+                (
+                    2,
+                    Some(
+                        "Invalid CSV row on line 4: field 3: invalid digit found in string"
+                            .to_string()
+                    )
+                ),
+            ]
+        );
+    }
+}
@@ -12,9 +12,188 @@ pub struct AppConfig {
     pub address: String,
     #[serde(default = "default_wireguard_config_path")]
     pub wireguard_config_path: String,
+    #[serde(default)]
+    pub firewall_backend: FirewallBackend,
+    // rotate preshared keys automatically after this many days
+    #[serde(default)]
+    pub preshared_key_rotation_days: Option<u32>,
+    // encrypts the private and preshared keys in data.json when set
+    #[serde(default)]
+    pub master_key: Option<MasterKeySource>,
+    // required as a bearer token on every admin route, generated into the data directory if unset
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    // lets integrations from before the admin token keep working while they are migrated,
+    // every admin route is open to anyone who can reach the API while this is false
+    #[serde(default = "default_require_admin_token")]
+    pub require_admin_token: bool,
+    // allows exports with private and preshared keys, which always need the admin token
+    #[serde(default)]
+    pub allow_secret_export: bool,
+    // runs wg-quick down when the service is stopped, otherwise the tunnel outlives it
+    #[serde(default)]
+    pub stop_interface_on_shutdown: bool,
+    // enables IP forwarding at startup and persists it in /etc/sysctl.d
+    #[serde(default)]
+    pub fix_sysctls: bool,
+    #[serde(default)]
+    pub wireguard_backend: WireGuardBackendKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum MasterKeySource {
+    // base64 key, generated if the file does not exist yet
+    File {
+        path: String,
+    },
+    // base64 key
+    Env {
+        #[serde(default = "default_master_key_variable")]
+        variable: String,
+    },
+    // read from WIREGUARD_UI_PASSPHRASE or prompted for at startup
+    Passphrase,
+}
+
+// the short form taken by PANEL_MASTER_KEY and --master-key
+impl std::str::FromStr for MasterKeySource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("file", path)) if !path.is_empty() => Ok(MasterKeySource::File {
+                path: path.to_string(),
+            }),
+            Some(("env", variable)) if !variable.is_empty() => Ok(MasterKeySource::Env {
+                variable: variable.to_string(),
+            }),
+            None if value == "env" => Ok(MasterKeySource::Env {
+                variable: default_master_key_variable(),
+            }),
+            None if value == "passphrase" => Ok(MasterKeySource::Passphrase),
+            _ => Err(format!(
+                "'{value}' is not one of file:<path>, env, env:<variable> or passphrase"
+            )),
+        }
+    }
+}
+
+// set through PANEL_* environment variables or command line flags, both win over the config file
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ConfigOverrides {
+    /// Address the web API listens on
+    #[arg(long, env = "PANEL_ADDRESS", global = true)]
+    pub address: Option<String>,
+    /// Name of the WireGuard interface
+    #[arg(long, env = "PANEL_WIREGUARD_INTERFACE", global = true)]
+    pub wireguard_interface: Option<String>,
+    /// Interface that forwarded traffic leaves through
+    #[arg(long, env = "PANEL_NETWORK_INTERFACE", global = true)]
+    pub network_interface: Option<String>,
+    /// Where the wg-quick config of the interface is written
+    #[arg(long, env = "PANEL_WIREGUARD_CONFIG_PATH", global = true)]
+    pub wireguard_config_path: Option<String>,
+    /// Firewall used for forwarding, NAT and access rules
+    #[arg(long, env = "PANEL_FIREWALL_BACKEND", global = true, value_enum)]
+    pub firewall_backend: Option<FirewallBackend>,
+    /// Rotate preshared keys automatically after this many days
+    #[arg(long, env = "PANEL_PRESHARED_KEY_ROTATION_DAYS", global = true)]
+    pub preshared_key_rotation_days: Option<u32>,
+    /// Bearer token for the admin API [default: generated into the data directory]
+    #[arg(long, env = "PANEL_ADMIN_TOKEN", global = true, hide_env_values = true)]
+    pub admin_token: Option<String>,
+    /// Require the admin token on admin routes, only turn this off while migrating integrations
+    #[arg(long, env = "PANEL_REQUIRE_ADMIN_TOKEN", global = true)]
+    pub require_admin_token: Option<bool>,
+    /// How the WireGuard interface is managed
+    #[arg(long, env = "PANEL_WIREGUARD_BACKEND", global = true, value_enum)]
+    pub wireguard_backend: Option<WireGuardBackendKind>,
+    /// Bring the interface down when the server stops
+    #[arg(long, env = "PANEL_STOP_INTERFACE_ON_SHUTDOWN", global = true)]
+    pub stop_interface_on_shutdown: Option<bool>,
+    /// Allow client exports with private and preshared keys
+    #[arg(long, env = "PANEL_ALLOW_SECRET_EXPORT", global = true)]
+    pub allow_secret_export: Option<bool>,
+    /// Enable IP forwarding at startup and persist it in /etc/sysctl.d
+    #[arg(long, env = "PANEL_FIX_SYSCTLS", global = true)]
+    pub fix_sysctls: Option<bool>,
+    /// Encrypt the keys in data.json with a key from file:<path>, env[:<variable>] or passphrase
+    #[arg(long, env = "PANEL_MASTER_KEY", global = true)]
+    pub master_key: Option<MasterKeySource>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FirewallBackend {
+    #[default]
+    Auto,
+    Nftables,
+    Iptables,
+    Disabled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WireGuardBackendKind {
+    #[default]
+    Kernel,
+    // wireguard-go for hosts without the kernel module, started even where the module exists,
+    // WG_QUICK_USERSPACE_IMPLEMENTATION selects another one such as boringtun
+    Userspace,
+    // simulated in memory without root, wg-quick or a real interface, for development and tests
+    Memory,
 }
 
 impl AppConfig {
+    // the simulated backend leaves the firewall and the wg-quick config of the host alone
+    pub fn manages_host(&self) -> bool {
+        self.wireguard_backend != WireGuardBackendKind::Memory
+    }
+
+    pub fn apply_overrides(&mut self, overrides: &ConfigOverrides) {
+        let overrides = overrides.clone();
+        if let Some(address) = overrides.address {
+            self.address = address;
+        }
+        if let Some(wireguard_interface) = overrides.wireguard_interface {
+            self.wireguard_interface = wireguard_interface;
+        }
+        if let Some(network_interface) = overrides.network_interface {
+            self.network_interface = network_interface;
+        }
+        if let Some(wireguard_config_path) = overrides.wireguard_config_path {
+            self.wireguard_config_path = wireguard_config_path;
+        }
+        if let Some(firewall_backend) = overrides.firewall_backend {
+            self.firewall_backend = firewall_backend;
+        }
+        if let Some(days) = overrides.preshared_key_rotation_days {
+            self.preshared_key_rotation_days = Some(days);
+        }
+        if let Some(admin_token) = overrides.admin_token {
+            self.admin_token = Some(admin_token);
+        }
+        if let Some(require_admin_token) = overrides.require_admin_token {
+            self.require_admin_token = require_admin_token;
+        }
+        if let Some(wireguard_backend) = overrides.wireguard_backend {
+            self.wireguard_backend = wireguard_backend;
+        }
+        if let Some(stop_interface_on_shutdown) = overrides.stop_interface_on_shutdown {
+            self.stop_interface_on_shutdown = stop_interface_on_shutdown;
+        }
+        if let Some(allow_secret_export) = overrides.allow_secret_export {
+            self.allow_secret_export = allow_secret_export;
+        }
+        if let Some(fix_sysctls) = overrides.fix_sysctls {
+            self.fix_sysctls = fix_sysctls;
+        }
+        if let Some(master_key) = overrides.master_key {
+            self.master_key = Some(master_key);
+        }
+    }
+
     pub fn get_network_interface_name(&self) -> Result<String, AppError> {
         if !self.network_interface.is_empty() {
             return Ok(self.network_interface.to_owned());
@@ -52,6 +231,14 @@ fn default_network_interface() -> String {
     "".to_string()
 }
 
+fn default_master_key_variable() -> String {
+    "WIREGUARD_UI_MASTER_KEY".to_string()
+}
+
+fn default_require_admin_token() -> bool {
+    true
+}
+
 fn default_address() -> String {
     "0.0.0.0:6252".to_string()
 }
@@ -59,3 +246,55 @@ fn default_address() -> String {
 fn default_wireguard_config_path() -> String {
     "/etc/wireguard/wg0.conf".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(args: &[&str]) -> ConfigOverrides {
+        #[derive(clap::Parser)]
+        struct Args {
+            #[command(flatten)]
+            overrides: ConfigOverrides,
+        }
+        let args = std::iter::once("panel").chain(args.iter().copied());
+        <Args as clap::Parser>::try_parse_from(args)
+            .unwrap()
+            .overrides
+    }
+
+    #[test]
+    fn overrides_win_over_file_only_defaults() {
+        let mut config: AppConfig = serde_yaml::from_str("{}").unwrap();
+        config.apply_overrides(&overrides(&[
+            "--allow-secret-export=true",
+            "--fix-sysctls=true",
+            "--master-key=file:/etc/panel/master.key",
+        ]));
+        assert!(config.allow_secret_export);
+        assert!(config.fix_sysctls);
+        assert!(matches!(
+            config.master_key,
+            Some(MasterKeySource::File { path }) if path == "/etc/panel/master.key"
+        ));
+    }
+
+    #[test]
+    fn master_key_short_form_names_every_source() {
+        assert!(matches!(
+            "env".parse(),
+            Ok(MasterKeySource::Env { variable }) if variable == "WIREGUARD_UI_MASTER_KEY"
+        ));
+        assert!(matches!(
+            "env:PANEL_KEY".parse(),
+            Ok(MasterKeySource::Env { variable }) if variable == "PANEL_KEY"
+        ));
+        assert!(matches!(
+            "passphrase".parse(),
+            Ok(MasterKeySource::Passphrase)
+        ));
+        for invalid in ["file:", "env:", "vault:key", "file"] {
+            assert!(invalid.parse::<MasterKeySource>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,30 +1,117 @@
-use std::fs::{File, OpenOptions};
+use std::fs::{File, OpenOptions, TryLockError};
 use std::io;
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use crate::data::config::AppConfig;
+use tracing::{info, warn};
+
+use crate::data::config::{AppConfig, ConfigOverrides};
+use crate::data::secrets;
+use crate::data::token;
 use crate::data::wireguard_data::WireGuardData;
 use crate::error::AppError;
 
+static PATHS: OnceLock<DataPaths> = OnceLock::new();
+static CONFIG_OVERRIDES: OnceLock<ConfigOverrides> = OnceLock::new();
+// the last storage check, probes would otherwise write to the disk on every request
+static STORAGE_CHECK: Mutex<Option<(Instant, Result<(), String>)>> = Mutex::new(None);
+
+const STORAGE_CHECK_TTL: Duration = Duration::from_secs(30);
+
+// where the files of the app live, the working directory unless set from the command line
+#[derive(Debug, Clone)]
+pub struct DataPaths {
+    pub config: PathBuf,
+    pub data_dir: PathBuf,
+}
+
+pub fn set_paths(paths: DataPaths) {
+    PATHS.set(paths).expect("Data paths can only be set once");
+}
+
+// applied on every read so that a reload keeps what the environment and command line set
+pub fn set_config_overrides(overrides: ConfigOverrides) {
+    CONFIG_OVERRIDES
+        .set(overrides)
+        .expect("Config overrides can only be set once");
+}
+
+pub fn paths() -> &'static DataPaths {
+    PATHS.get_or_init(|| DataPaths {
+        config: PathBuf::from("config.yaml"),
+        data_dir: PathBuf::from("."),
+    })
+}
+
+pub fn data_file() -> PathBuf {
+    paths().data_dir.join("data.json")
+}
+
+pub fn audit_log_file() -> PathBuf {
+    paths().data_dir.join("audit.log")
+}
+
+pub fn admin_token_file() -> PathBuf {
+    paths().data_dir.join("admin_token")
+}
+
+// the config file belongs to the operator, it is only ever read and may be missing
 pub fn read_config_file() -> Result<AppConfig, AppError> {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(true) // required to set truncate to false
-        .truncate(false)
-        .create(true)
-        .open("config.yaml")?;
-    let mut data = String::new();
-    file.read_to_string(&mut data)?;
+    let data = match std::fs::read_to_string(&paths().config) {
+        Ok(data) => data,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.into()),
+    };
+    let mut config: AppConfig = match data.trim().is_empty() {
+        true => serde_yaml::from_str("{}")?,
+        false => serde_yaml::from_str(&data)?,
+    };
+    if let Some(overrides) = CONFIG_OVERRIDES.get() {
+        config.apply_overrides(overrides);
+    }
+    Ok(config)
+}
 
-    let json: AppConfig = serde_yaml::from_str(&data)?;
-    Ok(json)
+// held by a running server for its whole lifetime, so that nothing else writes data.json
+// while the server would overwrite it with its own copy on the next save
+pub fn lock_data_dir() -> Result<File, AppError> {
+    let path = paths().data_dir.join("data.lock");
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(AppError::DataDirLocked(path.display().to_string())),
+        Err(TryLockError::Error(error)) => Err(error.into()),
+    }
 }
 
-pub fn save_config_file(config: &AppConfig) -> Result<(), AppError> {
-    let mut file = File::create("config.yaml")?;
-    let yaml = serde_yaml::to_string(config)?;
-    file.write_all(yaml.as_bytes())?;
-    Ok(())
+// used when no admin token is configured, generated once and kept next to the data
+pub fn read_or_create_admin_token() -> Result<String, AppError> {
+    let path = admin_token_file();
+    match std::fs::read_to_string(&path) {
+        Ok(admin_token) if !admin_token.trim().is_empty() => {
+            return Ok(admin_token.trim().to_string())
+        }
+        Ok(_) => {}
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+    let admin_token = token::generate_token();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)?;
+    file.write_all(admin_token.as_bytes())?;
+    info!("Generated admin token in {}", path.display());
+    Ok(admin_token)
 }
 
 pub fn read_json_file() -> Result<WireGuardData, AppError> {
@@ -33,7 +120,7 @@ pub fn read_json_file() -> Result<WireGuardData, AppError> {
         .write(true) // required to set truncate to false
         .truncate(false)
         .create(true)
-        .open("data.json")?;
+        .open(data_file())?;
     let mut data = String::new();
     file.read_to_string(&mut data)?;
 
@@ -45,17 +132,61 @@ pub fn read_json_file() -> Result<WireGuardData, AppError> {
     Ok(json)
 }
 
+// brings data written by older versions in line with the config, run after every read
+pub fn upgrade_data(config: &AppConfig, data: &mut WireGuardData) {
+    if data
+        .server
+        .as_mut()
+        .is_some_and(|server| server.remove_default_firewall_hooks(config))
+    {
+        warn!("Removed the iptables PostUp and PostDown hooks of the server, the firewall module manages forwarding and NAT");
+    }
+}
+
 pub fn save_json_file(data: &WireGuardData) -> Result<(), AppError> {
-    let mut file = File::create("data.json")?;
-    let json = serde_json::to_string_pretty(data)?;
+    let data = secrets::encrypt_for_storage(data)?;
+    let json = serde_json::to_string_pretty(&data)?;
+    // written next to the file and renamed so that being killed never leaves it half written
+    let path = data_file();
+    let temporary_path = path.with_extension("json.tmp");
+    let mut file = File::create(&temporary_path)?;
     file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(temporary_path, path)?;
     Ok(())
 }
 
+// creates and removes a file next to data.json, which fails on read-only or full filesystems,
+// the result is reused for a while
+pub fn check_storage_writable() -> Result<(), String> {
+    let mut last_check = STORAGE_CHECK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((checked_at, result)) = last_check.as_ref() {
+        if checked_at.elapsed() < STORAGE_CHECK_TTL {
+            return result.clone();
+        }
+    }
+    let result = write_check_file().map_err(|error| error.to_string());
+    *last_check = Some((Instant::now(), result.clone()));
+    result
+}
+
+fn write_check_file() -> Result<(), io::Error> {
+    let path = data_file().with_extension("json.check");
+    let mut file = File::create(&path)?;
+    file.write_all(b"ok")?;
+    file.sync_all()?;
+    std::fs::remove_file(path)
+}
+
 pub fn save_wireguard_config(
     data: &WireGuardData,
     app_config: &AppConfig,
 ) -> Result<(), io::Error> {
+    if !app_config.manages_host() {
+        return Ok(());
+    }
     let mut file = File::create(&app_config.wireguard_config_path)?;
     let config = data.get_server_config(app_config);
     file.write_all(config.unwrap_or_default().as_bytes())?;
@@ -0,0 +1,620 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::data::client_bulk::{BulkClientOperation, BulkClientResult};
+use crate::data::client_transfer::ParsedImportRow;
+use crate::data::time::parse_rfc3339_millis;
+use crate::data::wireguard_client::WireGuardOptionalClientData;
+use crate::data::wireguard_server::{WireGuardOptionalServerData, WireGuardServerData};
+use crate::error::AppError;
+
+// wg-easy only stores the server address, the subnet is always a /24
+const WG_EASY_PREFIX: u8 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ExternalSource {
+    // wg0.json of wg-easy
+    WgEasy,
+    // database directory of the Go wireguard-ui
+    WireguardUi,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExternalImportRequest {
+    #[serde(flatten)]
+    pub export: ExternalExport,
+    // used when the source does not know the public endpoint
+    pub endpoint: Option<String>,
+    #[serde(default)]
+    pub import_server: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalImportResponse {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub server: Option<WireGuardServerData>,
+    pub results: Vec<BulkClientResult>,
+}
+
+// the exported data itself, the API never reads files named by the caller
+#[derive(Debug, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum ExternalExport {
+    // content of wg0.json
+    WgEasy {
+        config: WgEasyConfig,
+    },
+    // content of the JSON files in the database directory
+    WireguardUi {
+        interfaces: WireguardUiInterface,
+        keypair: WireguardUiKeypair,
+        #[serde(default)]
+        global_settings: WireguardUiGlobalSettings,
+        // one entry per file in clients/, kept loose so that a broken one only fails its row
+        #[serde(default)]
+        clients: Vec<serde_json::Value>,
+    },
+}
+
+pub struct ExternalImport {
+    pub server: WireGuardOptionalServerData,
+    pub rows: Vec<ParsedImportRow>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WgEasyConfig {
+    server: WgEasyServer,
+    #[serde(default)]
+    clients: HashMap<String, WgEasyClient>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WgEasyServer {
+    private_key: String,
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WgEasyClient {
+    id: String,
+    name: String,
+    address: String,
+    private_key: Option<String>,
+    public_key: String,
+    pre_shared_key: Option<String>,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    created_at: Option<String>,
+    // only written by versions that support expiring clients
+    expired_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WireguardUiInterface {
+    #[serde(default)]
+    addresses: Vec<String>,
+    listen_port: Option<u16>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WireguardUiKeypair {
+    private_key: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WireguardUiGlobalSettings {
+    #[serde(default)]
+    endpoint_address: String,
+    #[serde(default)]
+    dns_servers: Vec<String>,
+    #[serde(default)]
+    mtu: u16,
+    #[serde(default)]
+    persistent_keepalive: u16,
+    #[serde(default)]
+    table: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WireguardUiClient {
+    name: String,
+    #[serde(default)]
+    private_key: String,
+    public_key: String,
+    #[serde(default)]
+    preshared_key: String,
+    #[serde(default)]
+    allocated_ips: Vec<String>,
+    #[serde(default)]
+    allowed_ips: Vec<String>,
+    #[serde(default)]
+    extra_allowed_ips: Vec<String>,
+    #[serde(default)]
+    use_server_dns: bool,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    created_at: Option<String>,
+}
+
+// only for the command line, where the caller can read the files anyway
+pub fn read_external(source: ExternalSource, path: &str) -> Result<ExternalImport, AppError> {
+    match source {
+        ExternalSource::WgEasy => Ok(parse_wg_easy(read_json(Path::new(path))?)),
+        ExternalSource::WireguardUi => read_wireguard_ui(Path::new(path)),
+    }
+}
+
+pub fn parse_export(export: ExternalExport) -> ExternalImport {
+    match export {
+        ExternalExport::WgEasy { config } => parse_wg_easy(config),
+        ExternalExport::WireguardUi {
+            interfaces,
+            keypair,
+            global_settings,
+            clients,
+        } => parse_wireguard_ui(
+            interfaces,
+            keypair,
+            global_settings,
+            clients
+                .into_iter()
+                .enumerate()
+                .map(|(index, client)| {
+                    serde_json::from_value(client)
+                        .map_err(|error| format!("Could not read clients[{index}]: {error}"))
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn parse_wg_easy(config: WgEasyConfig) -> ExternalImport {
+    let server = WireGuardOptionalServerData {
+        endpoint: None,
+        address: Some(vec![format!("{}/{WG_EASY_PREFIX}", config.server.address)]),
+        dns: None,
+        listen_port: None,
+        private_key: Some(config.server.private_key),
+        pre_up: None,
+        post_up: None,
+        pre_down: None,
+        post_down: None,
+        table: None,
+        mtu: None,
+    };
+
+    let mut clients: Vec<WgEasyClient> = config.clients.into_values().collect();
+    clients.sort_by(|a, b| a.name.cmp(&b.name));
+    let rows = clients
+        .into_iter()
+        .map(|client| {
+            create_row(WireGuardOptionalClientData {
+                name: Some(client.name),
+                uuid: Uuid::parse_str(&client.id).ok(),
+                enabled: Some(client.enabled),
+                group: None,
+                tags: None,
+                expires_at: client.expired_at.as_deref().and_then(parse_rfc3339_millis),
+                generate_preshared_key: Some(false),
+                preshared_key: client.pre_shared_key.filter(|key| !key.is_empty()),
+                server_allowed_ips: None,
+                persistent_keep_alive: None,
+                private_key: client.private_key.filter(|key| !key.is_empty()),
+                public_key: Some(client.public_key),
+                address: Some(format!("{}/32", client.address)),
+                client_allowed_ips: None,
+                dns: None,
+                mtu: None,
+                keys_rotated_at: client.created_at.as_deref().and_then(parse_rfc3339_millis),
+            })
+        })
+        .collect();
+    ExternalImport { server, rows }
+}
+
+fn read_wireguard_ui(path: &Path) -> Result<ExternalImport, AppError> {
+    let interface: WireguardUiInterface = read_json(&path.join("server/interfaces.json"))?;
+    let keypair: WireguardUiKeypair = read_json(&path.join("server/keypair.json"))?;
+    let global_settings_path = path.join("server/global_settings.json");
+    let settings: WireguardUiGlobalSettings = match global_settings_path.exists() {
+        true => read_json(&global_settings_path)?,
+        false => WireguardUiGlobalSettings::default(),
+    };
+
+    let clients_path = path.join("clients");
+    let mut client_files: Vec<_> = match clients_path.exists() {
+        true => fs::read_dir(&clients_path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?,
+        false => Vec::new(),
+    };
+    client_files.retain(|file| {
+        file.extension()
+            .is_some_and(|extension| extension == "json")
+    });
+    client_files.sort();
+    let clients = client_files
+        .iter()
+        .map(|file| {
+            read_json(file).map_err(|error| format!("Could not read {}: {error}", file.display()))
+        })
+        .collect();
+    Ok(parse_wireguard_ui(interface, keypair, settings, clients))
+}
+
+fn parse_wireguard_ui(
+    interface: WireguardUiInterface,
+    keypair: WireguardUiKeypair,
+    settings: WireguardUiGlobalSettings,
+    clients: Vec<Result<WireguardUiClient, String>>,
+) -> ExternalImport {
+    let listen_port = interface.listen_port.unwrap_or(51820);
+    let server = WireGuardOptionalServerData {
+        endpoint: (!settings.endpoint_address.is_empty())
+            .then(|| format!("{}:{listen_port}", settings.endpoint_address)),
+        address: Some(interface.addresses),
+        dns: Some(settings.dns_servers.clone()),
+        listen_port: Some(listen_port),
+        private_key: Some(keypair.private_key),
+        // its hooks set up forwarding and NAT by hand, which the firewall module already does
+        pre_up: None,
+        post_up: None,
+        pre_down: None,
+        post_down: None,
+        table: non_empty(settings.table),
+        mtu: (settings.mtu != 0).then_some(settings.mtu),
+    };
+
+    let rows = clients
+        .into_iter()
+        .map(|client| {
+            let client = match client {
+                Ok(client) => client,
+                Err(error) => return ParsedImportRow::Invalid(error),
+            };
+            let address = match client.allocated_ips.first() {
+                Some(address) => address.to_owned(),
+                None => {
+                    return ParsedImportRow::Invalid(format!(
+                        "Client '{}' has no allocated address",
+                        client.name
+                    ))
+                }
+            };
+            let mut server_allowed_ips = client.allocated_ips.clone();
+            server_allowed_ips.extend(client.extra_allowed_ips);
+            create_row(WireGuardOptionalClientData {
+                name: Some(client.name),
+                uuid: None,
+                enabled: Some(client.enabled),
+                group: None,
+                tags: None,
+                expires_at: None,
+                generate_preshared_key: Some(false),
+                preshared_key: non_empty(client.preshared_key),
+                server_allowed_ips: Some(server_allowed_ips),
+                persistent_keep_alive: (settings.persistent_keepalive != 0)
+                    .then_some(settings.persistent_keepalive),
+                private_key: non_empty(client.private_key),
+                public_key: Some(client.public_key),
+                address: Some(address),
+                client_allowed_ips: Some(client.allowed_ips).filter(|ips| !ips.is_empty()),
+                dns: Some(match client.use_server_dns {
+                    true => settings.dns_servers.clone(),
+                    false => Vec::new(),
+                }),
+                mtu: (settings.mtu != 0).then_some(settings.mtu),
+                keys_rotated_at: client.created_at.as_deref().and_then(parse_rfc3339_millis),
+            })
+        })
+        .collect();
+    ExternalImport { server, rows }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, AppError> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn create_row(client: WireGuardOptionalClientData) -> ParsedImportRow {
+    ParsedImportRow::Operation(Box::new(BulkClientOperation::Create { client }))
+}
+
+fn non_empty(value: String) -> Option<String> {
+    (!value.is_empty()).then_some(value)
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::client_transfer::import_clients;
+    use crate::data::config::AppConfig;
+    use crate::data::wireguard_client::WireGuardClientData;
+    use crate::data::wireguard_data::WireGuardData;
+
+    const SERVER_PRIVATE_KEY: &str = "YJFW40qeR+g7MVHDvtuJMhI2qctXy1mPSvLR0j6MF2g=";
+    const PHONE_PRIVATE_KEY: &str = "uBHQZNLOKYlJHVuWbN6N0Ht0dpwJ5ZBXsI72UpbXKXk=";
+    const PHONE_PUBLIC_KEY: &str = "1IEkwtnf2eQjj9+cxAYLNr9C0z8mNLTSOErAPeGExHA=";
+    const LAPTOP_PRIVATE_KEY: &str = "gDMJxIdfUUH2TGtOo3RYsefLFfifhOd1NqgYBBL9q38=";
+    const LAPTOP_PUBLIC_KEY: &str = "bDmfv4cvg3XdOS//G62/t7HHA+p/AiTvmed+6kYZWWA=";
+    const PHONE_PRESHARED_KEY: &str = "bk6WKf6NSmmzqHMPycanRQq7Q9gu2waW6eWamuwJJn8=";
+
+    fn config() -> AppConfig {
+        serde_yaml::from_str("{}").unwrap()
+    }
+
+    // imports the clients the way the API does after taking over the server
+    fn import(import: ExternalImport) -> WireGuardData {
+        let server = import
+            .server
+            .to_wireguard_server_data(Some("vpn.example.com:51820".into()), &config())
+            .unwrap();
+        let data = WireGuardData {
+            server: Some(server),
+            ..Default::default()
+        };
+        let (imported, results) = import_clients(&import.rows, &config(), &data);
+        assert!(results.iter().all(|result| result.success), "{results:?}");
+        imported.unwrap()
+    }
+
+    fn client<'a>(data: &'a WireGuardData, name: &str) -> &'a WireGuardClientData {
+        data.clients
+            .iter()
+            .find(|client| client.name == name)
+            .unwrap()
+    }
+
+    fn fixture_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("wireguard-ui-import-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("server")).unwrap();
+        fs::create_dir_all(dir.join("clients")).unwrap();
+        dir
+    }
+
+    fn write_json(path: std::path::PathBuf, value: serde_json::Value) {
+        fs::write(path, serde_json::to_string_pretty(&value).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn wg_easy_config_maps_onto_server_and_clients() {
+        let export: ExternalExport = serde_json::from_value(serde_json::json!({
+            "source": "wg_easy",
+            "config": {
+                "server": {
+                    "privateKey": SERVER_PRIVATE_KEY,
+                    "publicKey": "P5cJQG/GKHhf5qK9gXCmcQ0YJRF6yQ643ovvBGX5wi8=",
+                    "address": "10.8.0.1",
+                },
+                "clients": {
+                    "3f5e1c1a-8b2d-4c1e-9f6a-2b7d8e9f0a1b": {
+                        "id": "3f5e1c1a-8b2d-4c1e-9f6a-2b7d8e9f0a1b",
+                        "name": "phone",
+                        "address": "10.8.0.2",
+                        "privateKey": PHONE_PRIVATE_KEY,
+                        "publicKey": PHONE_PUBLIC_KEY,
+                        "preSharedKey": PHONE_PRESHARED_KEY,
+                        "createdAt": "2024-01-31T12:00:00.123Z",
+                        "updatedAt": "2024-02-01T08:00:00.000Z",
+                        "expiredAt": "2030-01-01T00:00:00.000Z",
+                        "enabled": true,
+                    },
+                    "9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c6d": {
+                        "id": "9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c6d",
+                        "name": "laptop",
+                        "address": "10.8.0.3",
+                        "privateKey": LAPTOP_PRIVATE_KEY,
+                        "publicKey": LAPTOP_PUBLIC_KEY,
+                        "createdAt": "2022-11-02T08:15:00-03:30",
+                        "enabled": false,
+                    },
+                },
+            },
+        }))
+        .unwrap();
+        let external = parse_export(export);
+        assert_eq!(
+            external.server.address,
+            Some(vec!["10.8.0.1/24".to_string()])
+        );
+        assert_eq!(
+            external.server.private_key.as_deref(),
+            Some(SERVER_PRIVATE_KEY)
+        );
+
+        let data = import(external);
+        let phone = client(&data, "phone");
+        assert_eq!(
+            phone.uuid,
+            Uuid::parse_str("3f5e1c1a-8b2d-4c1e-9f6a-2b7d8e9f0a1b").unwrap()
+        );
+        assert!(phone.enabled);
+        assert_eq!(phone.private_key.as_deref(), Some(PHONE_PRIVATE_KEY));
+        assert_eq!(phone.public_key, PHONE_PUBLIC_KEY);
+        assert_eq!(phone.preshared_key.as_deref(), Some(PHONE_PRESHARED_KEY));
+        assert_eq!(phone.address, "10.8.0.2/32");
+        assert_eq!(phone.server_allowed_ips, ["10.8.0.2/32"]);
+        assert_eq!(phone.keys_rotated_at, Some(1706702400123));
+        assert_eq!(phone.preshared_key_rotated_at, Some(1706702400123));
+        assert_eq!(phone.expires_at, Some(1893456000000));
+
+        let laptop = client(&data, "laptop");
+        assert_eq!(
+            laptop.uuid,
+            Uuid::parse_str("9a8b7c6d-5e4f-4a3b-8c2d-1e0f9a8b7c6d").unwrap()
+        );
+        assert!(!laptop.enabled);
+        assert_eq!(laptop.public_key, LAPTOP_PUBLIC_KEY);
+        assert_eq!(laptop.preshared_key, None);
+        assert_eq!(laptop.preshared_key_rotated_at, None);
+        assert_eq!(laptop.address, "10.8.0.3/32");
+        assert_eq!(laptop.keys_rotated_at, Some(1667389500000));
+        assert_eq!(laptop.expires_at, None);
+    }
+
+    #[test]
+    fn wireguard_ui_database_maps_onto_server_and_clients() {
+        let dir = fixture_dir("database");
+        write_json(
+            dir.join("server/interfaces.json"),
+            serde_json::json!({
+                "addresses": ["10.252.1.1/24"],
+                "listen_port": 51830,
+                "updated_at": "2023-06-15T10:00:00Z",
+            }),
+        );
+        write_json(
+            dir.join("server/keypair.json"),
+            serde_json::json!({
+                "private_key": SERVER_PRIVATE_KEY,
+                "public_key": "P5cJQG/GKHhf5qK9gXCmcQ0YJRF6yQ643ovvBGX5wi8=",
+                "updated_at": "2023-06-15T10:00:00Z",
+            }),
+        );
+        write_json(
+            dir.join("server/global_settings.json"),
+            serde_json::json!({
+                "endpoint_address": "vpn.example.org",
+                "dns_servers": ["1.1.1.1"],
+                "mtu": 1420,
+                "persistent_keepalive": 15,
+                "table": "auto",
+            }),
+        );
+        write_json(
+            dir.join("clients/ci4ejcl3e5m1s0fvl5o0.json"),
+            serde_json::json!({
+                "id": "ci4ejcl3e5m1s0fvl5o0",
+                "private_key": PHONE_PRIVATE_KEY,
+                "public_key": PHONE_PUBLIC_KEY,
+                "preshared_key": PHONE_PRESHARED_KEY,
+                "name": "phone",
+                "email": "",
+                "allocated_ips": ["10.252.1.2/32"],
+                "allowed_ips": ["0.0.0.0/0"],
+                "extra_allowed_ips": ["192.168.10.0/24"],
+                "use_server_dns": true,
+                "enabled": true,
+                "created_at": "2023-06-15T17:30:45.558741+07:00",
+                "updated_at": "2023-06-16T09:00:00+07:00",
+            }),
+        );
+        write_json(
+            dir.join("clients/ci4ejd53e5m1s0fvl5og.json"),
+            serde_json::json!({
+                "id": "ci4ejd53e5m1s0fvl5og",
+                "private_key": "",
+                "public_key": LAPTOP_PUBLIC_KEY,
+                "preshared_key": "",
+                "name": "laptop",
+                "allocated_ips": ["10.252.1.3/32"],
+                "allowed_ips": [],
+                "extra_allowed_ips": [],
+                "use_server_dns": false,
+                "enabled": false,
+                "created_at": "2024-01-31T12:00:00.123Z",
+            }),
+        );
+
+        let external = read_wireguard_ui(&dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            external.server.endpoint.as_deref(),
+            Some("vpn.example.org:51830")
+        );
+        assert_eq!(
+            external.server.address,
+            Some(vec!["10.252.1.1/24".to_string()])
+        );
+        assert_eq!(external.server.listen_port, Some(51830));
+        assert_eq!(
+            external.server.private_key.as_deref(),
+            Some(SERVER_PRIVATE_KEY)
+        );
+
+        let data = import(external);
+        let phone = client(&data, "phone");
+        assert!(phone.enabled);
+        assert_eq!(phone.private_key.as_deref(), Some(PHONE_PRIVATE_KEY));
+        assert_eq!(phone.public_key, PHONE_PUBLIC_KEY);
+        assert_eq!(phone.preshared_key.as_deref(), Some(PHONE_PRESHARED_KEY));
+        assert_eq!(phone.address, "10.252.1.2/32");
+        assert_eq!(
+            phone.server_allowed_ips,
+            ["10.252.1.2/32", "192.168.10.0/24"]
+        );
+        assert_eq!(phone.client_allowed_ips, ["0.0.0.0/0"]);
+        assert_eq!(phone.dns, ["1.1.1.1"]);
+        assert_eq!(phone.persistent_keep_alive, Some(15));
+        assert_eq!(phone.mtu, Some(1420));
+        assert_eq!(phone.keys_rotated_at, Some(1686825045558));
+        assert_eq!(phone.preshared_key_rotated_at, Some(1686825045558));
+
+        let laptop = client(&data, "laptop");
+        assert!(!laptop.enabled);
+        assert_eq!(laptop.private_key, None);
+        assert_eq!(laptop.public_key, LAPTOP_PUBLIC_KEY);
+        assert_eq!(laptop.preshared_key, None);
+        assert_eq!(laptop.address, "10.252.1.3/32");
+        assert!(laptop.dns.is_empty());
+        assert_eq!(laptop.keys_rotated_at, Some(1706702400123));
+
+        // its xid client ids are no UUIDs, the clients get fresh ones instead
+        assert_eq!(phone.uuid.get_version_num(), 4);
+        assert_eq!(laptop.uuid.get_version_num(), 4);
+        assert_ne!(phone.uuid, laptop.uuid);
+    }
+
+    #[test]
+    fn unreadable_wireguard_ui_clients_only_fail_their_row() {
+        let export: ExternalExport = serde_json::from_value(serde_json::json!({
+            "source": "wireguard_ui",
+            "interfaces": { "addresses": ["10.252.1.1/24"] },
+            "keypair": { "private_key": SERVER_PRIVATE_KEY },
+            "clients": [
+                {
+                    "id": "ci4ejcl3e5m1s0fvl5o0",
+                    "name": "phone",
+                    "public_key": PHONE_PUBLIC_KEY,
+                    "allocated_ips": ["10.252.1.2/32"],
+                },
+                { "id": "ci4ejd53e5m1s0fvl5og", "name": "laptop" },
+                { "name": "tablet", "public_key": LAPTOP_PUBLIC_KEY },
+            ],
+        }))
+        .unwrap();
+        let external = parse_export(export);
+        let errors: Vec<Option<&str>> = external
+            .rows
+            .iter()
+            .map(|row| match row {
+                ParsedImportRow::Operation(_) => None,
+                ParsedImportRow::Invalid(error) => Some(error.as_str()),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                None,
+                Some("Could not read clients[1]: missing field `public_key`"),
+                Some("Client 'tablet' has no allocated address"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::data::client_config::ConfigFormat;
+use crate::data::time::current_time_millis;
+use crate::data::token;
+
+const MILLIS_PER_HOUR: u64 = 60 * 60 * 1000;
+
+// only the hash of the token is stored, the token itself is shown once on creation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub token_hash: String,
+    pub target: InvitationTarget,
+    // shown to whoever holds the token, so it should not reveal more than the admin wants to
+    #[serde(default)]
+    pub label: Option<String>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub redeemed_at: Option<u64>,
+    pub redeemed_by: Option<String>,
+    // the client the invitation was redeemed for
+    pub client: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvitationTarget {
+    Client(Uuid),
+    // redeeming creates a new client in the group
+    Group(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateInvitationRequest {
+    pub target: InvitationTarget,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default = "default_expires_in_hours")]
+    pub expires_in_hours: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedInvitation {
+    pub invitation: Invitation,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RedeemInvitationRequest {
+    // name of the client created for group invitations
+    pub name: Option<String>,
+    // keeps the private key on the user's device
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub format: ConfigFormat,
+}
+
+// what an unauthenticated user may see before redeeming, never which client or group it is for
+#[derive(Debug, Clone, Serialize)]
+pub struct InvitationInfo {
+    pub label: Option<String>,
+    pub expires_at: u64,
+}
+
+impl Invitation {
+    pub fn new(
+        target: InvitationTarget,
+        label: Option<String>,
+        expires_in_hours: u32,
+    ) -> (Invitation, String) {
+        let token = token::generate_token();
+        let now = current_time_millis();
+        let invitation = Invitation {
+            id: Uuid::new_v4(),
+            token_hash: token::hash_token(&token),
+            target,
+            label,
+            created_at: now,
+            expires_at: now + expires_in_hours as u64 * MILLIS_PER_HOUR,
+            redeemed_at: None,
+            redeemed_by: None,
+            client: None,
+        };
+        (invitation, token)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= current_time_millis()
+    }
+
+    pub fn info(&self) -> InvitationInfo {
+        InvitationInfo {
+            label: self.label.clone(),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+fn default_expires_in_hours() -> u32 {
+    72
+}
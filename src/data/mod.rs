@@ -1,6 +1,20 @@
+pub mod access_policy;
+pub mod audit;
+pub mod client_bulk;
+pub mod client_config;
+pub mod client_query;
+pub mod client_transfer;
 pub mod config;
 pub mod data_manager;
+pub mod external_import;
+pub mod invitation;
+pub mod portal;
+pub mod secrets;
+pub mod time;
+pub mod token;
 pub mod wireguard_client;
 pub mod wireguard_data;
+pub mod wireguard_group;
 pub mod wireguard_peer;
 pub mod wireguard_server;
+pub mod wireguard_user;
@@ -0,0 +1,108 @@
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::data::time::current_time_millis;
+use crate::data::token;
+use crate::data::wireguard_client::WireGuardClientData;
+use crate::data::wireguard_peer::PeerUsage;
+
+// grants an end user access to their own clients through the portal API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortalToken {
+    pub id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub clients: Vec<Uuid>,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatePortalTokenRequest {
+    pub name: String,
+    pub clients: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedPortalToken {
+    pub portal_token: PortalToken,
+    pub token: String,
+}
+
+// attached to portal requests once the token has been checked
+#[derive(Debug, Clone)]
+pub struct PortalSession {
+    pub name: String,
+    pub clients: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PortalClientStatus {
+    #[serde(serialize_with = "uuid::serde::simple::serialize")]
+    pub uuid: Uuid,
+    pub name: String,
+    pub address: String,
+    pub enabled: bool,
+    pub expired: bool,
+    pub expires_at: Option<u64>,
+    pub online: bool,
+    pub last_handshake: Option<u64>,
+    pub transmitted_bytes: u64,
+    pub received_bytes: u64,
+    pub config_outdated: bool,
+    // the client can only be enabled again by an admin
+    pub admin_disabled: bool,
+}
+
+impl PortalToken {
+    pub fn new(name: String, clients: Vec<Uuid>) -> (PortalToken, String) {
+        let token = token::generate_token();
+        let portal_token = PortalToken {
+            id: Uuid::new_v4(),
+            name,
+            token_hash: token::hash_token(&token),
+            clients,
+            created_at: current_time_millis(),
+        };
+        (portal_token, token)
+    }
+
+    pub fn session(&self) -> PortalSession {
+        PortalSession {
+            name: self.name.to_owned(),
+            clients: self.clients.clone(),
+        }
+    }
+}
+
+impl PortalSession {
+    pub fn owns(&self, uuid: &Uuid) -> bool {
+        self.clients.contains(uuid)
+    }
+}
+
+impl PortalClientStatus {
+    pub fn new(client: &WireGuardClientData, usage: Option<&PeerUsage>) -> Self {
+        let usage = usage.copied().unwrap_or_default();
+        PortalClientStatus {
+            uuid: client.uuid,
+            name: client.name.to_owned(),
+            address: client.address.to_owned(),
+            enabled: client.enabled,
+            expired: client.is_expired(),
+            expires_at: client.expires_at,
+            online: client.query_item(usage.last_handshake).is_online(),
+            last_handshake: usage.last_handshake.and_then(|last_handshake| {
+                last_handshake
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|duration| duration.as_millis() as u64)
+            }),
+            transmitted_bytes: usage.transmitted_bytes,
+            received_bytes: usage.received_bytes,
+            config_outdated: client.config_outdated,
+            admin_disabled: client.admin_disabled,
+        }
+    }
+}
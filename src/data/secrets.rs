@@ -0,0 +1,476 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::RwLock;
+
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::data::config::{AppConfig, MasterKeySource};
+use crate::data::data_manager;
+use crate::data::wireguard_data::WireGuardData;
+use crate::error::{AppError, EncryptionError};
+
+const ENCRYPTED_PREFIX: &str = "enc:";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const SALT_LENGTH: usize = 16;
+// encrypted alongside the secrets so that a wrong master key is noticed before decrypting
+const KEY_CHECK: &str = "wireguard-ui";
+const PASSPHRASE_VARIABLE: &str = "WIREGUARD_UI_PASSPHRASE";
+const NEW_PASSPHRASE_VARIABLE: &str = "WIREGUARD_UI_NEW_PASSPHRASE";
+
+static MASTER_KEY: RwLock<Option<MasterKey>> = RwLock::new(None);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionMetadata {
+    // only set for keys derived from a passphrase
+    pub salt: Option<String>,
+    pub key_check: String,
+}
+
+// a new master key that is only used in memory until the data has been saved with it
+pub enum StagedMasterKey {
+    // written next to the configured file, which it replaces once the data is saved
+    File { path: String, staged_path: String },
+    // written to a file in the data directory for the operator to move into the variable
+    Env { variable: String, path: String },
+    Passphrase,
+}
+
+#[derive(Clone)]
+pub struct MasterKey {
+    key: [u8; KEY_LENGTH],
+    salt: Option<String>,
+}
+
+// loads the configured master key and decrypts the secrets of freshly read data,
+// plaintext secrets are left as they are and get encrypted on the next save once the
+// returned key is installed
+pub fn init(config: &AppConfig, data: &mut WireGuardData) -> Result<Option<MasterKey>, AppError> {
+    let metadata = data.encryption.take();
+    let master_key = match &config.master_key {
+        Some(source) => load_master_key(
+            source,
+            metadata
+                .as_ref()
+                .and_then(|metadata| metadata.salt.as_ref()),
+        )?,
+        None if metadata.is_some() => return Err(EncryptionError::MissingMasterKey.into()),
+        None => return Ok(None),
+    };
+    if let Some(metadata) = &metadata {
+        if master_key
+            .decrypt(&metadata.key_check, "key check")
+            .ok()
+            .as_deref()
+            != Some(KEY_CHECK)
+        {
+            return Err(EncryptionError::WrongMasterKey.into());
+        }
+    }
+    for_each_secret(data, |secret, name| {
+        if secret.starts_with(ENCRYPTED_PREFIX) {
+            *secret = master_key.decrypt(secret, name)?;
+        }
+        Ok(())
+    })?;
+    Ok(Some(master_key))
+}
+
+// makes the key the one the data is encrypted with on every following save
+pub fn install(master_key: Option<MasterKey>) {
+    *MASTER_KEY.write().unwrap() = master_key;
+}
+
+// returns the data as it should be written to disk
+pub fn encrypt_for_storage(data: &WireGuardData) -> Result<WireGuardData, AppError> {
+    let master_key = match MASTER_KEY.read().unwrap().clone() {
+        Some(master_key) => master_key,
+        None => return Ok(data.clone()),
+    };
+    let mut encrypted = data.clone();
+    for_each_secret(&mut encrypted, |secret, _| {
+        *secret = master_key.encrypt(secret);
+        Ok(())
+    })?;
+    encrypted.encryption = Some(EncryptionMetadata {
+        salt: master_key.salt.clone(),
+        key_check: master_key.encrypt(KEY_CHECK),
+    });
+    Ok(encrypted)
+}
+
+// replaces the master key in memory, the caller saves the data to re-encrypt it and then calls
+// finish_rekey, so that the key on disk never belongs to data that was not written yet
+pub fn rekey(config: &AppConfig) -> Result<StagedMasterKey, AppError> {
+    let source = match &config.master_key {
+        Some(source) => source,
+        None => return Err(EncryptionError::MissingMasterKey.into()),
+    };
+    let (master_key, staged_key) = match source {
+        MasterKeySource::File { path } => {
+            let master_key = MasterKey::generate();
+            let staged_path = format!("{path}.new");
+            replace_key_file(&staged_path, &master_key)?;
+            let staged_key = StagedMasterKey::File {
+                path: path.to_owned(),
+                staged_path,
+            };
+            (master_key, staged_key)
+        }
+        MasterKeySource::Env { variable } => {
+            let master_key = MasterKey::generate();
+            let path = data_manager::paths()
+                .data_dir
+                .join("master_key.new")
+                .display()
+                .to_string();
+            replace_key_file(&path, &master_key)?;
+            let staged_key = StagedMasterKey::Env {
+                variable: variable.to_owned(),
+                path,
+            };
+            (master_key, staged_key)
+        }
+        MasterKeySource::Passphrase => {
+            let passphrase = read_passphrase(NEW_PASSPHRASE_VARIABLE, "new master passphrase")?;
+            (
+                MasterKey::from_passphrase(&passphrase, None)?,
+                StagedMasterKey::Passphrase,
+            )
+        }
+    };
+    *MASTER_KEY.write().unwrap() = Some(master_key);
+    Ok(staged_key)
+}
+
+// moves a key file into place after the data was saved with it, the previous one is kept
+pub fn finish_rekey(staged_key: &StagedMasterKey) -> Result<(), AppError> {
+    if let StagedMasterKey::File { path, staged_path } = staged_key {
+        if std::path::Path::new(path).exists() {
+            std::fs::rename(path, format!("{path}.old"))?;
+        }
+        std::fs::rename(staged_path, path)?;
+    }
+    Ok(())
+}
+
+// for when saving the data failed, it is still encrypted with the previous key
+pub fn discard_rekey(staged_key: &StagedMasterKey) {
+    let staged_path = match staged_key {
+        StagedMasterKey::File { staged_path, .. } => staged_path,
+        StagedMasterKey::Env { path, .. } => path,
+        StagedMasterKey::Passphrase => return,
+    };
+    let _ = std::fs::remove_file(staged_path);
+}
+
+fn load_master_key(source: &MasterKeySource, salt: Option<&String>) -> Result<MasterKey, AppError> {
+    match source {
+        MasterKeySource::File { path } => {
+            let mut file = match File::open(path) {
+                Ok(file) => file,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                    info!("Generating master key in {path}");
+                    let master_key = MasterKey::generate();
+                    write_key_file(path, &master_key)?;
+                    return Ok(master_key);
+                }
+                Err(error) => return Err(error.into()),
+            };
+            let mut key = String::new();
+            file.read_to_string(&mut key)?;
+            MasterKey::from_base64(key.trim())
+        }
+        MasterKeySource::Env { variable } => match std::env::var(variable) {
+            Ok(key) => MasterKey::from_base64(key.trim()),
+            Err(_) => Err(EncryptionError::InvalidMasterKey(format!(
+                "environment variable {variable} is not set"
+            ))
+            .into()),
+        },
+        MasterKeySource::Passphrase => {
+            let passphrase = read_passphrase(PASSPHRASE_VARIABLE, "master passphrase")?;
+            MasterKey::from_passphrase(&passphrase, salt)
+        }
+    }
+}
+
+fn read_passphrase(variable: &str, description: &str) -> Result<String, AppError> {
+    if let Ok(passphrase) = std::env::var(variable) {
+        return Ok(passphrase);
+    }
+    // read from the terminal without echoing it
+    let passphrase = rpassword::prompt_password(format!("Enter the {description}: "))?;
+    if passphrase.is_empty() {
+        return Err(EncryptionError::InvalidMasterKey(format!(
+            "no passphrase given, set {variable} or enter it at startup"
+        ))
+        .into());
+    }
+    Ok(passphrase)
+}
+
+// a staged key left behind by an earlier attempt was never used for any data
+fn replace_key_file(path: &str, master_key: &MasterKey) -> Result<(), AppError> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+    write_key_file(path, master_key)
+}
+
+fn write_key_file(path: &str, master_key: &MasterKey) -> Result<(), AppError> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(BASE64_STANDARD.encode(master_key.key).as_bytes())?;
+    Ok(())
+}
+
+fn for_each_secret(
+    data: &mut WireGuardData,
+    mut apply: impl FnMut(&mut String, &str) -> Result<(), AppError>,
+) -> Result<(), AppError> {
+    if let Some(server) = &mut data.server {
+        apply(&mut server.private_key, "server private key")?;
+        if let Some(pending_key) = &mut server.pending_key {
+            apply(&mut pending_key.private_key, "pending server private key")?;
+        }
+    }
+    for client in &mut data.clients {
+        if let Some(private_key) = &mut client.private_key {
+            apply(
+                private_key,
+                &format!("private key of client '{}'", client.name),
+            )?;
+        }
+        if let Some(preshared_key) = &mut client.preshared_key {
+            apply(
+                preshared_key,
+                &format!("preshared key of client '{}'", client.name),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+impl MasterKey {
+    fn generate() -> Self {
+        MasterKey {
+            key: ChaCha20Poly1305::generate_key(&mut OsRng).into(),
+            salt: None,
+        }
+    }
+
+    fn from_base64(key: &str) -> Result<Self, AppError> {
+        let bytes = BASE64_STANDARD
+            .decode(key)
+            .map_err(|error| EncryptionError::InvalidMasterKey(error.to_string()))?;
+        let key: [u8; KEY_LENGTH] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            EncryptionError::InvalidMasterKey(format!(
+                "key must be {KEY_LENGTH} bytes, got {}",
+                bytes.len()
+            ))
+        })?;
+        Ok(MasterKey { key, salt: None })
+    }
+
+    fn from_passphrase(passphrase: &str, salt: Option<&String>) -> Result<Self, AppError> {
+        let salt = match salt {
+            Some(salt) => BASE64_STANDARD
+                .decode(salt)
+                .map_err(|error| EncryptionError::KeyDerivation(error.to_string()))?,
+            None => {
+                let mut salt = vec![0; SALT_LENGTH];
+                OsRng.fill_bytes(&mut salt);
+                salt
+            }
+        };
+        let mut key = [0; KEY_LENGTH];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|error| EncryptionError::KeyDerivation(error.to_string()))?;
+        Ok(MasterKey {
+            key,
+            salt: Some(BASE64_STANDARD.encode(salt)),
+        })
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+
+    fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut bytes = nonce.to_vec();
+        bytes.extend(
+            self.cipher()
+                .encrypt(&nonce, plaintext.as_bytes())
+                .expect("encrypting into a Vec cannot fail"),
+        );
+        format!("{ENCRYPTED_PREFIX}{}", BASE64_STANDARD.encode(bytes))
+    }
+
+    fn decrypt(&self, value: &str, name: &str) -> Result<String, AppError> {
+        let failed = || EncryptionError::DecryptionFailed(name.to_string());
+        let bytes = value
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|value| BASE64_STANDARD.decode(value).ok())
+            .filter(|bytes| bytes.len() > NONCE_LENGTH)
+            .ok_or_else(failed)?;
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        let plaintext = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| failed())?;
+        Ok(String::from_utf8(plaintext).map_err(|_| failed())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    // the installed master key is global, so tests that use it run one at a time
+    static INSTALLED_KEY: Mutex<()> = Mutex::new(());
+
+    fn lock_installed_key() -> MutexGuard<'static, ()> {
+        INSTALLED_KEY
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn key_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "wireguard-ui-secrets-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_config(path: &std::path::Path) -> AppConfig {
+        serde_json::from_value(serde_json::json!({
+            "master_key": { "source": "file", "path": path.display().to_string() },
+        }))
+        .unwrap()
+    }
+
+    fn data() -> WireGuardData {
+        WireGuardData {
+            server: Some(
+                serde_json::from_value(serde_json::json!({
+                    "endpoint": "vpn.example.com:51820",
+                    "address": ["10.8.0.1/24"],
+                    "dns": [],
+                    "listen_port": 51820,
+                    "private_key": "GOb2/TDoBE2zgJpyJaQSzvZGmIUiH7HZlXBUx+Xgo1c=",
+                    "public_key": "BqvHrNdoiKzcmUEVvH4xC6huaxY2wRnKfVdW+0Uw61c=",
+                }))
+                .unwrap(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn server_private_key(data: &WireGuardData) -> &str {
+        &data.server.as_ref().unwrap().private_key
+    }
+
+    #[test]
+    fn secrets_survive_encryption_for_storage() {
+        let _installed_key = lock_installed_key();
+        let config = file_config(&key_dir("round-trip").join("master_key"));
+        let mut data = data();
+        install(init(&config, &mut data).unwrap());
+
+        let mut stored = encrypt_for_storage(&data).unwrap();
+        assert!(server_private_key(&stored).starts_with(ENCRYPTED_PREFIX));
+        assert!(stored.encryption.is_some());
+
+        init(&config, &mut stored).unwrap();
+        assert_eq!(server_private_key(&stored), server_private_key(&data));
+        assert!(stored.encryption.is_none());
+    }
+
+    #[test]
+    fn wrong_master_key_is_detected() {
+        let _installed_key = lock_installed_key();
+        let dir = key_dir("wrong-key");
+        let mut data = data();
+        install(init(&file_config(&dir.join("master_key")), &mut data).unwrap());
+        let mut stored = encrypt_for_storage(&data).unwrap();
+
+        let error = init(&file_config(&dir.join("other_key")), &mut stored)
+            .err()
+            .expect("a different key is rejected");
+        assert!(matches!(
+            error,
+            AppError::Encryption(EncryptionError::WrongMasterKey)
+        ));
+    }
+
+    #[test]
+    fn passphrase_keys_reuse_the_stored_salt() {
+        let master_key = MasterKey::from_passphrase("correct horse", None).unwrap();
+        let derived_again =
+            MasterKey::from_passphrase("correct horse", master_key.salt.as_ref()).unwrap();
+        assert_eq!(derived_again.key, master_key.key);
+        assert_eq!(derived_again.salt, master_key.salt);
+        assert_eq!(
+            derived_again
+                .decrypt(&master_key.encrypt(KEY_CHECK), "key check")
+                .unwrap(),
+            KEY_CHECK
+        );
+
+        let fresh_salt = MasterKey::from_passphrase("correct horse", None).unwrap();
+        assert_ne!(fresh_salt.salt, master_key.salt);
+        assert_ne!(fresh_salt.key, master_key.key);
+    }
+
+    #[test]
+    fn rekeyed_key_file_replaces_the_old_one_once_finished() {
+        let _installed_key = lock_installed_key();
+        let path = key_dir("rekey").join("master_key");
+        let staged_path = path.with_file_name("master_key.new");
+        let config = file_config(&path);
+        let mut data = data();
+        install(init(&config, &mut data).unwrap());
+        let previous_key = std::fs::read_to_string(&path).unwrap();
+
+        let staged_key = rekey(&config).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), previous_key);
+        let new_key = std::fs::read_to_string(&staged_path).unwrap();
+        let mut stored = encrypt_for_storage(&data).unwrap();
+        finish_rekey(&staged_key).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), new_key);
+        assert_eq!(
+            std::fs::read_to_string(path.with_file_name("master_key.old")).unwrap(),
+            previous_key
+        );
+        init(&config, &mut stored).unwrap();
+        assert_eq!(server_private_key(&stored), server_private_key(&data));
+
+        let staged_key = rekey(&config).unwrap();
+        assert!(staged_path.exists());
+        discard_rekey(&staged_key);
+        assert!(!staged_path.exists());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), new_key);
+    }
+}
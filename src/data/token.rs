@@ -0,0 +1,18 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use sha2::{Digest, Sha256};
+
+const TOKEN_LENGTH: usize = 32;
+
+pub fn generate_token() -> String {
+    let mut token = [0; TOKEN_LENGTH];
+    OsRng.fill_bytes(&mut token);
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+// tokens are only ever stored hashed
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
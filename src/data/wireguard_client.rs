@@ -1,90 +1,161 @@
-use std::net::Ipv4Addr;
-use std::str::FromStr;
+use std::net::IpAddr;
+use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use wireguard_keys::{Privkey, Secret};
+use wireguard_keys::{Privkey, Pubkey, Secret};
 
+use crate::data::client_query::ClientQueryItem;
+use crate::data::config::AppConfig;
+use crate::data::time::{current_time_millis, MILLIS_PER_DAY};
+use crate::data::wireguard_data::WireGuardData;
 use crate::error::{AppError, RestAPIError};
-use crate::WireGuardAppValues;
+use crate::validation::server_network;
+
+// rendered into configs of clients that hold their own private key
+pub const PRIVATE_KEY_PLACEHOLDER: &str = "{PRIVATE_KEY}";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WireGuardClientData {
     pub name: String,
     #[serde(serialize_with = "uuid::serde::simple::serialize")]
     pub uuid: Uuid,
+    // starts at the revision the data is about to get, so a client that is recreated with the
+    // same uuid never hands out an ETag that an earlier one already used
+    #[serde(default)]
+    pub revision: u64,
     pub enabled: bool,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default = "Vec::new")]
+    pub tags: Vec<String>,
+    // set for device clients owned by a user
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
     // stored in server & client configs
     pub preshared_key: Option<String>,
     // stored in server config
     pub public_key: String,
     pub server_allowed_ips: Vec<String>,
     pub persistent_keep_alive: Option<u16>,
-    // stored in client config
-    pub private_key: String,
+    // stored in client config, missing if the client holds its own private key
+    #[serde(default)]
+    pub private_key: Option<String>,
     pub address: String,
     pub client_allowed_ips: Vec<String>,
     pub dns: Vec<String>,
+    #[serde(default)]
+    pub mtu: Option<u16>,
+    #[serde(default)]
+    pub keys_rotated_at: Option<u64>,
+    #[serde(default)]
+    pub preshared_key_rotated_at: Option<u64>,
+    // set when a rotation changed something the client has to pick up
+    #[serde(default)]
+    pub config_outdated: bool,
+    // set when an admin disabled the client, end users can not enable it again from the portal
+    #[serde(default)]
+    pub admin_disabled: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct WireGuardOptionalClientData {
     pub name: Option<String>,
     pub uuid: Option<Uuid>,
     pub enabled: Option<bool>,
+    pub group: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub expires_at: Option<u64>,
     pub generate_preshared_key: Option<bool>,
     pub preshared_key: Option<String>,
     pub server_allowed_ips: Option<Vec<String>>,
     pub persistent_keep_alive: Option<u16>,
     pub private_key: Option<String>,
+    // for clients that keep their private key to themselves, ignored if private_key is set
+    #[serde(default)]
+    pub public_key: Option<String>,
     pub address: Option<String>,
     pub client_allowed_ips: Option<Vec<String>>,
     pub dns: Option<Vec<String>>,
+    pub mtu: Option<u16>,
+    // when the given keys were made, only known for clients imported from other panels
+    #[serde(skip)]
+    pub keys_rotated_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RotateClientKeysRequest {
+    pub public_key: Option<String>,
 }
 
 impl WireGuardOptionalClientData {
     pub fn to_wireguard_client_data(
         &self,
         default_name: Option<String>,
-        app_values: &WireGuardAppValues,
+        config: &AppConfig,
+        data: &WireGuardData,
     ) -> Result<WireGuardClientData, AppError> {
-        let config = &app_values.config;
-        let data = &app_values.wireguard_data;
         let server = &data.server;
-        let private_key = self
-            .private_key
-            .to_owned()
-            .unwrap_or_else(|| Privkey::generate().to_base64());
-
-        let mut beginning_address = match server.clone() {
-            Some(server) => {
-                let mut addr = server.address[0].clone();
-                addr = addr
-                    .rsplit_once('/')
-                    .map(|(ip, _)| ip.to_string())
-                    .unwrap_or(addr);
-                match Ipv4Addr::from_str(addr.as_str()) {
-                    Ok(ip) => ip,
-                    Err(_) => {
-                        return Err(AppError::InvalidServerAddress(server.address[0].clone()));
+        let group = match &self.group {
+            Some(name) => match data.get_group(name) {
+                Some(group) => Some(group),
+                None => {
+                    return Err(AppError::RestAPI(RestAPIError::GroupNotFound(
+                        name.to_owned(),
+                    )))
+                }
+            },
+            None => None,
+        };
+        let (private_key, public_key) = match (&self.private_key, &self.public_key) {
+            (Some(private_key), _) => (
+                Some(private_key.to_owned()),
+                derive_public_key(private_key)?,
+            ),
+            (None, Some(public_key)) => (None, parse_public_key(public_key)?),
+            (None, None) => {
+                let private_key = Privkey::generate();
+                (
+                    Some(private_key.to_base64()),
+                    private_key.pubkey().to_base64(),
+                )
+            }
+        };
+
+        let address = match &self.address {
+            Some(address) => address.to_owned(),
+            None => {
+                let server_address = match server {
+                    Some(server) => server.address.first().cloned().unwrap_or_default(),
+                    None => config
+                        .get_wireguard_network_interface()
+                        .map(|i| format!("{}/{}", i.ipv4[0].addr, i.ipv4[0].prefix_len))?,
+                };
+                let (server_ip, prefix) = match server_network(&server_address) {
+                    Some((IpAddr::V4(ip), prefix)) => (ip, prefix),
+                    _ => return Err(AppError::InvalidServerAddress(server_address)),
+                };
+                match data.next_free_address(server_ip, prefix) {
+                    Some(ip) => format!("{ip}/32"),
+                    None => {
+                        return Err(AppError::RestAPI(RestAPIError::AddressPoolExhausted(
+                            format!("{server_ip}/{prefix}"),
+                        )))
                     }
                 }
             }
-            None => config
-                .clone()
-                .get_wireguard_network_interface()
-                .map(|i| i.ipv4[0].addr)?,
         };
-        // increment
-        let mut octets = beginning_address.octets();
-        if octets[3] < 255 {
-            octets[3] += 1;
-        } else {
-            octets[2] += 1;
-            octets[3] = 0;
-        }
-        beginning_address = Ipv4Addr::from(octets);
-        let ip = format!("{beginning_address}/32");
+
+        let preshared_key = self.preshared_key.to_owned().or_else(|| {
+            match self.generate_preshared_key.unwrap_or(true) {
+                true => Some(Secret::generate().to_base64()),
+                false => None,
+            }
+        });
+        let now = current_time_millis();
+        let enabled = self.enabled.unwrap_or(false);
 
         Ok(WireGuardClientData {
             name: match self.name.to_owned().or(default_name) {
@@ -96,40 +167,213 @@ impl WireGuardOptionalClientData {
                 }
             },
             uuid: self.uuid.unwrap_or_else(Uuid::new_v4),
-            enabled: self.enabled.unwrap_or(false),
-            preshared_key: self.preshared_key.to_owned().or_else(|| {
-                match self.generate_preshared_key.unwrap_or(true) {
-                    true => Some(Secret::generate().to_base64()),
-                    false => None,
-                }
+            revision: data.revision + 1,
+            enabled,
+            group: self.group.to_owned(),
+            tags: self.tags.to_owned().unwrap_or_default(),
+            user: None,
+            expires_at: self.expires_at.or_else(|| {
+                group
+                    .and_then(|group| group.expires_after_days)
+                    .map(|days| current_time_millis() + days as u64 * MILLIS_PER_DAY)
             }),
-            public_key: Privkey::parse(private_key.as_str())
-                .map_err(|_| {
-                    AppError::RestAPI(RestAPIError::InvalidPrivateKey(private_key.to_owned()))
-                })?
-                .pubkey()
-                .to_base64(),
+            public_key,
             server_allowed_ips: self
                 .server_allowed_ips
                 .to_owned()
-                .unwrap_or_else(|| vec![ip.clone()]),
-            persistent_keep_alive: self.persistent_keep_alive,
+                .unwrap_or_else(|| vec![address.clone()]),
+            persistent_keep_alive: self
+                .persistent_keep_alive
+                .or_else(|| group.and_then(|group| group.persistent_keep_alive)),
             private_key,
-            address: self.address.to_owned().unwrap_or(ip),
+            address,
             client_allowed_ips: self
                 .client_allowed_ips
                 .to_owned()
-                .unwrap_or_else(|| vec!["0.0.0.0".to_string()]),
-            dns: self.dns.to_owned().unwrap_or_default(),
+                .or_else(|| group.and_then(|group| group.client_allowed_ips.to_owned()))
+                .unwrap_or_else(|| vec!["0.0.0.0/0".to_string()]),
+            dns: self
+                .dns
+                .to_owned()
+                .or_else(|| group.and_then(|group| group.dns.to_owned()))
+                .unwrap_or_default(),
+            mtu: self.mtu.or_else(|| group.and_then(|group| group.mtu)),
+            keys_rotated_at: Some(self.keys_rotated_at.unwrap_or(now)),
+            preshared_key_rotated_at: match self.preshared_key {
+                Some(_) => Some(self.keys_rotated_at.unwrap_or(now)),
+                None => preshared_key.is_some().then_some(now),
+            },
+            preshared_key,
+            config_outdated: false,
+            admin_disabled: false,
         })
     }
+
+    pub fn merge_into(
+        &self,
+        existing: &WireGuardClientData,
+        data: &WireGuardData,
+    ) -> Result<WireGuardClientData, AppError> {
+        if let Some(uuid) = self.uuid {
+            if uuid != existing.uuid {
+                return Err(AppError::RestAPI(RestAPIError::UuidChanged(
+                    existing.uuid,
+                    uuid,
+                )));
+            }
+        }
+        if let Some(group) = &self.group {
+            if data.get_group(group).is_none() {
+                return Err(AppError::RestAPI(RestAPIError::GroupNotFound(
+                    group.to_owned(),
+                )));
+            }
+        }
+
+        let mut client = existing.clone();
+        if let Some(private_key) = &self.private_key {
+            client.public_key = derive_public_key(private_key)?;
+            client.private_key = Some(private_key.to_owned());
+        } else if let Some(public_key) = &self.public_key {
+            // switching to a key held by the client drops the stored private key
+            client.public_key = parse_public_key(public_key)?;
+            client.private_key = None;
+        }
+        if client.public_key != existing.public_key {
+            client.keys_rotated_at = Some(current_time_millis());
+        }
+        if let Some(address) = &self.address {
+            // keep the routed address in sync if it was only ever the client address
+            if self.server_allowed_ips.is_none()
+                && client.server_allowed_ips == vec![client.address.clone()]
+            {
+                client.server_allowed_ips = vec![address.to_owned()];
+            }
+            client.address.clone_from(address);
+        }
+        match (&self.preshared_key, self.generate_preshared_key) {
+            (Some(preshared_key), _) => client.preshared_key = Some(preshared_key.to_owned()),
+            (None, Some(true)) => client.preshared_key = Some(Secret::generate().to_base64()),
+            (None, Some(false)) => client.preshared_key = None,
+            (None, None) => {}
+        }
+        if client.preshared_key.is_some() && client.preshared_key != existing.preshared_key {
+            client.preshared_key_rotated_at = Some(current_time_millis());
+        }
+        if let Some(name) = &self.name {
+            client.name.clone_from(name);
+        }
+        if let Some(enabled) = self.enabled {
+            client.set_enabled_by_admin(enabled);
+        }
+        if let Some(group) = &self.group {
+            client.group = Some(group.to_owned());
+        }
+        if let Some(tags) = &self.tags {
+            client.tags.clone_from(tags);
+        }
+        if let Some(expires_at) = self.expires_at {
+            client.expires_at = Some(expires_at);
+        }
+        if let Some(server_allowed_ips) = &self.server_allowed_ips {
+            client.server_allowed_ips.clone_from(server_allowed_ips);
+        }
+        if let Some(persistent_keep_alive) = self.persistent_keep_alive {
+            client.persistent_keep_alive = Some(persistent_keep_alive);
+        }
+        if let Some(client_allowed_ips) = &self.client_allowed_ips {
+            client.client_allowed_ips.clone_from(client_allowed_ips);
+        }
+        if let Some(dns) = &self.dns {
+            client.dns.clone_from(dns);
+        }
+        if let Some(mtu) = self.mtu {
+            client.mtu = Some(mtu);
+        }
+        Ok(client)
+    }
 }
 
 impl WireGuardClientData {
+    pub fn query_item(&self, last_handshake: Option<SystemTime>) -> ClientQueryItem<'_> {
+        ClientQueryItem {
+            name: &self.name,
+            address: &self.address,
+            public_key: &self.public_key,
+            enabled: self.enabled,
+            group: self.group.as_ref(),
+            tags: &self.tags,
+            last_handshake,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= current_time_millis())
+    }
+
+    // clients holding their own private key have to hand in the new public key
+    pub fn rotate_keypair(&mut self, public_key: Option<&String>) -> Result<(), AppError> {
+        match (public_key, &self.private_key) {
+            (Some(public_key), _) => {
+                self.public_key = parse_public_key(public_key)?;
+                self.private_key = None;
+            }
+            (None, Some(_)) => {
+                let private_key = Privkey::generate();
+                self.public_key = private_key.pubkey().to_base64();
+                self.private_key = Some(private_key.to_base64());
+            }
+            (None, None) => {
+                return Err(AppError::RestAPI(RestAPIError::PublicKeyRequired(
+                    self.name.to_owned(),
+                )))
+            }
+        }
+        self.keys_rotated_at = Some(current_time_millis());
+        self.config_outdated = true;
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn set_enabled_by_admin(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.admin_disabled = !enabled;
+    }
+
+    // for clients replaced as a whole by an admin, the flag only changes along with enabled
+    pub fn keep_admin_disabled(&mut self, existing: Option<&WireGuardClientData>) {
+        self.admin_disabled = match existing {
+            Some(existing) if existing.enabled == self.enabled => existing.admin_disabled,
+            Some(_) => !self.enabled,
+            // a new client is not disabled by anyone yet, whatever enabled it starts with
+            None => false,
+        };
+    }
+
+    pub fn rotate_preshared_key(&mut self) {
+        self.preshared_key = Some(Secret::generate().to_base64());
+        self.preshared_key_rotated_at = Some(current_time_millis());
+        self.config_outdated = true;
+        self.revision += 1;
+    }
+
+    // clients without a rotation timestamp are never due, the scheduler stamps them first
+    pub fn is_preshared_key_rotation_due(&self, rotation_days: u32) -> bool {
+        self.preshared_key.is_some()
+            && self.preshared_key_rotated_at.is_some_and(|rotated_at| {
+                rotated_at + rotation_days as u64 * MILLIS_PER_DAY <= current_time_millis()
+            })
+    }
+
     pub fn get_server_peer_config(&self) -> String {
         let mut result = format!("# Name: {}", self.name);
         result += &format!("\n# UUID: {}", self.uuid);
-        let prefix = if self.enabled { "\n" } else { "\n# " };
+        let prefix = if self.enabled && !self.is_expired() {
+            "\n"
+        } else {
+            "\n# "
+        };
         result += &format!("{}[Peer]", prefix);
         result += &format!("{}PublicKey = {}", prefix, self.public_key);
         if let Some(preshared_key) = &self.preshared_key {
@@ -152,19 +396,165 @@ impl WireGuardClientData {
         server_endpoint: &String,
     ) -> String {
         let mut result = format!("# Name: {}", self.name);
-        result += "[Interface]";
-        result += &format!("PrivateKey = {}", self.private_key);
-        result += &format!("Address = {}", self.address);
+        result += "\n[Interface]";
+        result += &format!(
+            "\nPrivateKey = {}",
+            self.private_key
+                .as_deref()
+                .unwrap_or(PRIVATE_KEY_PLACEHOLDER)
+        );
+        result += &format!("\nAddress = {}", self.address);
         if !self.dns.is_empty() {
-            result += &format!("DNS = {}", self.dns.join(","));
+            result += &format!("\nDNS = {}", self.dns.join(","));
+        }
+        if let Some(mtu) = self.mtu {
+            result += &format!("\nMTU = {mtu}");
         }
-        result += "[Peer]";
-        result += &format!("PublicKey = {server_public_key}");
+        result += "\n\n[Peer]";
+        result += &format!("\nPublicKey = {server_public_key}");
         if let Some(preshared_key) = &self.preshared_key {
-            result += &format!("PresharedKey = {preshared_key}");
+            result += &format!("\nPresharedKey = {preshared_key}");
         }
-        result += &format!("AllowedIPs = {}", self.client_allowed_ips.join(","));
-        result += &format!("Endpoint = {server_endpoint}");
+        result += &format!("\nAllowedIPs = {}", self.client_allowed_ips.join(","));
+        result += &format!("\nEndpoint = {server_endpoint}");
+        if let Some(persistent_keep_alive) = self.persistent_keep_alive {
+            result += &format!("\nPersistentKeepalive = {persistent_keep_alive}");
+        }
+        result += "\n";
         result
     }
 }
+
+fn derive_public_key(private_key: &str) -> Result<String, AppError> {
+    Ok(Privkey::parse(private_key)
+        .map_err(|_| AppError::RestAPI(RestAPIError::InvalidPrivateKey(private_key.to_owned())))?
+        .pubkey()
+        .to_base64())
+}
+
+fn parse_public_key(public_key: &str) -> Result<String, AppError> {
+    Ok(Pubkey::parse(public_key)
+        .map_err(|_| AppError::RestAPI(RestAPIError::InvalidPublicKey(public_key.to_owned())))?
+        .to_base64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> WireGuardClientData {
+        serde_json::from_value(serde_json::json!({
+            "name": "phone",
+            "uuid": "1a5e566e96fd4649b5b97c614c502aec",
+            "enabled": true,
+            "preshared_key": "Zfqskp/k7QSqoD7x6QnfLGCBeZnnwLWpHUyyPxv1y6E=",
+            "public_key": "BqvHrNdoiKzcmUEVvH4xC6huaxY2wRnKfVdW+0Uw61c=",
+            "server_allowed_ips": ["10.8.0.2/32"],
+            "persistent_keep_alive": 25,
+            "private_key": "GOb2/TDoBE2zgJpyJaQSzvZGmIUiH7HZlXBUx+Xgo1c=",
+            "address": "10.8.0.2/32",
+            "client_allowed_ips": ["0.0.0.0/0", "::/0"],
+            "dns": ["1.1.1.1", "1.0.0.1"],
+            "mtu": 1420,
+        }))
+        .unwrap()
+    }
+
+    // section name and its keys in order, as wg-quick reads them
+    fn parse_config(config: &str) -> Vec<(String, Vec<(String, String)>)> {
+        let mut sections: Vec<(String, Vec<(String, String)>)> = Vec::new();
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(section) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                sections.push((section.to_string(), Vec::new()));
+                continue;
+            }
+            let (key, value) = line.split_once(" = ").expect("a key and a value");
+            sections
+                .last_mut()
+                .expect("keys inside a section")
+                .1
+                .push((key.to_string(), value.to_string()));
+        }
+        sections
+    }
+
+    #[test]
+    fn rendered_config_parses_into_interface_and_peer() {
+        let client = client();
+        let server_public_key = "2/lKMIzcDZij+J+aeFT1kMe8s8cxTRT48QTo3/mmXyU=".to_string();
+        let config =
+            client.get_client_config(&server_public_key, &"vpn.example.com:51820".to_string());
+        assert!(config.starts_with("# Name: phone\n[Interface]\n"));
+        assert!(config.contains("\n\n[Peer]\n"));
+
+        let sections = parse_config(&config);
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            sections,
+            vec![
+                (
+                    "Interface".to_string(),
+                    pairs(&[
+                        ("PrivateKey", "GOb2/TDoBE2zgJpyJaQSzvZGmIUiH7HZlXBUx+Xgo1c="),
+                        ("Address", "10.8.0.2/32"),
+                        ("DNS", "1.1.1.1,1.0.0.1"),
+                        ("MTU", "1420"),
+                    ])
+                ),
+                (
+                    "Peer".to_string(),
+                    pairs(&[
+                        ("PublicKey", "2/lKMIzcDZij+J+aeFT1kMe8s8cxTRT48QTo3/mmXyU="),
+                        (
+                            "PresharedKey",
+                            "Zfqskp/k7QSqoD7x6QnfLGCBeZnnwLWpHUyyPxv1y6E="
+                        ),
+                        ("AllowedIPs", "0.0.0.0/0,::/0"),
+                        ("Endpoint", "vpn.example.com:51820"),
+                        ("PersistentKeepalive", "25"),
+                    ])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rendered_config_leaves_out_unset_keys() {
+        let mut client = client();
+        client.private_key = None;
+        client.preshared_key = None;
+        client.persistent_keep_alive = None;
+        client.dns = Vec::new();
+        client.mtu = None;
+        let config = client.get_client_config(
+            &"2/lKMIzcDZij+J+aeFT1kMe8s8cxTRT48QTo3/mmXyU=".to_string(),
+            &"vpn.example.com:51820".to_string(),
+        );
+        let keys: Vec<String> = parse_config(&config)
+            .into_iter()
+            .flat_map(|(_, pairs)| pairs.into_iter().map(|(key, _)| key))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "PrivateKey",
+                "Address",
+                "PublicKey",
+                "AllowedIPs",
+                "Endpoint"
+            ]
+        );
+        assert!(config.contains(&format!("PrivateKey = {PRIVATE_KEY_PLACEHOLDER}\n")));
+    }
+}
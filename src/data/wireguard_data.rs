@@ -1,14 +1,41 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::data::access_policy::{AccessPolicy, AccessTarget};
 use crate::data::config::AppConfig;
+use crate::data::invitation::Invitation;
+use crate::data::portal::PortalToken;
+use crate::data::secrets::EncryptionMetadata;
+use crate::data::time::current_time_millis;
+use crate::data::token;
 use crate::data::wireguard_client::{WireGuardClientData, WireGuardOptionalClientData};
+use crate::data::wireguard_group::WireGuardGroupData;
+use crate::data::wireguard_peer::PeerUsage;
 use crate::data::wireguard_server::{WireGuardOptionalServerData, WireGuardServerData};
+use crate::data::wireguard_user::WireGuardUserData;
+use crate::validation::parse_network;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct WireGuardData {
+    #[serde(default)]
+    pub revision: u64,
     pub server: Option<WireGuardServerData>,
     #[serde(default = "Vec::new")]
     pub clients: Vec<WireGuardClientData>,
+    #[serde(default = "Vec::new")]
+    pub groups: Vec<WireGuardGroupData>,
+    #[serde(default = "Vec::new")]
+    pub users: Vec<WireGuardUserData>,
+    #[serde(default)]
+    pub access_policy: AccessPolicy,
+    #[serde(default = "Vec::new")]
+    pub invitations: Vec<Invitation>,
+    #[serde(default = "Vec::new")]
+    pub portal_tokens: Vec<PortalToken>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionMetadata>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,4 +70,205 @@ impl WireGuardData {
         }
         None
     }
+
+    pub fn increment_revision(&mut self) {
+        self.revision += 1;
+    }
+
+    pub fn next_free_address(&self, server_ip: Ipv4Addr, prefix: u8) -> Option<Ipv4Addr> {
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        let network = u32::from(server_ip) & mask;
+        let broadcast = network | !mask;
+        let used: HashSet<IpAddr> = self
+            .clients
+            .iter()
+            .filter_map(|client| parse_network(&client.address))
+            .map(|(ip, _)| ip)
+            .collect();
+        let server_ip = u32::from(server_ip);
+        // prefer addresses after the server's, then wrap around to the start of the subnet
+        (server_ip.checked_add(1).unwrap_or(broadcast)..broadcast)
+            .chain(network.checked_add(1).unwrap_or(server_ip)..server_ip)
+            .map(Ipv4Addr::from)
+            .find(|ip| !used.contains(&IpAddr::V4(*ip)))
+    }
+
+    // client configs carry the staged key from now on, so every client has to download its
+    // config again during the grace period, without one the key is activated right away
+    pub fn stage_server_key_rotation(&mut self, grace_period_hours: u32) -> bool {
+        let Some(server) = self.server.as_mut() else {
+            return false;
+        };
+        server.stage_key_rotation(grace_period_hours);
+        self.mark_client_configs_outdated();
+        if grace_period_hours == 0 {
+            self.activate_pending_server_key();
+        }
+        true
+    }
+
+    // clients that already downloaded the staged key need the current one again
+    pub fn cancel_server_key_rotation(&mut self) -> bool {
+        let pending_key = self
+            .server
+            .as_mut()
+            .and_then(|server| server.pending_key.take());
+        if pending_key.is_none() {
+            return false;
+        }
+        self.mark_client_configs_outdated();
+        true
+    }
+
+    // swaps in the staged server keypair, clients were flagged when it was staged
+    pub fn activate_pending_server_key(&mut self) -> bool {
+        let server = match self.server.as_mut() {
+            Some(server) => server,
+            None => return false,
+        };
+        let pending_key = match server.pending_key.take() {
+            Some(pending_key) => pending_key,
+            None => return false,
+        };
+        server.private_key = pending_key.private_key;
+        server.public_key = pending_key.public_key;
+        server.keys_rotated_at = Some(current_time_millis());
+        true
+    }
+
+    fn mark_client_configs_outdated(&mut self) {
+        for client in &mut self.clients {
+            client.config_outdated = true;
+            client.revision += 1;
+        }
+    }
+
+    // returns whether anything was rotated
+    pub fn run_scheduled_rotations(&mut self, config: &AppConfig) -> bool {
+        let mut changed = false;
+        if self
+            .server
+            .as_ref()
+            .is_some_and(|server| server.is_pending_key_due())
+        {
+            changed |= self.activate_pending_server_key();
+        }
+        if let Some(rotation_days) = config.preshared_key_rotation_days {
+            for client in &mut self.clients {
+                // clients from before rotation was enabled start their interval now,
+                // instead of all getting a new key on the first run
+                if client.preshared_key.is_some() && client.preshared_key_rotated_at.is_none() {
+                    client.preshared_key_rotated_at = Some(current_time_millis());
+                    changed = true;
+                } else if client.is_preshared_key_rotation_due(rotation_days) {
+                    client.rotate_preshared_key();
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    // disables the devices of users over their quota as an admin would, so that the portal
+    // cannot turn them back on, and returns the names of those users
+    pub fn enforce_transfer_quotas(&mut self, usage: &HashMap<String, PeerUsage>) -> Vec<String> {
+        let over_quota: Vec<String> = self
+            .users
+            .iter()
+            .filter(|user| user.is_over_quota(user.transferred_bytes(&self.clients, usage)))
+            .map(|user| user.name.to_owned())
+            .collect();
+        let revision = self.revision + 1;
+        let mut disabled_users = Vec::new();
+        for name in over_quota {
+            let mut disabled = false;
+            for device in self
+                .clients
+                .iter_mut()
+                .filter(|client| client.user.as_ref() == Some(&name) && client.enabled)
+            {
+                device.set_enabled_by_admin(false);
+                device.revision = revision;
+                disabled = true;
+            }
+            if disabled {
+                disabled_users.push(name);
+            }
+        }
+        disabled_users
+    }
+
+    // expired clients are only left out when the peers are applied, so the names of those the
+    // interface still has are returned for the scheduler to apply them
+    pub fn expired_peers(&self, usage: &HashMap<String, PeerUsage>) -> Vec<String> {
+        self.clients
+            .iter()
+            .filter(|client| client.is_expired() && usage.contains_key(&client.public_key))
+            .map(|client| client.name.to_owned())
+            .collect()
+    }
+
+    // users whose expiry passed while the interface still has some of their devices, the
+    // devices carry the expiry of their user and are removed along with the expired peers
+    pub fn expired_users(&self, usage: &HashMap<String, PeerUsage>) -> Vec<String> {
+        self.users
+            .iter()
+            .filter(|user| user.is_expired())
+            .filter(|user| {
+                self.clients.iter().any(|client| {
+                    client.user.as_ref() == Some(&user.name)
+                        && usage.contains_key(&client.public_key)
+                })
+            })
+            .map(|user| user.name.to_owned())
+            .collect()
+    }
+
+    pub fn find_invitation(&self, token: &str) -> Option<usize> {
+        let token_hash = token::hash_token(token);
+        self.invitations
+            .iter()
+            .position(|invitation| invitation.token_hash == token_hash)
+    }
+
+    pub fn find_portal_token(&self, token: &str) -> Option<&PortalToken> {
+        let token_hash = token::hash_token(token);
+        self.portal_tokens
+            .iter()
+            .find(|portal_token| portal_token.token_hash == token_hash)
+    }
+
+    pub fn get_group(&self, name: &String) -> Option<&WireGuardGroupData> {
+        self.groups.iter().find(|group| &group.name == name)
+    }
+
+    pub fn get_user(&self, name: &String) -> Option<&WireGuardUserData> {
+        self.users.iter().find(|user| &user.name == name)
+    }
+
+    pub fn get_user_devices(&self, name: &String) -> Vec<WireGuardClientData> {
+        self.clients
+            .iter()
+            .filter(|client| client.user.as_ref() == Some(name))
+            .cloned()
+            .collect()
+    }
+
+    pub fn resolve_access_target(&self, target: &AccessTarget) -> Vec<String> {
+        match target {
+            AccessTarget::Client(uuid) => self
+                .clients
+                .iter()
+                .filter(|client| &client.uuid == uuid)
+                .flat_map(|client| client.server_allowed_ips.clone())
+                .collect(),
+            AccessTarget::Group(group) => self
+                .clients
+                .iter()
+                .filter(|client| client.group.as_ref() == Some(group))
+                .flat_map(|client| client.server_allowed_ips.clone())
+                .collect(),
+            AccessTarget::Cidr(cidr) => vec![cidr.to_owned()],
+        }
+    }
 }
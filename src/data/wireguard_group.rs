@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WireGuardGroupData {
+    pub name: String,
+    // defaults for clients in this group that do not set the field themselves
+    pub dns: Option<Vec<String>>,
+    pub client_allowed_ips: Option<Vec<String>>,
+    pub persistent_keep_alive: Option<u16>,
+    pub mtu: Option<u16>,
+    pub expires_after_days: Option<u32>,
+}
@@ -5,11 +5,17 @@ use defguard_wireguard_rs::net::IpAddrMask;
 use serde::{Deserialize, Serialize, Serializer};
 use uuid::Uuid;
 
+use crate::data::client_query::ClientQueryItem;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WireGuardPeer {
     pub name: String,
     #[serde(serialize_with = "uuid::serde::simple::serialize")]
     pub uuid: Uuid,
+    pub public_key: String,
+    pub enabled: bool,
+    pub group: Option<String>,
+    pub tags: Vec<String>,
     #[serde(serialize_with = "serialize_ip_addr_mask_vec")]
     pub server_allowed_ips: Vec<IpAddrMask>,
     pub address: String,
@@ -22,6 +28,27 @@ pub struct WireGuardPeer {
     pub last_handshake: Option<SystemTime>,
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeerUsage {
+    pub transmitted_bytes: u64,
+    pub received_bytes: u64,
+    pub last_handshake: Option<SystemTime>,
+}
+
+impl WireGuardPeer {
+    pub fn query_item(&self) -> ClientQueryItem<'_> {
+        ClientQueryItem {
+            name: &self.name,
+            address: &self.address,
+            public_key: &self.public_key,
+            enabled: self.enabled,
+            group: self.group.as_ref(),
+            tags: &self.tags,
+            last_handshake: self.last_handshake,
+        }
+    }
+}
+
 fn serialize_ip_addr_mask_vec<S>(vec: &[IpAddrMask], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
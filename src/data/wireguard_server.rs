@@ -1,9 +1,13 @@
-use crate::data::config::AppConfig;
+use crate::data::config::{AppConfig, FirewallBackend};
+use crate::data::time::current_time_millis;
 use crate::error::{AppError, RestAPIError};
-use crate::WireGuardAppValues;
 use serde::{Deserialize, Serialize};
 use wireguard_keys::Privkey;
 
+// forwarding and NAT hooks every server got before the firewall module existed
+const DEFAULT_POST_UP: &str = "iptables -A FORWARD -i {WIREGUARD_INTERFACE} -j ACCEPT; iptables -t nat -A POSTROUTING -o {NETWORK_INTERFACE} -j MASQUERADE";
+const DEFAULT_POST_DOWN: &str = "iptables -D FORWARD -i {WIREGUARD_INTERFACE} -j ACCEPT; iptables -t nat -D POSTROUTING -o {NETWORK_INTERFACE} -j MASQUERADE";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WireGuardServerData {
     pub endpoint: String,
@@ -18,6 +22,25 @@ pub struct WireGuardServerData {
     pub post_down: Option<String>,
     pub table: Option<String>,
     pub mtu: Option<u16>,
+    #[serde(default)]
+    pub keys_rotated_at: Option<u64>,
+    #[serde(default)]
+    pub pending_key: Option<PendingServerKey>,
+}
+
+// a new keypair that replaces the current one once the grace period is over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingServerKey {
+    pub private_key: String,
+    pub public_key: String,
+    pub created_at: u64,
+    pub activate_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RotateServerKeysRequest {
+    #[serde(default)]
+    pub grace_period_hours: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,9 +62,10 @@ impl WireGuardOptionalServerData {
     pub fn to_wireguard_server_data(
         &self,
         default_endpoint: Option<String>,
-        app_values: &WireGuardAppValues,
+        config: &AppConfig,
     ) -> Result<WireGuardServerData, AppError> {
-        let config = &app_values.config;
+        // the firewall module programs forwarding and NAT itself unless it is disabled
+        let firewall_disabled = config.firewall_backend == FirewallBackend::Disabled;
 
         let private_key = self
             .private_key
@@ -65,16 +89,111 @@ impl WireGuardOptionalServerData {
                 .to_base64(),
             private_key: private_key.to_owned(),
             pre_up: self.pre_up.to_owned(),
-            post_up: self.post_up.to_owned().or(Some("iptables -A FORWARD -i {WIREGUARD_INTERFACE} -j ACCEPT; iptables -t nat -A POSTROUTING -o {NETWORK_INTERFACE} -j MASQUERADE".to_string())),
+            post_up: self.post_up.to_owned().or(firewall_disabled.then(|| DEFAULT_POST_UP.to_string())),
             pre_down: self.pre_down.to_owned(),
-            post_down: self.post_down.to_owned().or(Some("iptables -D FORWARD -i {WIREGUARD_INTERFACE} -j ACCEPT; iptables -t nat -D POSTROUTING -o {NETWORK_INTERFACE} -j MASQUERADE".to_string())),
+            post_down: self.post_down.to_owned().or(firewall_disabled.then(|| DEFAULT_POST_DOWN.to_string())),
             table: self.table.to_owned(),
             mtu: self.mtu,
+            keys_rotated_at: Some(current_time_millis()),
+            pending_key: None,
         })
     }
+
+    pub fn merge_into(
+        &self,
+        existing: &WireGuardServerData,
+    ) -> Result<WireGuardServerData, AppError> {
+        let mut server = existing.clone();
+        if let Some(private_key) = &self.private_key {
+            server.public_key = Privkey::parse(private_key.as_str())
+                .map_err(|_| {
+                    AppError::RestAPI(RestAPIError::InvalidPrivateKey(private_key.to_owned()))
+                })?
+                .pubkey()
+                .to_base64();
+            if &server.private_key != private_key {
+                server.keys_rotated_at = Some(current_time_millis());
+            }
+            server.private_key.clone_from(private_key);
+        }
+        if let Some(endpoint) = &self.endpoint {
+            server.endpoint.clone_from(endpoint);
+        }
+        if let Some(address) = &self.address {
+            server.address.clone_from(address);
+        }
+        if let Some(dns) = &self.dns {
+            server.dns.clone_from(dns);
+        }
+        if let Some(listen_port) = self.listen_port {
+            server.listen_port = listen_port;
+        }
+        if let Some(pre_up) = &self.pre_up {
+            server.pre_up = Some(pre_up.to_owned());
+        }
+        if let Some(post_up) = &self.post_up {
+            server.post_up = Some(post_up.to_owned());
+        }
+        if let Some(pre_down) = &self.pre_down {
+            server.pre_down = Some(pre_down.to_owned());
+        }
+        if let Some(post_down) = &self.post_down {
+            server.post_down = Some(post_down.to_owned());
+        }
+        if let Some(table) = &self.table {
+            server.table = Some(table.to_owned());
+        }
+        if let Some(mtu) = self.mtu {
+            server.mtu = Some(mtu);
+        }
+        Ok(server)
+    }
 }
 
 impl WireGuardServerData {
+    pub fn stage_key_rotation(&mut self, grace_period_hours: u32) -> &PendingServerKey {
+        let private_key = Privkey::generate();
+        let now = current_time_millis();
+        self.pending_key.insert(PendingServerKey {
+            private_key: private_key.to_base64(),
+            public_key: private_key.pubkey().to_base64(),
+            created_at: now,
+            activate_at: now + grace_period_hours as u64 * 60 * 60 * 1000,
+        })
+    }
+
+    // the key clients are given, the staged one during the grace period
+    pub fn client_public_key(&self) -> &String {
+        match &self.pending_key {
+            Some(pending_key) => &pending_key.public_key,
+            None => &self.public_key,
+        }
+    }
+
+    // servers created before the firewall module still carry its hooks, which would add a second
+    // set of forwarding and NAT rules next to the ones it programs and bypass client isolation
+    pub fn remove_default_firewall_hooks(&mut self, config: &AppConfig) -> bool {
+        if config.firewall_backend == FirewallBackend::Disabled {
+            return false;
+        }
+        let mut removed = false;
+        if self.post_up.as_deref() == Some(DEFAULT_POST_UP) {
+            self.post_up = None;
+            removed = true;
+        }
+        if self.post_down.as_deref() == Some(DEFAULT_POST_DOWN) {
+            self.post_down = None;
+            removed = true;
+        }
+        removed
+    }
+
+    pub fn is_pending_key_due(&self) -> bool {
+        self.pending_key
+            .as_ref()
+            .is_some_and(|pending_key| pending_key.activate_at <= current_time_millis())
+    }
+
     pub fn get_interface_config(&self, app_config: &AppConfig) -> String {
         let mut result = String::from("[Interface]");
         result += &format!("\nAddress = {}", self.address.join(","));
@@ -92,11 +211,14 @@ impl WireGuardServerData {
         }
 
         fn replace_interface_vars(str: &str, app_config: &AppConfig) -> String {
+            let network_interface = app_config
+                .get_network_interface_name()
+                .unwrap_or_else(|_| app_config.network_interface.to_owned());
             str.replace(
                 "{WIREGUARD_INTERFACE}",
                 app_config.wireguard_interface.as_str(),
             )
-            .replace("{NETWORK_INTERFACE}", app_config.network_interface.as_str())
+            .replace("{NETWORK_INTERFACE}", network_interface.as_str())
         }
         if let Some(pre_up) = &self.pre_up {
             second_part += &format!("\nPreUp = {}", replace_interface_vars(pre_up, app_config));
@@ -122,3 +244,50 @@ impl WireGuardServerData {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(config: &AppConfig) -> WireGuardServerData {
+        let server: WireGuardOptionalServerData = serde_json::from_value(serde_json::json!({
+            "endpoint": "vpn.example.com:51820",
+            "address": ["10.8.0.1/24"],
+        }))
+        .unwrap();
+        server.to_wireguard_server_data(None, config).unwrap()
+    }
+
+    fn config(firewall_backend: &str) -> AppConfig {
+        serde_json::from_value(serde_json::json!({ "firewall_backend": firewall_backend }))
+            .unwrap()
+    }
+
+    #[test]
+    fn default_firewall_hooks_of_older_servers_are_removed() {
+        let mut legacy = server(&config("disabled"));
+        assert_eq!(legacy.post_up.as_deref(), Some(DEFAULT_POST_UP));
+        assert_eq!(legacy.post_down.as_deref(), Some(DEFAULT_POST_DOWN));
+
+        // kept while the firewall module is off, they are the only NAT rules then
+        assert!(!legacy.remove_default_firewall_hooks(&config("disabled")));
+        assert!(legacy.post_up.is_some());
+
+        assert!(legacy.remove_default_firewall_hooks(&config("auto")));
+        assert_eq!(legacy.post_up, None);
+        assert_eq!(legacy.post_down, None);
+        assert!(!legacy.get_interface_config(&config("auto")).contains("iptables"));
+        assert!(!legacy.remove_default_firewall_hooks(&config("auto")));
+    }
+
+    #[test]
+    fn custom_firewall_hooks_are_kept() {
+        let mut custom = server(&config("nftables"));
+        assert_eq!(custom.post_up, None);
+        custom.post_up = Some(format!("{DEFAULT_POST_UP}; ip route add 10.9.0.0/24 dev eth1"));
+        custom.post_down = Some("ip route del 10.9.0.0/24 dev eth1".to_string());
+        assert!(!custom.remove_default_firewall_hooks(&config("nftables")));
+        assert!(custom.post_up.is_some());
+        assert!(custom.post_down.is_some());
+    }
+}
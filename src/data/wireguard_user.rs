@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::time::current_time_millis;
+use crate::data::wireguard_client::WireGuardClientData;
+use crate::data::wireguard_peer::PeerUsage;
+
+// a person owning one client per device, the devices share the expiry and quota of the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WireGuardUserData {
+    pub name: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    // copied onto every device so that expired devices are handled like any other client
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub max_devices: Option<u32>,
+    // bytes in both directions summed over all devices, the counters start over with the interface
+    #[serde(default)]
+    pub transfer_quota: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WireGuardUserDevices {
+    #[serde(flatten)]
+    pub user: WireGuardUserData,
+    pub devices: Vec<WireGuardClientData>,
+    pub transferred_bytes: u64,
+}
+
+impl WireGuardUserData {
+    pub fn apply_to_device(&self, device: &mut WireGuardClientData) {
+        if device.user.as_ref() != Some(&self.name) || device.expires_at != self.expires_at {
+            device.user = Some(self.name.to_owned());
+            device.expires_at = self.expires_at;
+            device.revision += 1;
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= current_time_millis())
+    }
+
+    pub fn has_room_for_device(&self, device_count: usize) -> bool {
+        self.max_devices
+            .is_none_or(|max_devices| device_count < max_devices as usize)
+    }
+
+    pub fn transferred_bytes(
+        &self,
+        devices: &[WireGuardClientData],
+        usage: &HashMap<String, PeerUsage>,
+    ) -> u64 {
+        devices
+            .iter()
+            .filter(|device| device.user.as_ref() == Some(&self.name))
+            .filter_map(|device| usage.get(&device.public_key))
+            .map(|usage| usage.transmitted_bytes + usage.received_bytes)
+            .sum()
+    }
+
+    pub fn is_over_quota(&self, transferred_bytes: u64) -> bool {
+        self.transfer_quota
+            .is_some_and(|transfer_quota| transferred_bytes >= transfer_quota)
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+use std::fs;
+
+use serde::Serialize;
+
+use crate::data::config::FirewallBackend;
+use crate::data::data_manager;
+use crate::firewall;
+use crate::WireGuardAppValues;
+
+#[derive(Debug, Serialize)]
+pub struct Liveness {
+    pub status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiagnosticsReport {
+    pub interface: InterfaceDiagnostics,
+    pub listen_port: ListenPortDiagnostics,
+    pub ip_forwarding: IpForwardingDiagnostics,
+    pub firewall: FirewallDiagnostics,
+    pub config_drift: ConfigDriftDiagnostics,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InterfaceDiagnostics {
+    pub name: String,
+    pub present: bool,
+    pub up: bool,
+    pub addresses: Vec<String>,
+    pub peers: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListenPortDiagnostics {
+    // from data.json and from the running interface, they differ until WireGuard is restarted
+    pub configured: Option<u16>,
+    pub active: Option<u16>,
+    pub bound: bool,
+}
+
+// None if the sysctl could not be read
+#[derive(Debug, Serialize)]
+pub struct IpForwardingDiagnostics {
+    pub ipv4: Option<bool>,
+    pub ipv6: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FirewallDiagnostics {
+    pub configured_backend: FirewallBackend,
+    pub active_backend: Option<FirewallBackend>,
+    pub rules_present: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigDriftDiagnostics {
+    pub drifted: bool,
+    // whether the wg-quick config on disk is what the current data would render
+    pub config_file_matches: Option<bool>,
+    // enabled clients that are not on the interface, by name
+    pub missing_peers: Vec<String>,
+    // peers on the interface that no enabled client has, by public key
+    pub unknown_peers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// ready once the data is usable, the tunnel exists and changes can be saved
+pub fn check_readiness(app_values: &WireGuardAppValues) -> ReadinessReport {
+    let checks = vec![
+        readiness_check(
+            "data",
+            match app_values.wireguard_data.server {
+                Some(_) => Ok(()),
+                None => Err("No server has been created yet".to_string()),
+            },
+        ),
+        readiness_check(
+            "interface",
+            match app_values.backend.is_up() {
+                true => Ok(()),
+                false => Err(format!(
+                    "WireGuard interface '{}' is not up",
+                    app_values.config.wireguard_interface
+                )),
+            },
+        ),
+        readiness_check("storage", data_manager::check_storage_writable()),
+    ];
+    ReadinessReport {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+fn readiness_check(name: &'static str, result: Result<(), String>) -> ReadinessCheck {
+    ReadinessCheck {
+        name,
+        ok: result.is_ok(),
+        message: result.err(),
+    }
+}
+
+pub fn diagnose(app_values: &WireGuardAppValues) -> DiagnosticsReport {
+    let config = &app_values.config;
+    let data = &app_values.wireguard_data;
+    let network_interface = config.get_wireguard_network_interface();
+    let host = app_values.backend.read_interface_data();
+
+    // a simulated interface has no network device, it is up whenever the backend says so
+    let present = app_values.backend.is_up();
+    let interface = InterfaceDiagnostics {
+        name: config.wireguard_interface.to_owned(),
+        present,
+        up: network_interface
+            .as_ref()
+            .map_or(present, |interface| interface.is_up()),
+        addresses: network_interface
+            .as_ref()
+            .map(|interface| {
+                let ipv4 = interface.ipv4.iter().map(ToString::to_string);
+                let ipv6 = interface.ipv6.iter().map(ToString::to_string);
+                ipv4.chain(ipv6).collect()
+            })
+            .unwrap_or_default(),
+        peers: host.as_ref().ok().map(|host| host.peers.len()),
+        error: match present {
+            true => None,
+            false => network_interface.as_ref().err().map(ToString::to_string),
+        },
+    };
+
+    let active_port = host.as_ref().ok().map(|host| host.listen_port);
+    let listen_port = ListenPortDiagnostics {
+        configured: data.server.as_ref().map(|server| server.listen_port),
+        active: active_port,
+        bound: active_port.is_some_and(udp_port_bound),
+    };
+
+    let ip_forwarding = IpForwardingDiagnostics {
+        ipv4: read_sysctl_flag("net.ipv4.ip_forward"),
+        ipv6: read_sysctl_flag("net.ipv6.conf.all.forwarding"),
+    };
+
+    let firewall = match firewall::inspect_firewall(config) {
+        Ok(status) => FirewallDiagnostics {
+            configured_backend: config.firewall_backend,
+            active_backend: status.map(|(backend, _)| backend),
+            rules_present: status.map(|(_, present)| present),
+            error: None,
+        },
+        Err(error) => FirewallDiagnostics {
+            configured_backend: config.firewall_backend,
+            active_backend: None,
+            rules_present: None,
+            error: Some(error.to_string()),
+        },
+    };
+
+    let config_file_matches = data
+        .get_server_config(config)
+        .filter(|_| config.manages_host())
+        .map(|expected| {
+            fs::read_to_string(&config.wireguard_config_path)
+                .is_ok_and(|on_disk| on_disk == expected)
+        });
+    let config_drift = match &host {
+        Ok(host) => {
+            let expected: Vec<_> = data
+                .clients
+                .iter()
+                .filter(|client| client.enabled && !client.is_expired())
+                .collect();
+            let expected_keys: HashSet<&String> =
+                expected.iter().map(|client| &client.public_key).collect();
+            let active_keys: HashSet<String> =
+                host.peers.keys().map(|key| key.to_string()).collect();
+            let missing_peers: Vec<String> = expected
+                .iter()
+                .filter(|client| !active_keys.contains(&client.public_key))
+                .map(|client| client.name.to_owned())
+                .collect();
+            let mut unknown_peers: Vec<String> = active_keys
+                .iter()
+                .filter(|key| !expected_keys.contains(key))
+                .cloned()
+                .collect();
+            unknown_peers.sort();
+            ConfigDriftDiagnostics {
+                drifted: config_file_matches == Some(false)
+                    || !missing_peers.is_empty()
+                    || !unknown_peers.is_empty(),
+                config_file_matches,
+                missing_peers,
+                unknown_peers,
+                error: None,
+            }
+        }
+        Err(error) => ConfigDriftDiagnostics {
+            drifted: config_file_matches == Some(false),
+            config_file_matches,
+            missing_peers: Vec::new(),
+            unknown_peers: Vec::new(),
+            error: Some(format!("Could not read the interface: {error}")),
+        },
+    };
+
+    DiagnosticsReport {
+        interface,
+        listen_port,
+        ip_forwarding,
+        firewall,
+        config_drift,
+    }
+}
+
+pub fn read_sysctl_flag(name: &str) -> Option<bool> {
+    fs::read_to_string(sysctl_path(name))
+        .ok()
+        .map(|value| value.trim() == "1")
+}
+
+pub fn sysctl_path(name: &str) -> String {
+    format!("/proc/sys/{}", name.replace('.', "/"))
+}
+
+// kernel sockets such as the one of WireGuard show up in /proc/net/udp without an owning process
+pub fn udp_port_bound(port: u16) -> bool {
+    let local_port = format!(":{port:04X}");
+    ["/proc/net/udp", "/proc/net/udp6"].iter().any(|path| {
+        fs::read_to_string(path).is_ok_and(|table| {
+            table.lines().skip(1).any(|line| {
+                line.split_whitespace()
+                    .nth(1)
+                    .is_some_and(|local_address| local_address.ends_with(&local_port))
+            })
+        })
+    })
+}
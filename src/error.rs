@@ -1,6 +1,9 @@
 use defguard_wireguard_rs::error::WireguardInterfaceError;
 use std::io;
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::validation::FieldError;
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -26,6 +29,28 @@ pub enum AppError {
     CouldNotGetDefaultInterface(String),
     #[error("Invalid server address: {0}")]
     InvalidServerAddress(String),
+    #[error("Invalid listen address: {0}")]
+    InvalidListenAddress(String),
+    #[error("Could not change state: {0}")]
+    StateWriter(String),
+    #[error("No client with the name or uuid '{0}'")]
+    ClientNotFound(String),
+    #[error("No server has been created yet")]
+    ServerNotFound,
+    #[error("This must be run as root, only the memory backend works without it")]
+    NotRoot,
+    #[error("{0} is held by a running server, make the change through its API instead")]
+    DataDirLocked(String),
+    #[error("Preflight checks failed")]
+    PreflightFailed,
+    #[error("Exporting secrets is disabled, set allow_secret_export to allow it")]
+    SecretExportDisabled,
+    #[error("Firewall error: {0}")]
+    Firewall(#[from] FirewallError),
+    #[error("Encryption error: {0}")]
+    Encryption(#[from] EncryptionError),
+    #[error("Could not render QR code: {0}")]
+    QrCode(#[from] qrcode::types::QrError),
 }
 
 #[derive(Error, Debug)]
@@ -43,4 +68,38 @@ pub enum RestAPIError {
     FieldMissing(String),
     #[error("Invalid base64 private key: '{0}'")]
     InvalidPrivateKey(String),
+    #[error("Invalid base64 public key: '{0}'")]
+    InvalidPublicKey(String),
+    #[error("Client '{0}' holds its own private key, a new public key is required")]
+    PublicKeyRequired(String),
+    #[error("Group '{0}' not found")]
+    GroupNotFound(String),
+    #[error("UUID cannot be changed from {0} to {1}")]
+    UuidChanged(Uuid, Uuid),
+    #[error("No free addresses left in {0}")]
+    AddressPoolExhausted(String),
+    #[error("Validation failed for {} field(s)", .0.len())]
+    ValidationFailed(Vec<FieldError>),
+}
+
+#[derive(Error, Debug)]
+pub enum FirewallError {
+    #[error("No supported firewall backend found (tried nft and iptables)")]
+    NoBackendAvailable,
+    #[error("Command '{command}' failed: {message}")]
+    CommandFailed { command: String, message: String },
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptionError {
+    #[error("data.json contains encrypted secrets but no master key is configured")]
+    MissingMasterKey,
+    #[error("Invalid master key: {0}")]
+    InvalidMasterKey(String),
+    #[error("The master key does not match the one data.json was encrypted with")]
+    WrongMasterKey,
+    #[error("Could not decrypt {0}")]
+    DecryptionFailed(String),
+    #[error("Could not derive the master key from the passphrase: {0}")]
+    KeyDerivation(String),
 }
@@ -0,0 +1,733 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use tracing::{debug, warn};
+
+use crate::data::access_policy::{IsolationMode, Protocol};
+use crate::data::config::{AppConfig, FirewallBackend};
+use crate::data::wireguard_data::WireGuardData;
+use crate::error::{AppError, FirewallError};
+use crate::validation::IpNetwork;
+
+const NFTABLES_TABLE: &str = "wireguard_ui";
+const IPTABLES_FORWARD_CHAIN: &str = "WIREGUARD_UI_FORWARD";
+const IPTABLES_POSTROUTING_CHAIN: &str = "WIREGUARD_UI_POSTROUTING";
+
+#[derive(Debug, Clone)]
+pub struct FirewallRuleset {
+    pub forward_rules: Vec<ForwardRule>,
+    pub masquerade_interface: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ForwardRule {
+    pub in_interface: Option<String>,
+    pub out_interface: Option<String>,
+    pub sources: Vec<IpNetwork>,
+    pub destinations: Vec<IpNetwork>,
+    pub protocol: Option<Protocol>,
+    pub ports: Vec<u16>,
+    pub established: bool,
+    pub action: RuleAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RuleAction {
+    #[default]
+    Accept,
+    Drop,
+}
+
+impl ForwardRule {
+    // both backends need a single address family per rule and an explicit
+    // protocol for port matches, so mixed rules are split up before rendering
+    fn expand(self) -> Vec<ForwardRule> {
+        let protocols = match (self.protocol, self.ports.is_empty()) {
+            (None, false) => vec![Some(Protocol::Tcp), Some(Protocol::Udp)],
+            (protocol, _) => vec![protocol],
+        };
+        let families: Vec<Option<bool>> = if self.sources.is_empty() && self.destinations.is_empty()
+        {
+            vec![None]
+        } else {
+            vec![Some(false), Some(true)]
+        };
+
+        let mut result = Vec::new();
+        for is_ipv6 in families {
+            let filter = |addresses: &Vec<IpNetwork>| -> Option<Vec<IpNetwork>> {
+                let filtered: Vec<IpNetwork> = addresses
+                    .iter()
+                    .filter(|address| is_ipv6.is_none_or(|is_ipv6| address.is_ipv6() == is_ipv6))
+                    .cloned()
+                    .collect();
+                // an empty list means "any", so a list that only had other families must not match
+                (addresses.is_empty() || !filtered.is_empty()).then_some(filtered)
+            };
+            let (Some(sources), Some(destinations)) =
+                (filter(&self.sources), filter(&self.destinations))
+            else {
+                continue;
+            };
+            for protocol in &protocols {
+                result.push(ForwardRule {
+                    sources: sources.clone(),
+                    destinations: destinations.clone(),
+                    protocol: *protocol,
+                    ..self.clone()
+                });
+            }
+        }
+        result
+    }
+
+    fn is_ipv6(&self) -> bool {
+        self.sources
+            .iter()
+            .chain(&self.destinations)
+            .any(IpNetwork::is_ipv6)
+    }
+
+    // rules without addresses apply to both address families
+    fn matches_family(&self, ipv6: bool) -> bool {
+        self.is_ipv6() == ipv6 || (self.sources.is_empty() && self.destinations.is_empty())
+    }
+}
+
+// anything that does not parse is left out, so only canonical addresses reach the rules
+fn parse_addresses(addresses: &[String]) -> Vec<IpNetwork> {
+    addresses
+        .iter()
+        .filter_map(|address| IpNetwork::parse(address))
+        .collect()
+}
+
+fn join_addresses(addresses: &[IpNetwork], separator: &str) -> String {
+    addresses
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+pub fn build_ruleset(
+    config: &AppConfig,
+    data: &WireGuardData,
+) -> Result<FirewallRuleset, AppError> {
+    let wireguard_interface = &config.wireguard_interface;
+    let network_interface = config.get_network_interface_name()?;
+    let policy = &data.access_policy;
+
+    let mut forward_rules = vec![ForwardRule {
+        out_interface: Some(wireguard_interface.to_owned()),
+        established: true,
+        ..Default::default()
+    }];
+
+    for rule in &policy.rules {
+        let sources = parse_addresses(&data.resolve_access_target(&rule.source));
+        let destinations = parse_addresses(&data.resolve_access_target(&rule.destination));
+        if sources.is_empty() || destinations.is_empty() {
+            continue;
+        }
+        forward_rules.push(ForwardRule {
+            in_interface: Some(wireguard_interface.to_owned()),
+            sources,
+            destinations,
+            protocol: rule.protocol,
+            ports: rule.ports.clone(),
+            ..Default::default()
+        });
+    }
+
+    if !policy.is_open() {
+        let mut open_addresses = Vec::new();
+        let mut group_addresses = BTreeMap::<&String, Vec<IpNetwork>>::new();
+        for client in &data.clients {
+            match policy.isolation_for(client.group.as_ref()) {
+                IsolationMode::AllowAll => {
+                    open_addresses.extend(parse_addresses(&client.server_allowed_ips));
+                }
+                IsolationMode::AllowWithinGroup => {
+                    if let Some(group) = &client.group {
+                        group_addresses
+                            .entry(group)
+                            .or_default()
+                            .extend(parse_addresses(&client.server_allowed_ips));
+                    }
+                }
+                IsolationMode::IsolateAll => {}
+            }
+        }
+
+        for addresses in [open_addresses]
+            .into_iter()
+            .chain(group_addresses.into_values())
+        {
+            if addresses.is_empty() {
+                continue;
+            }
+            forward_rules.push(ForwardRule {
+                in_interface: Some(wireguard_interface.to_owned()),
+                out_interface: Some(wireguard_interface.to_owned()),
+                sources: addresses.clone(),
+                destinations: addresses,
+                ..Default::default()
+            });
+        }
+        forward_rules.push(ForwardRule {
+            in_interface: Some(wireguard_interface.to_owned()),
+            out_interface: Some(wireguard_interface.to_owned()),
+            action: RuleAction::Drop,
+            ..Default::default()
+        });
+    }
+
+    // access rules only open up traffic between peers, everything leaving the tunnel is forwarded
+    forward_rules.push(ForwardRule {
+        in_interface: Some(wireguard_interface.to_owned()),
+        ..Default::default()
+    });
+
+    Ok(FirewallRuleset {
+        forward_rules: forward_rules
+            .into_iter()
+            .flat_map(ForwardRule::expand)
+            .collect(),
+        masquerade_interface: network_interface,
+    })
+}
+
+pub fn apply_firewall(config: &AppConfig, data: &WireGuardData) -> Result<(), AppError> {
+    let backend = match resolve_backend(config)? {
+        Some(backend) => backend,
+        None => return Ok(()),
+    };
+    let ruleset = build_ruleset(config, data)?;
+    match backend {
+        FirewallBackend::Nftables => apply_nftables(&ruleset)?,
+        _ => apply_iptables(&ruleset)?,
+    }
+    Ok(())
+}
+
+// only touches the rules while the interface is up, they are applied again on start anyway
+pub fn refresh_firewall(config: &AppConfig, data: &WireGuardData) -> Result<(), AppError> {
+    if config.get_wireguard_network_interface().is_err() {
+        return Ok(());
+    }
+    apply_firewall(config, data)
+}
+
+pub fn remove_firewall(config: &AppConfig) -> Result<(), AppError> {
+    match resolve_backend(config)? {
+        Some(FirewallBackend::Nftables) => remove_nftables()?,
+        Some(_) => remove_iptables(),
+        None => {}
+    }
+    Ok(())
+}
+
+// the backend in use and whether our rules are hooked into it, None when the firewall is disabled
+pub fn inspect_firewall(config: &AppConfig) -> Result<Option<(FirewallBackend, bool)>, AppError> {
+    let backend = match resolve_backend(config)? {
+        Some(backend) => backend,
+        None => return Ok(None),
+    };
+    let present = match backend {
+        FirewallBackend::Nftables => {
+            run_command("nft", &["list", "table", "inet", NFTABLES_TABLE]).is_ok()
+        }
+        _ => {
+            run_iptables(
+                "iptables",
+                None,
+                &["-C", "FORWARD", "-j", IPTABLES_FORWARD_CHAIN],
+            )
+            .is_ok()
+                && run_iptables(
+                    "iptables",
+                    Some("nat"),
+                    &["-C", "POSTROUTING", "-j", IPTABLES_POSTROUTING_CHAIN],
+                )
+                .is_ok()
+        }
+    };
+    Ok(Some((backend, present)))
+}
+
+fn resolve_backend(config: &AppConfig) -> Result<Option<FirewallBackend>, FirewallError> {
+    if !config.manages_host() {
+        return Ok(None);
+    }
+    match config.firewall_backend {
+        FirewallBackend::Disabled => Ok(None),
+        FirewallBackend::Auto => {
+            if command_available("nft") {
+                Ok(Some(FirewallBackend::Nftables))
+            } else if command_available("iptables") {
+                Ok(Some(FirewallBackend::Iptables))
+            } else {
+                Err(FirewallError::NoBackendAvailable)
+            }
+        }
+        backend => Ok(Some(backend)),
+    }
+}
+
+fn command_available(command: &str) -> bool {
+    Command::new(command)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+fn render_nftables(ruleset: &FirewallRuleset) -> String {
+    let mut result = format!("add table inet {NFTABLES_TABLE}\n");
+    result += &format!("delete table inet {NFTABLES_TABLE}\n");
+    result += &format!("table inet {NFTABLES_TABLE} {{\n");
+    result += "    chain forward {\n";
+    result += "        type filter hook forward priority filter; policy accept;\n";
+    for rule in &ruleset.forward_rules {
+        let mut line = String::new();
+        if let Some(in_interface) = &rule.in_interface {
+            line += &format!("iifname \"{in_interface}\" ");
+        }
+        if let Some(out_interface) = &rule.out_interface {
+            line += &format!("oifname \"{out_interface}\" ");
+        }
+        let address_family = if rule.is_ipv6() { "ip6" } else { "ip" };
+        if !rule.sources.is_empty() {
+            line += &format!(
+                "{address_family} saddr {{ {} }} ",
+                join_addresses(&rule.sources, ", ")
+            );
+        }
+        if !rule.destinations.is_empty() {
+            line += &format!(
+                "{address_family} daddr {{ {} }} ",
+                join_addresses(&rule.destinations, ", ")
+            );
+        }
+        if let Some(protocol) = rule.protocol {
+            let protocol = protocol_name(protocol);
+            if rule.ports.is_empty() {
+                line += &format!("meta l4proto {protocol} ");
+            } else {
+                let ports: Vec<String> = rule.ports.iter().map(ToString::to_string).collect();
+                line += &format!("{protocol} dport {{ {} }} ", ports.join(", "));
+            }
+        }
+        if rule.established {
+            line += "ct state related,established ";
+        }
+        line += match rule.action {
+            RuleAction::Accept => "accept",
+            RuleAction::Drop => "drop",
+        };
+        result += &format!("        {line}\n");
+    }
+    result += "    }\n";
+    result += "    chain postrouting {\n";
+    result += "        type nat hook postrouting priority srcnat; policy accept;\n";
+    result += &format!(
+        "        oifname \"{}\" masquerade\n",
+        ruleset.masquerade_interface
+    );
+    result += "    }\n";
+    result += "}\n";
+    result
+}
+
+fn apply_nftables(ruleset: &FirewallRuleset) -> Result<(), FirewallError> {
+    run_command_with_input("nft", &["-f", "-"], &render_nftables(ruleset))
+}
+
+fn remove_nftables() -> Result<(), FirewallError> {
+    // adding first makes the deletion succeed even if the table was never created
+    run_command("nft", &["add", "table", "inet", NFTABLES_TABLE])?;
+    run_command("nft", &["delete", "table", "inet", NFTABLES_TABLE])
+}
+
+fn iptables_rule_args(rule: &ForwardRule) -> Vec<String> {
+    let mut args = vec!["-A".to_string(), IPTABLES_FORWARD_CHAIN.to_string()];
+    if let Some(in_interface) = &rule.in_interface {
+        args.extend(["-i".to_string(), in_interface.to_owned()]);
+    }
+    if let Some(out_interface) = &rule.out_interface {
+        args.extend(["-o".to_string(), out_interface.to_owned()]);
+    }
+    if !rule.sources.is_empty() {
+        args.extend(["-s".to_string(), join_addresses(&rule.sources, ",")]);
+    }
+    if !rule.destinations.is_empty() {
+        args.extend(["-d".to_string(), join_addresses(&rule.destinations, ",")]);
+    }
+    if let Some(protocol) = rule.protocol {
+        args.extend(["-p".to_string(), protocol_name(protocol).to_string()]);
+        if !rule.ports.is_empty() {
+            let ports: Vec<String> = rule.ports.iter().map(ToString::to_string).collect();
+            args.extend(["-m", "multiport", "--dports"].map(ToString::to_string));
+            args.push(ports.join(","));
+        }
+    }
+    if rule.established {
+        args.extend(
+            ["-m", "conntrack", "--ctstate", "RELATED,ESTABLISHED"].map(ToString::to_string),
+        );
+    }
+    args.extend([
+        "-j".to_string(),
+        match rule.action {
+            RuleAction::Accept => "ACCEPT",
+            RuleAction::Drop => "DROP",
+        }
+        .to_string(),
+    ]);
+    args
+}
+
+fn protocol_name(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "tcp",
+        Protocol::Udp => "udp",
+    }
+}
+
+// declaring a chain in a restore file flushes it, and every table is committed as a whole, so a
+// failure leaves the previous rules in place instead of a half filled chain
+fn render_iptables(ruleset: &FirewallRuleset, ipv6: bool) -> String {
+    let mut result = "*filter\n".to_string();
+    result += &format!(":{IPTABLES_FORWARD_CHAIN} - [0:0]\n");
+    for rule in ruleset
+        .forward_rules
+        .iter()
+        .filter(|rule| rule.matches_family(ipv6))
+    {
+        result += &format!("{}\n", iptables_rule_args(rule).join(" "));
+    }
+    result += "COMMIT\n";
+    result += "*nat\n";
+    result += &format!(":{IPTABLES_POSTROUTING_CHAIN} - [0:0]\n");
+    result += &format!(
+        "-A {IPTABLES_POSTROUTING_CHAIN} -o {} -j MASQUERADE\n",
+        ruleset.masquerade_interface
+    );
+    result += "COMMIT\n";
+    result
+}
+
+fn apply_iptables(ruleset: &FirewallRuleset) -> Result<(), FirewallError> {
+    apply_iptables_family(ruleset, "iptables", false)?;
+    apply_ip6tables(ruleset, command_available("ip6tables"), |ruleset| {
+        apply_iptables_family(ruleset, "ip6tables", true)
+    })
+}
+
+// hosts without IPv6 often lack ip6tables or its nat table, which only matters once a
+// client actually has an IPv6 address
+fn apply_ip6tables(
+    ruleset: &FirewallRuleset,
+    available: bool,
+    apply: impl FnOnce(&FirewallRuleset) -> Result<(), FirewallError>,
+) -> Result<(), FirewallError> {
+    let uses_ipv6 = ruleset.forward_rules.iter().any(ForwardRule::is_ipv6);
+    if !available {
+        if uses_ipv6 {
+            warn!("ip6tables is not installed, skipping the IPv6 rules");
+        }
+        return Ok(());
+    }
+    match apply(ruleset) {
+        Err(error) if !uses_ipv6 => {
+            warn!("Skipping the IPv6 rules, no client uses IPv6: {error}");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn apply_iptables_family(
+    ruleset: &FirewallRuleset,
+    program: &str,
+    ipv6: bool,
+) -> Result<(), FirewallError> {
+    // the chains are complete before the built-in chains jump to them
+    run_command_with_input(
+        &format!("{program}-restore"),
+        &["--noflush"],
+        &render_iptables(ruleset, ipv6),
+    )?;
+    ensure_iptables_jump(program, None, IPTABLES_FORWARD_CHAIN, "FORWARD")?;
+    ensure_iptables_jump(
+        program,
+        Some("nat"),
+        IPTABLES_POSTROUTING_CHAIN,
+        "POSTROUTING",
+    )
+}
+
+fn ensure_iptables_jump(
+    program: &str,
+    table: Option<&str>,
+    chain: &str,
+    parent_chain: &str,
+) -> Result<(), FirewallError> {
+    if run_iptables(program, table, &["-C", parent_chain, "-j", chain]).is_err() {
+        run_iptables(program, table, &["-I", parent_chain, "1", "-j", chain])?;
+    }
+    Ok(())
+}
+
+fn remove_iptables() {
+    for program in ["iptables", "ip6tables"] {
+        for (table, chain, parent_chain) in [
+            (None, IPTABLES_FORWARD_CHAIN, "FORWARD"),
+            (Some("nat"), IPTABLES_POSTROUTING_CHAIN, "POSTROUTING"),
+        ] {
+            // the chain may not exist, so failures here are expected
+            let _ = run_iptables(program, table, &["-D", parent_chain, "-j", chain]);
+            let _ = run_iptables(program, table, &["-F", chain]);
+            let _ = run_iptables(program, table, &["-X", chain]);
+        }
+    }
+}
+
+fn run_iptables(program: &str, table: Option<&str>, args: &[&str]) -> Result<(), FirewallError> {
+    match table {
+        Some(table) => run_command(program, &[&["-t", table], args].concat()),
+        None => run_command(program, args),
+    }
+}
+
+fn run_command(program: &str, args: &[&str]) -> Result<(), FirewallError> {
+    let command = format!("{program} {}", args.join(" "));
+    debug!("Running {command}");
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|error| command_failed(&command, error.to_string()))?;
+    if !output.status.success() {
+        return Err(command_failed(
+            &command,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn run_command_with_input(program: &str, args: &[&str], input: &str) -> Result<(), FirewallError> {
+    let command = format!("{program} {}", args.join(" "));
+    debug!("Running {command}");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| command_failed(&command, error.to_string()))?;
+    if let Some(stdin) = child.stdin.as_mut() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|error| command_failed(&command, error.to_string()))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|error| command_failed(&command, error.to_string()))?;
+    if !output.status.success() {
+        return Err(command_failed(
+            &command,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn command_failed(command: &str, message: String) -> FirewallError {
+    FirewallError::CommandFailed {
+        command: command.to_string(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "1a5e566e96fd4649b5b97c614c502aec";
+
+    fn client(
+        name: &str,
+        uuid: &str,
+        group: Option<&str>,
+        addresses: &[&str],
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "uuid": uuid,
+            "enabled": true,
+            "group": group,
+            "public_key": "BqvHrNdoiKzcmUEVvH4xC6huaxY2wRnKfVdW+0Uw61c=",
+            "server_allowed_ips": addresses,
+            "address": addresses[0],
+            "client_allowed_ips": ["0.0.0.0/0"],
+            "dns": [],
+        })
+    }
+
+    // alice and bob share a group that may talk within itself, carol is isolated but may reach
+    // ssh on alice, and only alice has an IPv6 address
+    fn ruleset() -> FirewallRuleset {
+        let config: AppConfig =
+            serde_yaml::from_str("wireguard_interface: wg0\nnetwork_interface: eth0").unwrap();
+        let data: WireGuardData = serde_json::from_value(serde_json::json!({
+            "clients": [
+                client("alice", ALICE, Some("staff"), &["10.8.0.2/32", "fd00::2/128"]),
+                client("bob", "6c4e7d1ec0a34c2f9d7f3f5b8e2a9d10", Some("staff"), &["10.8.0.3/32"]),
+                client("carol", "0f1e2d3c4b5a69788796a5b4c3d2e1f0", None, &["10.8.0.4/32"]),
+            ],
+            "access_policy": {
+                "isolation": "isolate_all",
+                "group_isolation": { "staff": "allow_within_group" },
+                "rules": [{
+                    "source": { "cidr": "10.8.0.4/32" },
+                    "destination": { "client": ALICE },
+                    "protocol": "tcp",
+                    "ports": [22],
+                }],
+            },
+        }))
+        .unwrap();
+        build_ruleset(&config, &data).unwrap()
+    }
+
+    // interfaces, addresses and verdict of a rule, with "*" for anything
+    fn describe(rule: &ForwardRule) -> String {
+        let or_any = |value: String| {
+            if value.is_empty() {
+                "*".to_string()
+            } else {
+                value
+            }
+        };
+        format!(
+            "{} -> {} {} -> {}{} {:?}",
+            rule.in_interface.clone().unwrap_or("*".into()),
+            rule.out_interface.clone().unwrap_or("*".into()),
+            or_any(join_addresses(&rule.sources, ",")),
+            or_any(join_addresses(&rule.destinations, ",")),
+            if rule.established { " established" } else { "" },
+            rule.action,
+        )
+    }
+
+    #[test]
+    fn rules_are_ordered_from_established_to_forward() {
+        let rules: Vec<String> = ruleset().forward_rules.iter().map(describe).collect();
+        assert_eq!(
+            rules,
+            [
+                "* -> wg0 * -> * established Accept",
+                "wg0 -> * 10.8.0.4/32 -> 10.8.0.2/32 Accept",
+                "wg0 -> wg0 10.8.0.2/32,10.8.0.3/32 -> 10.8.0.2/32,10.8.0.3/32 Accept",
+                "wg0 -> wg0 fd00::2/128 -> fd00::2/128 Accept",
+                "wg0 -> wg0 * -> * Drop",
+                "wg0 -> * * -> * Accept",
+            ]
+        );
+    }
+
+    #[test]
+    fn rules_are_split_by_address_family() {
+        let ruleset = ruleset();
+        let ipv6: Vec<bool> = ruleset
+            .forward_rules
+            .iter()
+            .map(ForwardRule::is_ipv6)
+            .collect();
+        assert_eq!(ipv6, [false, false, false, true, false, false]);
+
+        let ipv4_rules = render_iptables(&ruleset, false);
+        let ipv6_rules = render_iptables(&ruleset, true);
+        assert!(!ipv4_rules.contains("fd00::2"));
+        assert!(ipv6_rules.contains("fd00::2/128"));
+        assert!(!ipv6_rules.contains("10.8.0."));
+        // the rules without addresses end up in both families
+        for rules in [&ipv4_rules, &ipv6_rules] {
+            assert!(rules.contains("-A WIREGUARD_UI_FORWARD -i wg0 -o wg0 -j DROP\n"));
+        }
+    }
+
+    #[test]
+    fn ipv6_rules_are_skipped_only_while_no_client_uses_ipv6() {
+        let failed = || {
+            Err(command_failed(
+                "ip6tables-restore --noflush",
+                "no nat".into(),
+            ))
+        };
+        let ipv6_ruleset = ruleset();
+        let mut ipv4_ruleset = ruleset();
+        ipv4_ruleset.forward_rules.retain(|rule| !rule.is_ipv6());
+
+        let mut applied = false;
+        assert!(apply_ip6tables(&ipv6_ruleset, false, |_| {
+            applied = true;
+            Ok(())
+        })
+        .is_ok());
+        assert!(!applied);
+        assert!(apply_ip6tables(&ipv4_ruleset, true, |_| failed()).is_ok());
+        assert!(apply_ip6tables(&ipv6_ruleset, true, |_| failed()).is_err());
+        assert!(apply_ip6tables(&ipv6_ruleset, true, |_| Ok(())).is_ok());
+    }
+
+    #[test]
+    fn nftables_rules_render_in_order() {
+        assert_eq!(
+            render_nftables(&ruleset()),
+            "add table inet wireguard_ui
+delete table inet wireguard_ui
+table inet wireguard_ui {
+    chain forward {
+        type filter hook forward priority filter; policy accept;
+        oifname \"wg0\" ct state related,established accept
+        iifname \"wg0\" ip saddr { 10.8.0.4/32 } ip daddr { 10.8.0.2/32 } tcp dport { 22 } accept
+        iifname \"wg0\" oifname \"wg0\" ip saddr { 10.8.0.2/32, 10.8.0.3/32 } ip daddr { 10.8.0.2/32, 10.8.0.3/32 } accept
+        iifname \"wg0\" oifname \"wg0\" ip6 saddr { fd00::2/128 } ip6 daddr { fd00::2/128 } accept
+        iifname \"wg0\" oifname \"wg0\" drop
+        iifname \"wg0\" accept
+    }
+    chain postrouting {
+        type nat hook postrouting priority srcnat; policy accept;
+        oifname \"eth0\" masquerade
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn iptables_rules_render_in_order() {
+        assert_eq!(
+            render_iptables(&ruleset(), false),
+            "*filter
+:WIREGUARD_UI_FORWARD - [0:0]
+-A WIREGUARD_UI_FORWARD -o wg0 -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A WIREGUARD_UI_FORWARD -i wg0 -s 10.8.0.4/32 -d 10.8.0.2/32 -p tcp -m multiport --dports 22 -j ACCEPT
+-A WIREGUARD_UI_FORWARD -i wg0 -o wg0 -s 10.8.0.2/32,10.8.0.3/32 -d 10.8.0.2/32,10.8.0.3/32 -j ACCEPT
+-A WIREGUARD_UI_FORWARD -i wg0 -o wg0 -j DROP
+-A WIREGUARD_UI_FORWARD -i wg0 -j ACCEPT
+COMMIT
+*nat
+:WIREGUARD_UI_POSTROUTING - [0:0]
+-A WIREGUARD_UI_POSTROUTING -o eth0 -j MASQUERADE
+COMMIT
+"
+        );
+    }
+}
@@ -0,0 +1,27 @@
+#![cfg(target_os = "linux")]
+use std::sync::Arc;
+
+use crate::backend::WireGuardBackend;
+use crate::data::config::AppConfig;
+use crate::data::wireguard_data::WireGuardData;
+
+pub mod backend;
+pub mod cli;
+pub mod data;
+pub mod diagnostics;
+pub mod error;
+pub mod firewall;
+pub mod logging;
+pub mod preflight;
+pub mod scheduler;
+pub mod server;
+pub mod state;
+pub mod validation;
+pub mod wireguard;
+
+#[derive(Clone)]
+pub struct WireGuardAppValues {
+    pub backend: Arc<dyn WireGuardBackend>,
+    pub config: AppConfig,
+    pub wireguard_data: WireGuardData,
+}
@@ -1,54 +1,180 @@
 #![cfg(target_os = "linux")]
 use std::error::Error;
-use std::sync::{Arc, Mutex};
-use std::thread;
 
-use defguard_wireguard_rs::WGApi;
+use clap::Parser;
 use nix::unistd::Uid;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
 
-use crate::data::config::AppConfig;
-use crate::data::wireguard_data::WireGuardData;
-
-mod data;
-mod error;
-mod server;
-mod wireguard;
+use wireguard_ui_backend::cli::{Cli, Command};
+use wireguard_ui_backend::data::config::WireGuardBackendKind;
+use wireguard_ui_backend::error::AppError;
+use wireguard_ui_backend::state::AppState;
+use wireguard_ui_backend::{
+    backend, cli, data, firewall, logging, preflight, scheduler, server, WireGuardAppValues,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    if !Uid::effective().is_root() {
-        panic!("This must be run as root!");
+    let cli = Cli::parse();
+    data::data_manager::set_paths(cli.paths());
+    data::data_manager::set_config_overrides(cli.overrides.clone());
+    logging::init(cli.log_format, &cli.log_level);
+    match cli.command.unwrap_or_default() {
+        Command::Serve => {
+            if let Err(error) = serve().await {
+                error!("{error}");
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        command => {
+            if let Err(error) = cli::run(command) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+            Ok(())
+        }
     }
+}
 
-    println!("Reading config file");
-    let config = data::data_manager::read_config_file()?;
-    data::data_manager::save_config_file(&config)?;
+async fn serve() -> Result<(), Box<dyn Error>> {
+    info!("Reading config file");
+    let mut config = data::data_manager::read_config_file()?;
+    if config.wireguard_backend != WireGuardBackendKind::Memory && !Uid::effective().is_root() {
+        return Err(AppError::NotRoot.into());
+    }
+    let _data_lock = data::data_manager::lock_data_dir()?;
+    if config.admin_token.is_none() {
+        config.admin_token = Some(data::data_manager::read_or_create_admin_token()?);
+    }
+    if !config.require_admin_token {
+        warn!("require_admin_token is disabled, the admin API accepts requests without a token");
+    }
 
-    println!("Reading data file");
-    let data = data::data_manager::read_json_file()?;
+    info!("Reading data file");
+    let mut data = data::data_manager::read_json_file()?;
+    data::secrets::install(data::secrets::init(&config, &mut data)?);
+    data::data_manager::upgrade_data(&config, &mut data);
+    // also encrypts plaintext secrets if a master key was configured
     data::data_manager::save_json_file(&data)?;
 
-    println!("Preparing WireGuard");
-    let wg_api = WGApi::new(config.wireguard_interface.to_owned(), false)?;
+    if config.fix_sysctls {
+        info!("Enabling IP forwarding");
+        if let Err(error) = preflight::fix_sysctls(&data) {
+            error!("Could not enable IP forwarding: {error}");
+        }
+    }
+    info!("Preparing WireGuard");
+    let backend = backend::create_backend(&config);
+    // runs before giving up on a backend that could not be created, the checks tell why
+    info!("Running preflight checks");
+    preflight::log_report(&preflight::run_preflight(
+        &config,
+        &data,
+        backend.as_deref().ok(),
+    ));
+    let backend = backend?;
+
+    // replaces whatever rules an earlier run left behind
+    if backend.is_up() {
+        info!("Applying firewall rules");
+        firewall::apply_firewall(&config, &data)?;
+    }
 
-    let app_values = Arc::new(Mutex::new(WireGuardAppValues {
-        wg_api,
+    let state = AppState::new(WireGuardAppValues {
+        backend,
         config,
         wireguard_data: data,
-    }));
-
-    println!("Starting server");
-    server::start_server(app_values.clone()).await;
+    })?;
 
-    // add something else later?
+    let (shutdown, shutdown_receiver) = watch::channel(false);
+    info!("Starting server");
+    let server = server::start_server(state.clone(), shutdown_receiver.clone()).await?;
+    scheduler::start_scheduler(state.clone(), shutdown_receiver);
 
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut hangup = signal(SignalKind::hangup())?;
     loop {
-        thread::park();
+        tokio::select! {
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+            _ = hangup.recv() => {
+                info!("Reloading config and data files");
+                if let Err(error) = state.write(reload).await.and_then(|result| result) {
+                    error!("Could not reload, keeping the previous state: {error}");
+                }
+            }
+        }
     }
+
+    info!("Shutting down");
+    shutdown.send_replace(true);
+    if let Err(error) = server.await? {
+        error!("Server stopped with an error: {error}");
+    }
+
+    state.write(stop).await??;
+    Ok(())
 }
 
-pub struct WireGuardAppValues {
-    pub wg_api: WGApi,
-    pub config: AppConfig,
-    pub wireguard_data: WireGuardData,
+// runs on the state writer after every other queued change has been persisted
+fn stop(app_values: &mut WireGuardAppValues) -> Result<(), AppError> {
+    info!("Saving data file");
+    data::data_manager::save_json_file(&app_values.wireguard_data)?;
+    // a tunnel that outlives the service keeps its rules, they are replaced on the next start
+    if app_values.config.stop_interface_on_shutdown {
+        info!("Stopping WireGuard");
+        app_values.backend.stop()?;
+        info!("Removing firewall rules");
+        firewall::remove_firewall(&app_values.config)?;
+    }
+    Ok(())
+}
+
+// replaces the in-memory state with what is on disk, nothing changes if either file is invalid
+// or the new state can not be applied
+fn reload(app_values: &mut WireGuardAppValues) -> Result<(), AppError> {
+    let mut config = data::data_manager::read_config_file()?;
+    let mut data = data::data_manager::read_json_file()?;
+    // the running state keeps saving with its key until the reloaded one is applied
+    let master_key = data::secrets::init(&config, &mut data)?;
+    data::data_manager::upgrade_data(&config, &mut data);
+
+    if config.admin_token.is_none() {
+        config.admin_token = app_values.config.admin_token.clone();
+    }
+    let backend = if config.wireguard_interface != app_values.config.wireguard_interface
+        || config.wireguard_backend != app_values.config.wireguard_backend
+    {
+        backend::create_backend(&config)?
+    } else {
+        app_values.backend.clone()
+    };
+    let reloaded = WireGuardAppValues {
+        backend,
+        config,
+        wireguard_data: data,
+    };
+    if let Err(error) = apply_state(&reloaded) {
+        // puts the firewall rules and peers of the state that is kept back in place
+        if let Err(error) = apply_state(app_values) {
+            error!("Could not restore the previous state: {error}");
+        }
+        return Err(error);
+    }
+    *app_values = reloaded;
+    data::secrets::install(master_key);
+    Ok(())
+}
+
+fn apply_state(app_values: &WireGuardAppValues) -> Result<(), AppError> {
+    firewall::refresh_firewall(&app_values.config, &app_values.wireguard_data)?;
+    if app_values.backend.is_up() {
+        data::data_manager::save_wireguard_config(&app_values.wireguard_data, &app_values.config)?;
+        app_values.backend.reload(&app_values.wireguard_data)?;
+    }
+    Ok(())
 }
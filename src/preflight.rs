@@ -0,0 +1,292 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde::Serialize;
+use tracing::{debug, error, warn};
+
+use crate::backend::WireGuardBackend;
+use crate::data::config::{AppConfig, FirewallBackend, WireGuardBackendKind};
+use crate::data::wireguard_data::WireGuardData;
+use crate::diagnostics;
+use crate::error::AppError;
+use crate::wireguard;
+
+const SYSCTL_CONFIG_PATH: &str = "/etc/sysctl.d/99-wireguard-ui.conf";
+const IPV4_FORWARDING: &str = "net.ipv4.ip_forward";
+const IPV6_FORWARDING: &str = "net.ipv6.conf.all.forwarding";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreflightCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+    // how to resolve a failed check, if there is a known way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreflightReport {
+    // false if any check failed, warnings do not count
+    pub passed: bool,
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightCheck {
+    fn new(name: impl Into<String>, status: CheckStatus, message: impl Into<String>) -> Self {
+        PreflightCheck {
+            name: name.into(),
+            status,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn with_fix(mut self, fix: impl Into<String>) -> Self {
+        if self.status != CheckStatus::Pass {
+            self.fix = Some(fix.into());
+        }
+        self
+    }
+}
+
+pub fn run_preflight(
+    config: &AppConfig,
+    data: &WireGuardData,
+    backend: Option<&dyn WireGuardBackend>,
+) -> PreflightReport {
+    let mut checks = Vec::new();
+    match config.wireguard_backend {
+        WireGuardBackendKind::Kernel => checks.push(check_kernel_module()),
+        WireGuardBackendKind::Userspace => {
+            checks.push(check_kernel_module_unused());
+            checks.push(check_tun_device());
+        }
+        WireGuardBackendKind::Memory => {}
+    }
+    checks.push(check_forwarding(IPV4_FORWARDING, CheckStatus::Fail));
+    checks.push(check_forwarding(
+        IPV6_FORWARDING,
+        if uses_ipv6(data) {
+            CheckStatus::Fail
+        } else {
+            CheckStatus::Warn
+        },
+    ));
+    for binary in required_binaries(config, data) {
+        checks.push(check_binary(binary));
+    }
+    checks.push(check_listen_port(config, data, backend));
+    PreflightReport {
+        passed: checks.iter().all(|check| check.status != CheckStatus::Fail),
+        checks,
+    }
+}
+
+pub fn log_report(report: &PreflightReport) {
+    for check in &report.checks {
+        match check.status {
+            CheckStatus::Pass => debug!(check = %check.name, "{}", check.message),
+            CheckStatus::Warn => warn!(check = %check.name, "{}", check.message),
+            CheckStatus::Fail => error!(check = %check.name, "{}", check.message),
+        }
+    }
+}
+
+// enables forwarding right away and keeps it enabled across reboots once that worked,
+// IPv6 forwarding is left alone unless the tunnel carries IPv6
+pub fn fix_sysctls(data: &WireGuardData) -> Result<(), AppError> {
+    let mut names = vec![IPV4_FORWARDING];
+    if uses_ipv6(data) {
+        names.push(IPV6_FORWARDING);
+    }
+    for name in &names {
+        fs::write(diagnostics::sysctl_path(name), "1")?;
+    }
+    let mut content = "# Written by WireGuard UI\n".to_string();
+    for name in &names {
+        content += &format!("{name} = 1\n");
+    }
+    fs::write(SYSCTL_CONFIG_PATH, content)?;
+    Ok(())
+}
+
+// IPv6 forwarding only matters once the tunnel carries IPv6
+fn uses_ipv6(data: &WireGuardData) -> bool {
+    data.server
+        .as_ref()
+        .is_some_and(|server| server.address.iter().any(|address| address.contains(':')))
+}
+
+fn check_kernel_module() -> PreflightCheck {
+    let name = "kernel_module";
+    if Path::new("/sys/module/wireguard").exists() {
+        return PreflightCheck::new(name, CheckStatus::Pass, "The wireguard module is loaded");
+    }
+    if kernel_module_installed() {
+        PreflightCheck::new(
+            name,
+            CheckStatus::Pass,
+            "The wireguard module is available but not loaded yet",
+        )
+    } else {
+        PreflightCheck::new(
+            name,
+            CheckStatus::Fail,
+            "The wireguard module is not available",
+        )
+        .with_fix("Install the WireGuard kernel module or use a kernel that includes it")
+    }
+}
+
+// a dry run finds modules that are installed but not loaded yet, `ip link add` loads them itself
+fn kernel_module_installed() -> bool {
+    Command::new("modprobe")
+        .args(["--dry-run", "wireguard"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// the userspace implementation is started even where the module could be loaded, which only
+// gives up the faster kernel implementation
+fn check_kernel_module_unused() -> PreflightCheck {
+    let name = "kernel_module";
+    if Path::new("/sys/module/wireguard").exists() || kernel_module_installed() {
+        PreflightCheck::new(
+            name,
+            CheckStatus::Warn,
+            "The wireguard module is available, the userspace implementation is slower",
+        )
+        .with_fix("Switch to the kernel backend")
+    } else {
+        PreflightCheck::new(
+            name,
+            CheckStatus::Pass,
+            "The wireguard module is not available, the userspace implementation is used",
+        )
+    }
+}
+
+fn check_tun_device() -> PreflightCheck {
+    let name = "tun_device";
+    if Path::new("/dev/net/tun").exists() {
+        PreflightCheck::new(name, CheckStatus::Pass, "/dev/net/tun is available")
+    } else {
+        PreflightCheck::new(name, CheckStatus::Fail, "/dev/net/tun does not exist")
+            .with_fix("Pass /dev/net/tun into the container and grant it CAP_NET_ADMIN")
+    }
+}
+
+fn check_forwarding(name: &str, status_if_disabled: CheckStatus) -> PreflightCheck {
+    let check_name = format!("sysctl:{name}");
+    match diagnostics::read_sysctl_flag(name) {
+        Some(true) => PreflightCheck::new(check_name, CheckStatus::Pass, format!("{name} is 1")),
+        Some(false) => PreflightCheck::new(
+            check_name,
+            status_if_disabled,
+            format!("{name} is 0, traffic from clients is not forwarded"),
+        )
+        .with_fix(format!(
+            "Enable fix_sysctls in the config or run preflight --fix to write {SYSCTL_CONFIG_PATH}"
+        )),
+        None => PreflightCheck::new(
+            check_name,
+            status_if_disabled,
+            format!("{name} could not be read"),
+        ),
+    }
+}
+
+fn required_binaries(config: &AppConfig, data: &WireGuardData) -> Vec<&'static str> {
+    // the simulated backend needs none of the WireGuard tools
+    let mut binaries = match config.wireguard_backend {
+        WireGuardBackendKind::Kernel => vec!["wg-quick", "wg"],
+        // the library checks for wireguard-go even if another implementation is selected
+        WireGuardBackendKind::Userspace => vec!["wg-quick", "wg", "ip", "wireguard-go"],
+        WireGuardBackendKind::Memory => Vec::new(),
+    };
+    if !config.manages_host() {
+        return binaries;
+    }
+    match config.firewall_backend {
+        FirewallBackend::Nftables => binaries.push("nft"),
+        FirewallBackend::Iptables => {
+            binaries.extend(["iptables", "iptables-restore"]);
+            // the IPv6 rules are skipped with a warning while no client uses IPv6
+            if uses_ipv6(data) {
+                binaries.extend(["ip6tables", "ip6tables-restore"]);
+            }
+        }
+        // auto only needs one of them, which apply_firewall reports on its own
+        FirewallBackend::Auto | FirewallBackend::Disabled => {}
+    }
+    binaries
+}
+
+fn check_binary(binary: &str) -> PreflightCheck {
+    let name = format!("binary:{binary}");
+    match find_in_path(binary) {
+        Some(path) => PreflightCheck::new(name, CheckStatus::Pass, format!("Found {path}")),
+        None => PreflightCheck::new(name, CheckStatus::Fail, format!("{binary} is not in PATH"))
+            .with_fix(format!("Install the package that provides {binary}")),
+    }
+}
+
+fn check_listen_port(
+    config: &AppConfig,
+    data: &WireGuardData,
+    backend: Option<&dyn WireGuardBackend>,
+) -> PreflightCheck {
+    let name = "listen_port";
+    let port = match &data.server {
+        Some(server) => server.listen_port,
+        None => {
+            return PreflightCheck::new(name, CheckStatus::Pass, "No server has been created yet")
+        }
+    };
+    if !diagnostics::udp_port_bound(port) {
+        return PreflightCheck::new(name, CheckStatus::Pass, format!("UDP port {port} is free"));
+    }
+    // the port is expected to be taken if our own interface is already up
+    if backend.is_some_and(|backend| {
+        backend.is_up()
+            && wireguard::get_listen_port(backend).is_ok_and(|active_port| active_port == port)
+    }) {
+        return PreflightCheck::new(
+            name,
+            CheckStatus::Pass,
+            format!("UDP port {port} is used by {}", config.wireguard_interface),
+        );
+    }
+    PreflightCheck::new(
+        name,
+        CheckStatus::Fail,
+        format!("UDP port {port} is already used by another socket"),
+    )
+    .with_fix("Stop the other service or change listen_port of the server")
+}
+
+fn find_in_path(binary: &str) -> Option<String> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|directory| directory.join(binary))
+        .find(|candidate| {
+            fs::metadata(candidate).is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+        .map(|candidate| candidate.display().to_string())
+}
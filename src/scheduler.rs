@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::data::{audit, data_manager};
+use crate::state::AppState;
+use crate::{firewall, server, wireguard, WireGuardAppValues};
+
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+// activates staged server keys, rotates preshared keys once they are due, cuts off users
+// that went over their transfer quota and removes the peers of clients that expired
+pub fn start_scheduler(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.wait_for(|stop| *stop) => break,
+            }
+            run_scheduled_tasks(&state).await;
+        }
+    });
+}
+
+// one pass of the scheduler, the loop above runs it every minute
+// like a failed request, changes that cannot be applied are rolled back along with what they
+// already wrote out, and are tried again on the next pass
+pub async fn run_scheduled_tasks(state: &AppState) {
+    let result = state
+        .write(|app_values| {
+            let previous = app_values.clone();
+            let config = app_values.config.clone();
+            let rotated = app_values.wireguard_data.run_scheduled_rotations(&config);
+            // usage is simply missing while the interface is down
+            let usage = wireguard::get_peer_usage(app_values.backend.as_ref()).unwrap_or_default();
+            let over_quota = app_values.wireguard_data.enforce_transfer_quotas(&usage);
+            let expired = app_values.wireguard_data.expired_peers(&usage);
+            let expired_users = app_values.wireguard_data.expired_users(&usage);
+            let changed = rotated || !over_quota.is_empty();
+            if !changed && expired.is_empty() {
+                return;
+            }
+            if changed {
+                app_values.wireguard_data.increment_revision();
+            }
+            // a new server key reaches the interface through the reload, see WgQuickBackend
+            if let Err(error) = apply_changes(app_values) {
+                error!("Could not apply scheduled changes, rolling them back: {error}");
+                *app_values = previous;
+                server::restore_side_effects(app_values);
+                return;
+            }
+            if rotated {
+                info!("Rotated keys on schedule");
+            }
+            for name in &over_quota {
+                info!("Disabled the devices of user {name}, its transfer quota is used up");
+                record("user.quota_exceeded", name);
+            }
+            for name in &expired {
+                info!("Removed client {name} from the interface, it has expired");
+            }
+            for name in &expired_users {
+                info!("Removed the devices of user {name} from the interface, it has expired");
+                record("user.expired", name);
+            }
+        })
+        .await;
+    if let Err(error) = result {
+        error!("Could not run scheduled tasks: {error}");
+    }
+}
+
+fn record(action: &str, user: &str) {
+    if let Err(error) = audit::record(action, "scheduler", Some(format!("user:{user}")), None) {
+        warn!("Could not write audit event {action}: {error}");
+    }
+}
+
+fn apply_changes(app_values: &WireGuardAppValues) -> Result<(), Box<dyn std::error::Error>> {
+    data_manager::save_json_file(&app_values.wireguard_data)?;
+    firewall::refresh_firewall(&app_values.config, &app_values.wireguard_data)?;
+    if app_values.backend.is_up() {
+        data_manager::save_wireguard_config(&app_values.wireguard_data, &app_values.config)?;
+        app_values.backend.reload(&app_values.wireguard_data)?;
+    }
+    Ok(())
+}
@@ -1,110 +1,2249 @@
+use std::collections::HashMap;
+use std::io;
 use std::net::SocketAddr;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use axum::body::Body;
-use axum::extract::{Path, State};
-use axum::http::{Response, StatusCode};
+use axum::extract::{ConnectInfo, MatchedPath, Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_MATCH};
+use axum::http::{HeaderMap, Response, StatusCode};
+use axum::middleware::Next;
 use axum::response::IntoResponse;
-use axum::{Json, Router};
+use axum::{Extension, Json, Router};
 use serde::Serialize;
 use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tower_http::trace::TraceLayer;
+use tracing::{error, field, info, info_span, warn, Span};
 use uuid::Uuid;
 
+use crate::data::access_policy::AccessPolicy;
+use crate::data::audit;
+use crate::data::client_bulk;
+use crate::data::client_bulk::{BulkClientRequest, BulkClientResponse};
+use crate::data::client_config;
+use crate::data::client_config::{ConfigQuery, RenderedConfig};
+use crate::data::client_query::ClientQuery;
+use crate::data::client_transfer;
+use crate::data::client_transfer::{
+    ClientImportResponse, ExportQuery, ImportQuery, TransferFormat,
+};
+use crate::data::config::AppConfig;
 use crate::data::data_manager;
-use crate::data::wireguard_client::{WireGuardClientData, WireGuardOptionalClientData};
+use crate::data::external_import;
+use crate::data::external_import::{ExternalImportRequest, ExternalImportResponse};
+use crate::data::invitation::{
+    CreateInvitationRequest, CreatedInvitation, Invitation, InvitationTarget,
+    RedeemInvitationRequest,
+};
+use crate::data::portal::{
+    CreatePortalTokenRequest, CreatedPortalToken, PortalClientStatus, PortalSession, PortalToken,
+};
+use crate::data::time::current_time_millis;
+use crate::data::token;
+use crate::data::wireguard_client::{
+    RotateClientKeysRequest, WireGuardClientData, WireGuardOptionalClientData,
+};
 use crate::data::wireguard_data::WireGuardOptionalData;
-use crate::data::wireguard_server::WireGuardOptionalServerData;
-use crate::error::AppError;
+use crate::data::wireguard_group::WireGuardGroupData;
+use crate::data::wireguard_peer::WireGuardPeer;
+use crate::data::wireguard_server::{RotateServerKeysRequest, WireGuardOptionalServerData};
+use crate::data::wireguard_user::{WireGuardUserData, WireGuardUserDevices};
+use crate::diagnostics::Liveness;
+use crate::error::{AppError, RestAPIError};
+use crate::state::AppState;
+use crate::validation::FieldError;
 use crate::wireguard::RestartWireGuardErrorType;
-use crate::{wireguard, WireGuardAppValues};
-
-pub async fn start_server(app_values: Arc<Mutex<WireGuardAppValues>>) {
-    let address = SocketAddr::from_str(app_values.lock().unwrap().config.address.as_str())
-        .expect("Could not parse address");
-    tokio::spawn(async move {
-        let listener = match TcpListener::bind(address).await {
-            Ok(listener) => listener,
-            #[allow(unused_variables)] // bugged
+use crate::{diagnostics, firewall, preflight, validation, wireguard, WireGuardAppValues};
+
+const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+// binds before returning so that an unusable address fails startup instead of the task,
+// the server drains open requests and stops once shutdown is set
+pub async fn start_server(
+    state: AppState,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<JoinHandle<io::Result<()>>, AppError> {
+    let address = state.snapshot().config.address.to_owned();
+    let address = SocketAddr::from_str(&address)
+        .map_err(|_| AppError::InvalidListenAddress(address.to_owned()))?;
+    let listener = TcpListener::bind(address).await?;
+    let server = tokio::spawn(async move {
+        axum::serve(
+            listener,
+            router(state).into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+        })
+        .await
+    });
+
+    info!("Server started on {}", address);
+    Ok(server)
+}
+
+// every route of the API with its middleware, the integration tests drive it without a listener
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/wireguard/server",
+            axum::routing::get(get_wireguard_server),
+        )
+        .route(
+            "/wireguard/server",
+            axum::routing::put(put_wireguard_server),
+        )
+        .route(
+            "/wireguard/server",
+            axum::routing::patch(patch_wireguard_server),
+        )
+        .route(
+            "/wireguard/server",
+            axum::routing::delete(delete_wireguard_server),
+        )
+        .route(
+            "/wireguard/server/rotate-keys",
+            axum::routing::post(post_wireguard_server_rotate_keys),
+        )
+        .route(
+            "/wireguard/server/rotate-keys",
+            axum::routing::delete(delete_wireguard_server_rotate_keys),
+        )
+        .route(
+            "/wireguard/server/rotate-keys/activate",
+            axum::routing::post(post_wireguard_server_rotate_keys_activate),
+        )
+        .route(
+            "/wireguard/clients",
+            axum::routing::get(get_wireguard_clients),
+        )
+        .route(
+            "/wireguard/clients",
+            axum::routing::put(put_wireguard_clients),
+        )
+        .route(
+            "/wireguard/clients",
+            axum::routing::post(post_wireguard_clients),
+        )
+        .route(
+            "/wireguard/clients/bulk",
+            axum::routing::post(post_wireguard_clients_bulk),
+        )
+        .route(
+            "/wireguard/clients/import",
+            axum::routing::post(post_wireguard_clients_import),
+        )
+        .route(
+            "/wireguard/clients/export",
+            axum::routing::get(get_wireguard_clients_export),
+        )
+        .route(
+            "/wireguard/clients/{uuid}",
+            axum::routing::get(get_wireguard_client),
+        )
+        .route(
+            "/wireguard/clients/{uuid}",
+            axum::routing::put(put_wireguard_client),
+        )
+        .route(
+            "/wireguard/clients/{uuid}",
+            axum::routing::patch(patch_wireguard_client),
+        )
+        .route(
+            "/wireguard/clients/{uuid}",
+            axum::routing::delete(delete_wireguard_client),
+        )
+        .route(
+            "/wireguard/clients/{uuid}/config",
+            axum::routing::get(get_wireguard_client_config),
+        )
+        .route(
+            "/wireguard/clients/{uuid}/config/acknowledge",
+            axum::routing::post(post_wireguard_client_config_acknowledge),
+        )
+        .route(
+            "/wireguard/clients/{uuid}/rotate-keys",
+            axum::routing::post(post_wireguard_client_rotate_keys),
+        )
+        .route(
+            "/wireguard/clients/{uuid}/rotate-preshared-key",
+            axum::routing::post(post_wireguard_client_rotate_preshared_key),
+        )
+        .route(
+            "/wireguard/groups",
+            axum::routing::get(get_wireguard_groups),
+        )
+        .route(
+            "/wireguard/groups",
+            axum::routing::post(post_wireguard_groups),
+        )
+        .route(
+            "/wireguard/groups/{name}",
+            axum::routing::get(get_wireguard_group),
+        )
+        .route(
+            "/wireguard/groups/{name}",
+            axum::routing::put(put_wireguard_group),
+        )
+        .route(
+            "/wireguard/groups/{name}",
+            axum::routing::delete(delete_wireguard_group),
+        )
+        .route("/wireguard/users", axum::routing::get(get_wireguard_users))
+        .route(
+            "/wireguard/users",
+            axum::routing::post(post_wireguard_users),
+        )
+        .route(
+            "/wireguard/users/{name}",
+            axum::routing::get(get_wireguard_user),
+        )
+        .route(
+            "/wireguard/users/{name}",
+            axum::routing::put(put_wireguard_user),
+        )
+        .route(
+            "/wireguard/users/{name}",
+            axum::routing::delete(delete_wireguard_user),
+        )
+        .route(
+            "/wireguard/users/{name}/devices",
+            axum::routing::get(get_wireguard_user_devices),
+        )
+        .route(
+            "/wireguard/users/{name}/devices",
+            axum::routing::post(post_wireguard_user_devices),
+        )
+        .route(
+            "/wireguard/users/{name}/devices/{uuid}",
+            axum::routing::delete(delete_wireguard_user_device),
+        )
+        .route(
+            "/wireguard/import",
+            axum::routing::post(post_wireguard_import),
+        )
+        .route(
+            "/wireguard/invitations",
+            axum::routing::get(get_wireguard_invitations),
+        )
+        .route(
+            "/wireguard/invitations",
+            axum::routing::post(post_wireguard_invitations),
+        )
+        .route(
+            "/wireguard/invitations/{id}",
+            axum::routing::delete(delete_wireguard_invitation),
+        )
+        .route("/wireguard/audit", axum::routing::get(get_audit_events))
+        .route(
+            "/wireguard/portal-tokens",
+            axum::routing::get(get_portal_tokens),
+        )
+        .route(
+            "/wireguard/portal-tokens",
+            axum::routing::post(post_portal_tokens),
+        )
+        .route(
+            "/wireguard/portal-tokens/{id}",
+            axum::routing::delete(delete_portal_token),
+        )
+        .route("/wireguard/peers", axum::routing::get(get_wireguard_peers))
+        .route("/wireguard/policy", axum::routing::get(get_access_policy))
+        .route("/wireguard/policy", axum::routing::put(put_access_policy))
+        .route("/wireguard/restart", axum::routing::post(wireguard_restart)) // also saves into file
+        .route("/wireguard/reload", axum::routing::post(wireguard_reload)) // also saves into file
+        .route("/wireguard/start", axum::routing::post(wireguard_start))
+        .route("/wireguard/stop", axum::routing::post(wireguard_stop))
+        .route("/sample", axum::routing::get(sample))
+        .route("/diagnostics", axum::routing::get(get_diagnostics))
+        .route("/preflight", axum::routing::get(get_preflight))
+        .route(
+            "/preflight/fix-sysctls",
+            axum::routing::post(post_preflight_fix_sysctls),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            require_admin,
+        ))
+        .merge(portal_routes(state.clone()))
+        // invitations are redeemed by users without any token
+        .route("/invite/{token}", axum::routing::get(get_invitation))
+        .route("/invite/{token}", axum::routing::post(redeem_invitation))
+        // probes of load balancers and monitoring, they reveal no data
+        .route("/healthz", axum::routing::get(get_healthz))
+        .route("/readyz", axum::routing::get(get_readyz))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_request(())
+                .on_response(log_response)
+                .on_failure(()),
+        )
+        .with_state(state)
+}
+
+// the route is the matched pattern so that ids and tokens in the path never end up in the logs
+fn request_span(request: &Request<Body>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or_else(|| request.uri().path());
+    let remote_addr = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.to_string());
+    info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        remote_addr,
+        status = field::Empty,
+        latency_ms = field::Empty,
+    )
+}
+
+fn log_response(response: &Response<Body>, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+    info!("finished request");
+}
+
+// routes for end users, a portal token only ever reaches the clients it was issued for
+fn portal_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/portal/clients", axum::routing::get(get_portal_clients))
+        .route(
+            "/portal/clients/{uuid}/config",
+            axum::routing::get(get_portal_client_config),
+        )
+        .route(
+            "/portal/clients/{uuid}/config/acknowledge",
+            axum::routing::post(post_portal_client_config_acknowledge),
+        )
+        .route(
+            "/portal/clients/{uuid}/rotate-keys",
+            axum::routing::post(post_portal_client_rotate_keys),
+        )
+        .route(
+            "/portal/clients/{uuid}/enable",
+            axum::routing::post(post_portal_client_enable),
+        )
+        .route(
+            "/portal/clients/{uuid}/disable",
+            axum::routing::post(post_portal_client_disable),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            require_portal_token,
+        ))
+}
+
+async fn require_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let authorized = {
+        let app_values = state.snapshot();
+        !app_values.config.require_admin_token
+            || has_admin_token(request.headers(), &app_values.config)
+    };
+    if !authorized {
+        return ErrorResponse::from((
+            StatusCode::UNAUTHORIZED,
+            "A valid admin token is required".to_string(),
+        ))
+        .into();
+    }
+    next.run(request).await
+}
+
+fn has_admin_token(headers: &HeaderMap, config: &AppConfig) -> bool {
+    match (bearer_token(headers), &config.admin_token) {
+        (Some(token), Some(admin_token)) => {
+            token::hash_token(token) == token::hash_token(admin_token)
+        }
+        _ => false,
+    }
+}
+
+async fn require_portal_token(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response<Body> {
+    let session = {
+        let app_values = state.snapshot();
+        bearer_token(request.headers())
+            .and_then(|token| app_values.wireguard_data.find_portal_token(token))
+            .map(PortalToken::session)
+    };
+    match session {
+        Some(session) => {
+            request.extensions_mut().insert(session);
+            next.run(request).await
+        }
+        None => ErrorResponse::from((
+            StatusCode::UNAUTHORIZED,
+            "A valid portal token is required".to_string(),
+        ))
+        .into(),
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+async fn get_wireguard_server(State(state): State<AppState>) -> impl IntoResponse {
+    let app_values = state.snapshot();
+    (
+        StatusCode::OK,
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(app_values.wireguard_data.server.clone()),
+    )
+}
+
+async fn put_wireguard_server(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<Option<WireGuardOptionalServerData>>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let server = match body {
+            Some(server) => match server.to_wireguard_server_data(
+                app_values
+                    .wireguard_data
+                    .server
+                    .clone()
+                    .map(|server| server.endpoint),
+                &app_values.config,
+            ) {
+                Ok(server) => {
+                    let errors = validation::validate_server(&server);
+                    if !errors.is_empty() {
+                        return ErrorResponse::from(errors).into();
+                    }
+                    Some(server)
+                }
+                Err(error) => {
+                    return ErrorResponse::from((
+                        if let AppError::RestAPI(_) = error {
+                            StatusCode::BAD_REQUEST
+                        } else {
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        },
+                        format!("Could not create server: {error}"),
+                    ))
+                    .into();
+                }
+            },
+            None => None,
+        };
+        app_values.wireguard_data.server.clone_from(&server);
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(server),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn patch_wireguard_server(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<WireGuardOptionalServerData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let existing = match &app_values.wireguard_data.server {
+            Some(server) => server,
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    "Server has not been created yet".to_string(),
+                ))
+                .into()
+            }
+        };
+        let server = match body.merge_into(existing) {
+            Ok(server) => server,
+            Err(error) => {
+                return ErrorResponse::from((
+                    if let AppError::RestAPI(_) = error {
+                        StatusCode::BAD_REQUEST
+                    } else {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    },
+                    format!("Could not update server: {error}"),
+                ))
+                .into();
+            }
+        };
+        let errors = validation::validate_server(&server);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        app_values.wireguard_data.server = Some(server.clone());
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(server),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn delete_wireguard_server(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into_response();
+        }
+        app_values.wireguard_data.server = None;
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into();
+        }
+        // an interface can not be configured without a server, it and its rules go with it
+        if let Err(error) = firewall::remove_firewall(&app_values.config) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not remove firewall rules: {error}"),
+            ))
+            .into();
+        }
+        if let Err(error) = app_values.backend.stop() {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not stop WireGuard: {error}"),
+            ))
+            .into();
+        }
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+async fn post_wireguard_server_rotate_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Option<Json<RotateServerKeysRequest>>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let grace_period_hours = body.map(|body| body.grace_period_hours).unwrap_or(0);
+        if !app_values
+            .wireguard_data
+            .stage_server_key_rotation(grace_period_hours)
+        {
+            return ErrorResponse::from((
+                StatusCode::NOT_FOUND,
+                "Server has not been created yet".to_string(),
+            ))
+            .into();
+        }
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(app_values.wireguard_data.server.clone()),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn delete_wireguard_server_rotate_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        if !app_values.wireguard_data.cancel_server_key_rotation() {
+            return ErrorResponse::from((
+                StatusCode::NOT_FOUND,
+                "No server key rotation is pending".to_string(),
+            ))
+            .into();
+        }
+        app_values.wireguard_data.increment_revision();
+        match data_manager::save_json_file(&app_values.wireguard_data) {
+            Ok(_) => (
+                StatusCode::OK,
+                [(ETAG, etag(app_values.wireguard_data.revision))],
+                Json(app_values.wireguard_data.server.clone()),
+            )
+                .into_response(),
+            Err(error) => ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into(),
+        }
+    })
+    .await
+}
+
+async fn post_wireguard_server_rotate_keys_activate(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        if !app_values.wireguard_data.activate_pending_server_key() {
+            return ErrorResponse::from((
+                StatusCode::NOT_FOUND,
+                "No server key rotation is pending".to_string(),
+            ))
+            .into();
+        }
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        // the reload hands the interface its new private key
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(app_values.wireguard_data.server.clone()),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn get_wireguard_clients(
+    State(state): State<AppState>,
+    Query(query): Query<ClientQuery>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    let last_handshakes = if query.needs_handshakes() {
+        let backend = app_values.backend.clone();
+        match run_blocking(move || wireguard::get_last_handshakes(backend.as_ref())).await {
+            Ok(last_handshakes) => last_handshakes,
+            Err(error) => {
+                return ErrorResponse::from((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Could not get peers: {error}"),
+                ))
+                .into();
+            }
+        }
+    } else {
+        HashMap::new()
+    };
+    let page = query.apply(&app_values.wireguard_data.clients, |client| {
+        client.query_item(last_handshakes.get(&client.public_key).copied())
+    });
+    (
+        StatusCode::OK,
+        [
+            (TOTAL_COUNT_HEADER, page.total.to_string()),
+            (ETAG.as_str(), etag(app_values.wireguard_data.revision)),
+        ],
+        Json(page.items),
+    )
+        .into_response()
+}
+
+async fn put_wireguard_clients(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut body): Json<Vec<WireGuardClientData>>,
+) -> impl IntoResponse {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into_response();
+        }
+        let errors = validation::validate_clients(&body, app_values.wireguard_data.server.as_ref());
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into_response();
+        }
+        for client in &mut body {
+            let existing = app_values.wireguard_data.get_client_config(&client.uuid);
+            client.revision = existing
+                .as_ref()
+                .map(|existing| existing.revision + 1)
+                .unwrap_or(app_values.wireguard_data.revision + 1);
+            client.keep_admin_disabled(existing.as_ref());
+        }
+        app_values.wireguard_data.clients = body;
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            String::new(),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn get_wireguard_client(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    match app_values.wireguard_data.get_client_config(&uuid) {
+        Some(client) => (
+            StatusCode::OK,
+            [(ETAG, etag(client.revision))],
+            Json(client),
+        )
+            .into_response(),
+        None => ErrorResponse::from((
+            StatusCode::NOT_FOUND,
+            format!("Client config for uuid {} not found", uuid),
+        ))
+        .into(),
+    }
+}
+
+async fn get_wireguard_client_config(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    Query(query): Query<ConfigQuery>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    let client_index = match find_client_index(&app_values, &uuid) {
+        Ok(index) => index,
+        Err(error) => return error.into(),
+    };
+    let server = match &app_values.wireguard_data.server {
+        Some(server) => server,
+        None => {
+            return ErrorResponse::from((
+                StatusCode::NOT_FOUND,
+                "Server has not been created yet".to_string(),
+            ))
+            .into()
+        }
+    };
+    let client = &app_values.wireguard_data.clients[client_index];
+    let config = match client_config::render_client_config(client, server, query.format) {
+        Ok(config) => config,
+        Err(error) => {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not render config: {error}"),
+            ))
+            .into()
+        }
+    };
+    config_response(config)
+}
+
+// a client confirms that it installed its current config, which is how it catches up with a
+// key rotation
+async fn post_wireguard_client_config_acknowledge(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let client_index = match find_client_index(app_values, &uuid) {
+            Ok(index) => index,
+            Err(error) => return error.into(),
+        };
+        let client = &mut app_values.wireguard_data.clients[client_index];
+        if let Err(error) = check_if_match(&headers, client.revision) {
+            return error.into();
+        }
+        client.config_outdated = false;
+        client.revision += 1;
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into();
+        }
+        let client = app_values.wireguard_data.clients[client_index].clone();
+        (
+            StatusCode::OK,
+            [(ETAG, etag(client.revision))],
+            Json(client),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn post_wireguard_client_rotate_keys(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+    body: Option<Json<RotateClientKeysRequest>>,
+) -> Response<Body> {
+    let public_key = body.and_then(|body| body.0.public_key);
+    rotate_client_keys(state, uuid, headers, move |client| {
+        client.rotate_keypair(public_key.as_ref())
+    })
+    .await
+}
+
+async fn post_wireguard_client_rotate_preshared_key(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    rotate_client_keys(state, uuid, headers, |client| {
+        client.rotate_preshared_key();
+        Ok(())
+    })
+    .await
+}
+
+async fn rotate_client_keys(
+    state: AppState,
+    uuid: Uuid,
+    headers: HeaderMap,
+    rotate: impl FnOnce(&mut WireGuardClientData) -> Result<(), AppError> + Send + 'static,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let client_index = match find_client_index(app_values, &uuid) {
+            Ok(index) => index,
+            Err(error) => return error.into(),
+        };
+        if let Err(error) = check_if_match(
+            &headers,
+            app_values.wireguard_data.clients[client_index].revision,
+        ) {
+            return error.into();
+        }
+        let mut client = app_values.wireguard_data.clients[client_index].clone();
+        if let Err(error) = rotate(&mut client) {
+            return ErrorResponse::from((
+                if let AppError::RestAPI(_) = error {
+                    StatusCode::BAD_REQUEST
+                } else {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                },
+                format!("Could not rotate keys: {error}"),
+            ))
+            .into();
+        }
+        let errors = validation::validate_client(&client, &app_values.wireguard_data);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        app_values.wireguard_data.clients[client_index] = client;
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        let client = app_values.wireguard_data.clients[client_index].clone();
+        (
+            StatusCode::OK,
+            [(ETAG, etag(client.revision))],
+            Json(client),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn put_wireguard_client(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+    Json(mut body): Json<WireGuardClientData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if body.uuid != uuid {
+            return ErrorResponse::from((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Could not update client: {}",
+                    RestAPIError::UuidChanged(uuid, body.uuid)
+                ),
+            ))
+            .into();
+        }
+        let errors = validation::validate_client(&body, &app_values.wireguard_data);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        let client_index = match app_values
+            .wireguard_data
+            .clients
+            .iter()
+            .position(|client| client.uuid == uuid)
+        {
+            Some(index) => index,
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    format!("Client config for uuid {} not found", uuid),
+                ))
+                .into()
+            }
+        };
+        let revision = app_values.wireguard_data.clients[client_index].revision;
+        if let Err(error) = check_if_match(&headers, revision) {
+            return error.into();
+        }
+        body.revision = revision + 1;
+        body.keep_admin_disabled(Some(&app_values.wireguard_data.clients[client_index]));
+        app_values.wireguard_data.clients[client_index] = body;
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (StatusCode::OK, [(ETAG, etag(revision + 1))], String::new()).into_response()
+    })
+    .await
+}
+
+async fn patch_wireguard_client(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+    Json(body): Json<WireGuardOptionalClientData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let client_index = match app_values
+            .wireguard_data
+            .clients
+            .iter()
+            .position(|client| client.uuid == uuid)
+        {
+            Some(index) => index,
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    format!("Client config for uuid {} not found", uuid),
+                ))
+                .into()
+            }
+        };
+        if let Err(error) = check_if_match(
+            &headers,
+            app_values.wireguard_data.clients[client_index].revision,
+        ) {
+            return error.into();
+        }
+        let mut client = match body.merge_into(
+            &app_values.wireguard_data.clients[client_index],
+            &app_values.wireguard_data,
+        ) {
+            Ok(client) => client,
+            Err(error) => {
+                return ErrorResponse::from((
+                    if let AppError::RestAPI(_) = error {
+                        StatusCode::BAD_REQUEST
+                    } else {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    },
+                    format!("Could not update client: {error}"),
+                ))
+                .into();
+            }
+        };
+        let errors = validation::validate_client(&client, &app_values.wireguard_data);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        client.revision += 1;
+        app_values.wireguard_data.clients[client_index] = client.clone();
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(client.revision))],
+            Json(client),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn delete_wireguard_client(
+    State(state): State<AppState>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let client_index = match app_values
+            .wireguard_data
+            .clients
+            .iter()
+            .position(|client| client.uuid == uuid)
+        {
+            Some(index) => index,
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    format!("Client config for uuid {} not found", uuid),
+                ))
+                .into()
+            }
+        };
+        if let Err(error) = check_if_match(
+            &headers,
+            app_values.wireguard_data.clients[client_index].revision,
+        ) {
+            return error.into();
+        }
+        app_values.wireguard_data.clients.remove(client_index);
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+async fn post_wireguard_clients(
+    State(state): State<AppState>,
+    Json(body): Json<WireGuardOptionalClientData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let new_client = match body.to_wireguard_client_data(
+            None,
+            &app_values.config,
+            &app_values.wireguard_data,
+        ) {
+            Ok(client) => client,
+            Err(error) => {
+                return ErrorResponse::from((
+                    if let AppError::RestAPI(_) = error {
+                        StatusCode::BAD_REQUEST
+                    } else {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    },
+                    format!("Could not create client: {error}"),
+                ))
+                .into();
+            }
+        };
+        if app_values
+            .wireguard_data
+            .clients
+            .iter()
+            .any(|client| client.uuid == new_client.uuid)
+        {
+            return ErrorResponse::from((
+                StatusCode::CONFLICT,
+                format!("Client with uuid {} already exists", new_client.uuid),
+            ))
+            .into();
+        }
+        let errors = validation::validate_client(&new_client, &app_values.wireguard_data);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        app_values.wireguard_data.clients.push(new_client.clone());
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(new_client.revision))],
+            Json(new_client),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn post_wireguard_clients_bulk(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<BulkClientRequest>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let (data, results) = client_bulk::apply_bulk_operations(
+            &body.operations,
+            &app_values.config,
+            &app_values.wireguard_data,
+        );
+        let data = match data {
+            Some(data) => data,
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(BulkClientResponse {
+                        applied: false,
+                        results,
+                    }),
+                )
+                    .into_response()
+            }
+        };
+        app_values.wireguard_data = data;
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(BulkClientResponse {
+                applied: true,
+                results,
+            }),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn post_wireguard_clients_import(
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let rows = match client_transfer::parse_import(query.format, &body) {
+            Ok(rows) => rows,
+            Err(error) => {
+                return ErrorResponse::from((
+                    StatusCode::BAD_REQUEST,
+                    format!("Could not read import: {error}"),
+                ))
+                .into()
+            }
+        };
+        let (data, results) =
+            client_transfer::import_clients(&rows, &app_values.config, &app_values.wireguard_data);
+        let data = match data {
+            Some(data) if !query.dry_run => data,
+            data => {
+                return (
+                    if data.is_some() {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::BAD_REQUEST
+                    },
+                    Json(ClientImportResponse {
+                        dry_run: query.dry_run,
+                        applied: false,
+                        results,
+                    }),
+                )
+                    .into_response()
+            }
+        };
+        app_values.wireguard_data = data;
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(ClientImportResponse {
+                dry_run: false,
+                applied: true,
+                results,
+            }),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn post_wireguard_import(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<ExternalImportRequest>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let mut import = external_import::parse_export(body.export);
+
+        let mut data = app_values.wireguard_data.clone();
+        let server = if body.import_server || data.server.is_none() {
+            if body.endpoint.is_some() {
+                import.server.endpoint.clone_from(&body.endpoint);
+            }
+            let server = match import.server.to_wireguard_server_data(
+                data.server
+                    .as_ref()
+                    .map(|server| server.endpoint.to_owned()),
+                &app_values.config,
+            ) {
+                Ok(server) => server,
+                Err(error) => {
+                    return ErrorResponse::from((
+                        if let AppError::RestAPI(_) = error {
+                            StatusCode::BAD_REQUEST
+                        } else {
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        },
+                        format!("Could not import server: {error}"),
+                    ))
+                    .into();
+                }
+            };
+            let errors = validation::validate_server(&server);
+            if !errors.is_empty() {
+                return ErrorResponse::from(errors).into();
+            }
+            data.server = Some(server.clone());
+            Some(server)
+        } else {
+            None
+        };
+
+        let (data, results) =
+            client_transfer::import_clients(&import.rows, &app_values.config, &data);
+        let data = match data {
+            Some(data) if !body.dry_run => data,
+            data => {
+                return (
+                    if data.is_some() {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::BAD_REQUEST
+                    },
+                    Json(ExternalImportResponse {
+                        dry_run: body.dry_run,
+                        applied: false,
+                        server,
+                        results,
+                    }),
+                )
+                    .into_response()
+            }
+        };
+        app_values.wireguard_data = data;
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(ExternalImportResponse {
+                dry_run: false,
+                applied: true,
+                server,
+                results,
+            }),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn get_wireguard_clients_export(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    if query.include_secrets {
+        if !app_values.config.allow_secret_export {
+            return ErrorResponse::from((
+                StatusCode::FORBIDDEN,
+                "Exporting secrets is disabled, set allow_secret_export to allow it".to_string(),
+            ))
+            .into();
+        }
+        // also checked when require_admin_token is turned off
+        if !has_admin_token(&headers, &app_values.config) {
+            return ErrorResponse::from((
+                StatusCode::UNAUTHORIZED,
+                "A valid admin token is required to export secrets".to_string(),
+            ))
+            .into();
+        }
+    }
+    match client_transfer::export_clients(
+        query.format,
+        &app_values.wireguard_data.clients,
+        query.include_secrets,
+    ) {
+        Ok(export) => {
+            if query.include_secrets {
+                audit(
+                    "clients.secrets_exported",
+                    "admin",
+                    None,
+                    Some(format!(
+                        "{} clients",
+                        app_values.wireguard_data.clients.len()
+                    )),
+                );
+            }
+            (
+                StatusCode::OK,
+                [(
+                    CONTENT_TYPE,
+                    match query.format {
+                        TransferFormat::Json => "application/json",
+                        TransferFormat::Csv => "text/csv",
+                    },
+                )],
+                export,
+            )
+                .into_response()
+        }
+        Err(error) => ErrorResponse::from((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Could not export clients: {error}"),
+        ))
+        .into(),
+    }
+}
+
+async fn get_wireguard_invitations(State(state): State<AppState>) -> impl IntoResponse {
+    let app_values = state.snapshot();
+    (
+        StatusCode::OK,
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(app_values.wireguard_data.invitations.clone()),
+    )
+}
+
+async fn post_wireguard_invitations(
+    State(state): State<AppState>,
+    Json(body): Json<CreateInvitationRequest>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let target = match &body.target {
+            InvitationTarget::Client(uuid) => match find_client_index(app_values, uuid) {
+                Ok(_) => format!("client:{uuid}"),
+                Err(error) => return error.into(),
+            },
+            InvitationTarget::Group(group) => {
+                if app_values.wireguard_data.get_group(group).is_none() {
+                    return ErrorResponse::from((
+                        StatusCode::NOT_FOUND,
+                        format!("Group {group} not found"),
+                    ))
+                    .into();
+                }
+                format!("group:{group}")
+            }
+        };
+        let (invitation, token) = Invitation::new(body.target, body.label, body.expires_in_hours);
+        app_values
+            .wireguard_data
+            .invitations
+            .push(invitation.clone());
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into();
+        }
+        audit(
+            "invitation.created",
+            "admin",
+            Some(target),
+            Some(format!("invitation {}", invitation.id)),
+        );
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(CreatedInvitation { invitation, token }),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn delete_wireguard_invitation(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let index = match app_values
+            .wireguard_data
+            .invitations
+            .iter()
+            .position(|invitation| invitation.id == id)
+        {
+            Some(index) => index,
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    format!("Invitation {id} not found"),
+                ))
+                .into()
+            }
+        };
+        app_values.wireguard_data.invitations.remove(index);
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into();
+        }
+        audit(
+            "invitation.revoked",
+            "admin",
+            Some(format!("invitation:{id}")),
+            None,
+        );
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+async fn get_portal_tokens(State(state): State<AppState>) -> impl IntoResponse {
+    let app_values = state.snapshot();
+    (
+        StatusCode::OK,
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(app_values.wireguard_data.portal_tokens.clone()),
+    )
+}
+
+async fn post_portal_tokens(
+    State(state): State<AppState>,
+    Json(body): Json<CreatePortalTokenRequest>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        for uuid in &body.clients {
+            if let Err(error) = find_client_index(app_values, uuid) {
+                return error.into();
+            }
+        }
+        let (portal_token, token) = PortalToken::new(body.name, body.clients);
+        app_values
+            .wireguard_data
+            .portal_tokens
+            .push(portal_token.clone());
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into();
+        }
+        audit(
+            "portal_token.created",
+            "admin",
+            Some(format!("portal_token:{}", portal_token.id)),
+            None,
+        );
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(CreatedPortalToken {
+                portal_token,
+                token,
+            }),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn delete_portal_token(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let index = match app_values
+            .wireguard_data
+            .portal_tokens
+            .iter()
+            .position(|portal_token| portal_token.id == id)
+        {
+            Some(index) => index,
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    format!("Portal token {id} not found"),
+                ))
+                .into()
+            }
+        };
+        app_values.wireguard_data.portal_tokens.remove(index);
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into();
+        }
+        audit(
+            "portal_token.revoked",
+            "admin",
+            Some(format!("portal_token:{id}")),
+            None,
+        );
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+async fn get_portal_clients(
+    State(state): State<AppState>,
+    Extension(session): Extension<PortalSession>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    // usage is simply missing while the interface is down
+    let backend = app_values.backend.clone();
+    let usage = run_blocking(move || wireguard::get_peer_usage(backend.as_ref()))
+        .await
+        .unwrap_or_default();
+    let clients: Vec<PortalClientStatus> = app_values
+        .wireguard_data
+        .clients
+        .iter()
+        .filter(|client| session.owns(&client.uuid))
+        .map(|client| PortalClientStatus::new(client, usage.get(&client.public_key)))
+        .collect();
+    (StatusCode::OK, Json(clients)).into_response()
+}
+
+async fn get_portal_client_config(
+    State(state): State<AppState>,
+    Extension(session): Extension<PortalSession>,
+    Path(uuid): Path<Uuid>,
+    query: Query<ConfigQuery>,
+) -> Response<Body> {
+    if !session.owns(&uuid) {
+        return portal_client_not_found(&uuid);
+    }
+    get_wireguard_client_config(State(state), Path(uuid), query).await
+}
+
+async fn post_portal_client_config_acknowledge(
+    State(state): State<AppState>,
+    Extension(session): Extension<PortalSession>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !session.owns(&uuid) {
+        return portal_client_not_found(&uuid);
+    }
+    post_wireguard_client_config_acknowledge(State(state), Path(uuid), headers).await
+}
+
+async fn post_portal_client_rotate_keys(
+    State(state): State<AppState>,
+    Extension(session): Extension<PortalSession>,
+    Path(uuid): Path<Uuid>,
+    headers: HeaderMap,
+    body: Option<Json<RotateClientKeysRequest>>,
+) -> Response<Body> {
+    if !session.owns(&uuid) {
+        return portal_client_not_found(&uuid);
+    }
+    let response = post_wireguard_client_rotate_keys(State(state), Path(uuid), headers, body).await;
+    if response.status().is_success() {
+        audit(
+            "client.keys_rotated",
+            &format!("portal:{}", session.name),
+            Some(format!("client:{uuid}")),
+            None,
+        );
+    }
+    response
+}
+
+async fn post_portal_client_enable(
+    State(state): State<AppState>,
+    Extension(session): Extension<PortalSession>,
+    Path(uuid): Path<Uuid>,
+) -> Response<Body> {
+    set_portal_client_enabled(state, session, uuid, true).await
+}
+
+async fn post_portal_client_disable(
+    State(state): State<AppState>,
+    Extension(session): Extension<PortalSession>,
+    Path(uuid): Path<Uuid>,
+) -> Response<Body> {
+    set_portal_client_enabled(state, session, uuid, false).await
+}
+
+async fn set_portal_client_enabled(
+    state: AppState,
+    session: PortalSession,
+    uuid: Uuid,
+    enabled: bool,
+) -> Response<Body> {
+    if !session.owns(&uuid) {
+        return portal_client_not_found(&uuid);
+    }
+    write(&state, move |app_values| {
+        let client_index = match find_client_index(app_values, &uuid) {
+            Ok(index) => index,
+            Err(error) => return error.into(),
+        };
+        let client = &mut app_values.wireguard_data.clients[client_index];
+        if enabled && client.admin_disabled {
+            return ErrorResponse::from((
+                StatusCode::FORBIDDEN,
+                format!("Client {uuid} was disabled by an admin"),
+            ))
+            .into();
+        }
+        if client.enabled != enabled {
+            client.enabled = enabled;
+            client.revision += 1;
+            app_values.wireguard_data.increment_revision();
+            if let Err(error) = save_and_refresh_firewall(app_values) {
+                return error.into();
+            }
+            if let Err(error) = apply_to_interface(app_values) {
+                return error.into();
+            }
+            audit(
+                if enabled {
+                    "client.enabled"
+                } else {
+                    "client.disabled"
+                },
+                &format!("portal:{}", session.name),
+                Some(format!("client:{uuid}")),
+                None,
+            );
+        }
+        let usage = wireguard::get_peer_usage(app_values.backend.as_ref()).unwrap_or_default();
+        let client = &app_values.wireguard_data.clients[client_index];
+        (
+            StatusCode::OK,
+            Json(PortalClientStatus::new(
+                client,
+                usage.get(&client.public_key),
+            )),
+        )
+            .into_response()
+    })
+    .await
+}
+
+// portal users get the same answer for clients of others as for missing ones
+fn portal_client_not_found(uuid: &Uuid) -> Response<Body> {
+    ErrorResponse::from((
+        StatusCode::NOT_FOUND,
+        format!("Client config for uuid {} not found", uuid),
+    ))
+    .into()
+}
+
+async fn get_audit_events() -> Response<Body> {
+    match audit::read_events() {
+        Ok(events) => (StatusCode::OK, Json(events)).into_response(),
+        Err(error) => ErrorResponse::from((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Could not read audit log: {error}"),
+        ))
+        .into(),
+    }
+}
+
+// lets the invite page show what the token is for without using it up
+async fn get_invitation(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    match find_usable_invitation(&app_values, &token) {
+        Ok(index) => (
+            StatusCode::OK,
+            Json(app_values.wireguard_data.invitations[index].info()),
+        )
+            .into_response(),
+        Err(error) => error.into(),
+    }
+}
+
+async fn redeem_invitation(
+    State(state): State<AppState>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    Path(token): Path<String>,
+    body: Option<Json<RedeemInvitationRequest>>,
+) -> Response<Body> {
+    // only known when the router is served with connect info, like in request_span
+    let remote_ip = connect_info.map(|Extension(ConnectInfo(address))| address.ip().to_string());
+    write(&state, move |app_values| {
+        let body = body.map(|body| body.0).unwrap_or_default();
+        let index = match find_usable_invitation(app_values, &token) {
+            Ok(index) => index,
+            Err(error) => return error.into(),
+        };
+        let server = match &app_values.wireguard_data.server {
+            Some(server) => server.clone(),
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    "Server has not been created yet".to_string(),
+                ))
+                .into()
+            }
+        };
+
+        let mut data = app_values.wireguard_data.clone();
+        let client = match data.invitations[index].target.clone() {
+            InvitationTarget::Client(uuid) => {
+                let client_index = match find_client_index(app_values, &uuid) {
+                    Ok(client_index) => client_index,
+                    Err(error) => return error.into(),
+                };
+                let mut client = data.clients[client_index].clone();
+                if let Some(public_key) = &body.public_key {
+                    if let Err(error) = client.rotate_keypair(Some(public_key)) {
+                        return ErrorResponse::from((
+                            StatusCode::BAD_REQUEST,
+                            format!("Could not redeem invitation: {error}"),
+                        ))
+                        .into();
+                    }
+                }
+                client.config_outdated = false;
+                data.clients[client_index] = client.clone();
+                client
+            }
+            InvitationTarget::Group(group) => {
+                let new_client = WireGuardOptionalClientData {
+                    name: Some(body.name.unwrap_or_else(|| "Invited device".to_string())),
+                    enabled: Some(true),
+                    group: Some(group),
+                    public_key: body.public_key,
+                    ..Default::default()
+                };
+                match new_client.to_wireguard_client_data(None, &app_values.config, &data) {
+                    Ok(client) => {
+                        data.clients.push(client.clone());
+                        client
+                    }
+                    Err(error) => {
+                        return ErrorResponse::from((
+                            if let AppError::RestAPI(_) = error {
+                                StatusCode::BAD_REQUEST
+                            } else {
+                                StatusCode::INTERNAL_SERVER_ERROR
+                            },
+                            format!("Could not redeem invitation: {error}"),
+                        ))
+                        .into();
+                    }
+                }
+            }
+        };
+        let errors = validation::validate_client(&client, &data);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        let config = match client_config::render_client_config(&client, &server, body.format) {
+            Ok(config) => config,
             Err(error) => {
-                panic!("Could not bind to address {address}: {error}");
+                return ErrorResponse::from((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Could not render config: {error}"),
+                ))
+                .into()
             }
         };
-        let server = axum::serve(
-            listener,
-            Router::new()
-                .route(
-                    "/wireguard/server",
-                    axum::routing::get(get_wireguard_server),
-                )
-                .route(
-                    "/wireguard/server",
-                    axum::routing::put(put_wireguard_server),
-                )
-                .route(
-                    "/wireguard/server",
-                    axum::routing::delete(delete_wireguard_server),
-                )
-                .route(
-                    "/wireguard/clients",
-                    axum::routing::get(get_wireguard_clients),
-                )
-                .route(
-                    "/wireguard/clients",
-                    axum::routing::put(put_wireguard_clients),
-                )
-                .route(
-                    "/wireguard/clients",
-                    axum::routing::post(post_wireguard_clients),
-                )
-                .route(
-                    "/wireguard/clients/:uuid",
-                    axum::routing::get(get_wireguard_client),
-                )
-                .route(
-                    "/wireguard/clients/:uuid",
-                    axum::routing::put(put_wireguard_client),
-                )
-                .route("/wireguard/peers", axum::routing::get(get_wireguard_peers))
-                .route("/wireguard/restart", axum::routing::post(wireguard_restart)) // also saves into file
-                .route("/wireguard/reload", axum::routing::post(wireguard_reload)) // also saves into file
-                .route("/wireguard/start", axum::routing::post(wireguard_start))
-                .route("/wireguard/stop", axum::routing::post(wireguard_stop))
-                .route("/sample", axum::routing::get(sample))
-                .with_state(app_values)
-                .into_make_service_with_connect_info::<SocketAddr>(),
+
+        let invitation = &mut data.invitations[index];
+        invitation.redeemed_at = Some(current_time_millis());
+        invitation.redeemed_by.clone_from(&remote_ip);
+        invitation.client = Some(client.uuid);
+        let invitation_id = invitation.id;
+        app_values.wireguard_data = data;
+        app_values.wireguard_data.increment_revision();
+
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        audit(
+            "invitation.redeemed",
+            remote_ip.as_deref().unwrap_or("unknown"),
+            Some(format!("client:{}", client.uuid)),
+            Some(format!("invitation {invitation_id}")),
         );
-        server.await.unwrap();
-        panic!("Server stopped unexpectedly");
-    });
+        config_response(config)
+    })
+    .await
+}
 
-    println!("Server started on {}", address);
+async fn get_wireguard_groups(State(state): State<AppState>) -> impl IntoResponse {
+    let app_values = state.snapshot();
+    (
+        StatusCode::OK,
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(app_values.wireguard_data.groups.clone()),
+    )
 }
 
-async fn get_wireguard_server(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> impl IntoResponse {
+async fn post_wireguard_groups(
+    State(state): State<AppState>,
+    Json(body): Json<WireGuardGroupData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        let errors = validation::validate_group(&body);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        if app_values.wireguard_data.get_group(&body.name).is_some() {
+            return ErrorResponse::from((
+                StatusCode::CONFLICT,
+                format!("Group {} already exists", body.name),
+            ))
+            .into();
+        }
+        app_values.wireguard_data.groups.push(body.clone());
+        app_values.wireguard_data.increment_revision();
+        match data_manager::save_json_file(&app_values.wireguard_data) {
+            Ok(_) => (StatusCode::OK, Json(body)).into_response(),
+            Err(error) => ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into(),
+        }
+    })
+    .await
+}
+
+async fn get_wireguard_group(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    match app_values.wireguard_data.get_group(&name) {
+        Some(group) => (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(group.clone()),
+        )
+            .into_response(),
+        None => {
+            ErrorResponse::from((StatusCode::NOT_FOUND, format!("Group {} not found", name))).into()
+        }
+    }
+}
+
+async fn put_wireguard_group(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<WireGuardGroupData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        if body.name != name {
+            return ErrorResponse::from((
+                StatusCode::BAD_REQUEST,
+                format!("Group name {} does not match {}", body.name, name),
+            ))
+            .into();
+        }
+        let errors = validation::validate_group(&body);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        let group_index = app_values
+            .wireguard_data
+            .groups
+            .iter()
+            .position(|group| group.name == name);
+        match group_index {
+            Some(index) => app_values.wireguard_data.groups[index] = body.clone(),
+            None => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    format!("Group {} not found", name),
+                ))
+                .into()
+            }
+        }
+        app_values.wireguard_data.increment_revision();
+
+        match data_manager::save_json_file(&app_values.wireguard_data) {
+            Ok(_) => (
+                StatusCode::OK,
+                [(ETAG, etag(app_values.wireguard_data.revision))],
+                Json(body),
+            )
+                .into_response(),
+            Err(error) => ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into(),
+        }
+    })
+    .await
+}
+
+async fn delete_wireguard_group(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        if app_values.wireguard_data.get_group(&name).is_none() {
+            return ErrorResponse::from((
+                StatusCode::NOT_FOUND,
+                format!("Group {} not found", name),
+            ))
+            .into();
+        }
+        if app_values
+            .wireguard_data
+            .clients
+            .iter()
+            .any(|client| client.group.as_ref() == Some(&name))
+        {
+            return ErrorResponse::from((
+                StatusCode::CONFLICT,
+                format!("Group {} still has clients", name),
+            ))
+            .into();
+        }
+        app_values
+            .wireguard_data
+            .groups
+            .retain(|group| group.name != name);
+        app_values
+            .wireguard_data
+            .access_policy
+            .group_isolation
+            .remove(&name);
+        app_values.wireguard_data.increment_revision();
+        match save_and_refresh_firewall(app_values) {
+            Ok(_) => (StatusCode::OK, String::new()).into_response(),
+            Err(error) => error.into(),
+        }
+    })
+    .await
+}
+
+async fn get_wireguard_users(State(state): State<AppState>) -> impl IntoResponse {
+    let app_values = state.snapshot();
+    (
+        StatusCode::OK,
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(app_values.wireguard_data.users.clone()),
+    )
+}
+
+async fn post_wireguard_users(
+    State(state): State<AppState>,
+    Json(body): Json<WireGuardUserData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if body.name.trim().is_empty() {
+            return ErrorResponse::from((
+                StatusCode::BAD_REQUEST,
+                "User name must not be empty".to_string(),
+            ))
+            .into();
+        }
+        if app_values.wireguard_data.get_user(&body.name).is_some() {
+            return ErrorResponse::from((
+                StatusCode::CONFLICT,
+                format!("User {} already exists", body.name),
+            ))
+            .into();
+        }
+        app_values.wireguard_data.users.push(body.clone());
+        app_values.wireguard_data.increment_revision();
+        match data_manager::save_json_file(&app_values.wireguard_data) {
+            Ok(_) => (StatusCode::OK, Json(body)).into_response(),
+            Err(error) => ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into(),
+        }
+    })
+    .await
+}
+
+async fn get_wireguard_user(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    let user = match app_values.wireguard_data.get_user(&name) {
+        Some(user) => user.clone(),
+        None => return user_not_found(&name),
+    };
+    // usage is simply missing while the interface is down
+    let backend = app_values.backend.clone();
+    let usage = run_blocking(move || wireguard::get_peer_usage(backend.as_ref()))
+        .await
+        .unwrap_or_default();
+    let devices = app_values.wireguard_data.get_user_devices(&name);
+    (
+        StatusCode::OK,
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(WireGuardUserDevices {
+            transferred_bytes: user.transferred_bytes(&devices, &usage),
+            user,
+            devices,
+        }),
+    )
+        .into_response()
+}
+
+async fn put_wireguard_user(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<WireGuardUserData>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        if body.name != name {
+            return ErrorResponse::from((
+                StatusCode::BAD_REQUEST,
+                format!("User name {} does not match {}", body.name, name),
+            ))
+            .into();
+        }
+        let user_index = match app_values
+            .wireguard_data
+            .users
+            .iter()
+            .position(|user| user.name == name)
+        {
+            Some(index) => index,
+            None => return user_not_found(&name),
+        };
+        let device_count = app_values.wireguard_data.get_user_devices(&name).len();
+        if body
+            .max_devices
+            .is_some_and(|max_devices| device_count > max_devices as usize)
+        {
+            return ErrorResponse::from((
+                StatusCode::CONFLICT,
+                format!("User {name} already has {device_count} devices"),
+            ))
+            .into();
+        }
+        app_values.wireguard_data.users[user_index] = body.clone();
+        for device in app_values
+            .wireguard_data
+            .clients
+            .iter_mut()
+            .filter(|client| client.user.as_ref() == Some(&name))
+        {
+            body.apply_to_device(device);
+        }
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(body),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn delete_wireguard_user(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        if app_values.wireguard_data.get_user(&name).is_none() {
+            return user_not_found(&name);
+        }
+        if !app_values.wireguard_data.get_user_devices(&name).is_empty() {
+            return ErrorResponse::from((
+                StatusCode::CONFLICT,
+                format!("User {} still has devices", name),
+            ))
+            .into();
+        }
+        app_values
+            .wireguard_data
+            .users
+            .retain(|user| user.name != name);
+        app_values.wireguard_data.increment_revision();
+        match data_manager::save_json_file(&app_values.wireguard_data) {
+            Ok(_) => (StatusCode::OK, String::new()).into_response(),
+            Err(error) => ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into(),
+        }
+    })
+    .await
+}
+
+async fn get_wireguard_user_devices(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    if app_values.wireguard_data.get_user(&name).is_none() {
+        return user_not_found(&name);
+    }
     (
         StatusCode::OK,
-        Json(app_values.lock().unwrap().wireguard_data.server.clone()),
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(app_values.wireguard_data.get_user_devices(&name)),
     )
+        .into_response()
 }
 
-async fn put_wireguard_server(
-    State(app_values_arc): State<Arc<Mutex<WireGuardAppValues>>>,
-    Json(body): Json<Option<WireGuardOptionalServerData>>,
+async fn post_wireguard_user_devices(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<WireGuardOptionalClientData>>,
 ) -> Response<Body> {
-    let mut app_values = app_values_arc.lock().unwrap();
-    let server = match body {
-        Some(server) => match server.to_wireguard_server_data(
-            app_values
-                .wireguard_data
-                .server
-                .clone()
-                .map(|server| server.endpoint),
-            &app_values,
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let user = match app_values.wireguard_data.get_user(&name) {
+            Some(user) => user.clone(),
+            None => return user_not_found(&name),
+        };
+        let device_count = app_values.wireguard_data.get_user_devices(&name).len();
+        if !user.has_room_for_device(device_count) {
+            return ErrorResponse::from((
+                StatusCode::CONFLICT,
+                format!("User {name} already has the maximum number of devices"),
+            ))
+            .into();
+        }
+        let body = body.map(|Json(body)| body).unwrap_or_default();
+        let mut device = match body.to_wireguard_client_data(
+            Some(format!("{name}-{}", device_count + 1)),
+            &app_values.config,
+            &app_values.wireguard_data,
         ) {
-            Ok(server) => Some(server),
+            Ok(device) => device,
             Err(error) => {
                 return ErrorResponse::from((
                     if let AppError::RestAPI(_) = error {
@@ -112,246 +2251,537 @@ async fn put_wireguard_server(
                     } else {
                         StatusCode::INTERNAL_SERVER_ERROR
                     },
-                    format!("Could not create server: {error}"),
+                    format!("Could not create device: {error}"),
                 ))
                 .into();
             }
-        },
-        None => None,
-    };
-    app_values.wireguard_data.server.clone_from(&server);
-    match data_manager::save_json_file(&app_values.wireguard_data) {
-        Ok(_) => (StatusCode::OK, Json(server)).into_response(),
+        };
+        device.user = Some(name.to_owned());
+        device.expires_at = user.expires_at;
+        let errors = validation::validate_client(&device, &app_values.wireguard_data);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        app_values.wireguard_data.clients.push(device.clone());
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        audit(
+            "user.device_added",
+            "admin",
+            Some(format!("client:{}", device.uuid)),
+            Some(format!("user:{name}")),
+        );
+        (
+            StatusCode::OK,
+            [(ETAG, etag(device.revision))],
+            Json(device),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn delete_wireguard_user_device(
+    State(state): State<AppState>,
+    Path((name, uuid)): Path<(String, Uuid)>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        if app_values.wireguard_data.get_user(&name).is_none() {
+            return user_not_found(&name);
+        }
+        let client_index = match find_client_index(app_values, &uuid) {
+            Ok(index) if app_values.wireguard_data.clients[index].user.as_ref() == Some(&name) => {
+                index
+            }
+            _ => {
+                return ErrorResponse::from((
+                    StatusCode::NOT_FOUND,
+                    format!("Device {uuid} of user {name} not found"),
+                ))
+                .into()
+            }
+        };
+        app_values.wireguard_data.clients.remove(client_index);
+        app_values.wireguard_data.increment_revision();
+        if let Err(error) = save_and_refresh_firewall(app_values) {
+            return error.into();
+        }
+        if let Err(error) = apply_to_interface(app_values) {
+            return error.into();
+        }
+        audit(
+            "user.device_removed",
+            "admin",
+            Some(format!("client:{uuid}")),
+            Some(format!("user:{name}")),
+        );
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+fn user_not_found(name: &String) -> Response<Body> {
+    ErrorResponse::from((StatusCode::NOT_FOUND, format!("User {} not found", name))).into()
+}
+
+async fn get_access_policy(State(state): State<AppState>) -> impl IntoResponse {
+    let app_values = state.snapshot();
+    (
+        StatusCode::OK,
+        [(ETAG, etag(app_values.wireguard_data.revision))],
+        Json(app_values.wireguard_data.access_policy.clone()),
+    )
+}
+
+async fn put_access_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<AccessPolicy>,
+) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = check_if_match(&headers, app_values.wireguard_data.revision) {
+            return error.into();
+        }
+        let errors = validation::validate_access_policy(&body, &app_values.wireguard_data);
+        if !errors.is_empty() {
+            return ErrorResponse::from(errors).into();
+        }
+        // the rules are applied from a copy, so a policy the firewall rejects is never stored
+        let mut wireguard_data = app_values.wireguard_data.clone();
+        wireguard_data.access_policy = body;
+        wireguard_data.increment_revision();
+        if let Err(error) = firewall::refresh_firewall(&app_values.config, &wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not apply firewall rules: {error}"),
+            ))
+            .into();
+        }
+        if let Err(error) = data_manager::save_json_file(&wireguard_data) {
+            // puts the rules of the policy that is still stored back in place
+            if let Err(error) =
+                firewall::refresh_firewall(&app_values.config, &app_values.wireguard_data)
+            {
+                error!("Could not restore the previous firewall rules: {error}");
+            }
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save data: {error}"),
+            ))
+            .into();
+        }
+        app_values.wireguard_data = wireguard_data;
+        (
+            StatusCode::OK,
+            [(ETAG, etag(app_values.wireguard_data.revision))],
+            Json(app_values.wireguard_data.access_policy.clone()),
+        )
+            .into_response()
+    })
+    .await
+}
+
+async fn get_wireguard_peers(
+    State(state): State<AppState>,
+    Query(query): Query<ClientQuery>,
+) -> Response<Body> {
+    let app_values = state.snapshot();
+    match run_blocking(move || wireguard::get_peers(&app_values)).await {
+        Ok(peers) => {
+            let page = query.apply(&peers, WireGuardPeer::query_item);
+            (
+                StatusCode::OK,
+                [(TOTAL_COUNT_HEADER, page.total)],
+                Json(page.items),
+            )
+                .into_response()
+        }
         Err(error) => ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not save data: {error}"),
+            format!("Could not get peers: {error}"),
         ))
         .into(),
     }
 }
 
-async fn delete_wireguard_server(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> impl IntoResponse {
-    let mut app_values = app_values.lock().unwrap();
-    app_values.wireguard_data.server = None;
-    match data_manager::save_json_file(&app_values.wireguard_data) {
-        Ok(_) => (StatusCode::OK, String::new()).into_response(),
+async fn get_healthz() -> Response<Body> {
+    (StatusCode::OK, Json(Liveness { status: "ok" })).into_response()
+}
+
+// answers 503 until every check passes so that a load balancer can take the panel out of rotation
+async fn get_readyz(State(state): State<AppState>) -> Response<Body> {
+    let app_values = state.snapshot();
+    match run_blocking(move || Ok(diagnostics::check_readiness(&app_values))).await {
+        Ok(report) => {
+            let status = if report.ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            (status, Json(report)).into_response()
+        }
         Err(error) => ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not save data: {error}"),
+            format!("Could not check readiness: {error}"),
         ))
         .into(),
     }
 }
 
-async fn get_wireguard_clients(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> impl IntoResponse {
-    (
-        StatusCode::OK,
-        Json(app_values.lock().unwrap().wireguard_data.clients.clone()),
-    )
-}
-
-async fn put_wireguard_clients(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-    Json(body): Json<Vec<WireGuardClientData>>,
-) -> impl IntoResponse {
-    let mut app_values = app_values.lock().unwrap();
-    app_values.wireguard_data.clients = body;
-    match data_manager::save_json_file(&app_values.wireguard_data) {
-        Ok(_) => (StatusCode::OK, String::new()).into_response(),
+async fn get_diagnostics(State(state): State<AppState>) -> Response<Body> {
+    let app_values = state.snapshot();
+    match run_blocking(move || Ok(diagnostics::diagnose(&app_values))).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
         Err(error) => ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not save data: {error}"),
+            format!("Could not run diagnostics: {error}"),
         ))
         .into(),
     }
 }
 
-async fn get_wireguard_client(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-    Path(uuid): Path<Uuid>,
-) -> Response<Body> {
-    let app_values = app_values.lock().unwrap();
-    match app_values.wireguard_data.get_client_config(&uuid) {
-        Some(client) => (StatusCode::OK, Json(client)).into_response(),
-        None => ErrorResponse::from((
-            StatusCode::NOT_FOUND,
-            format!("Client config for uuid {} not found", uuid),
+async fn get_preflight(State(state): State<AppState>) -> Response<Body> {
+    let app_values = state.snapshot();
+    match run_blocking(move || {
+        Ok(preflight::run_preflight(
+            &app_values.config,
+            &app_values.wireguard_data,
+            Some(app_values.backend.as_ref()),
+        ))
+    })
+    .await
+    {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(error) => ErrorResponse::from((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Could not run preflight checks: {error}"),
         ))
         .into(),
     }
 }
 
-async fn put_wireguard_client(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-    Path(uuid): Path<Uuid>,
-    Json(body): Json<WireGuardClientData>,
-) -> Response<Body> {
-    let mut app_values = app_values.lock().unwrap();
-    let client_index = app_values
-        .wireguard_data
-        .clients
-        .iter()
-        .position(|client| client.uuid == uuid);
-    match client_index {
-        Some(index) => app_values.wireguard_data.clients[index] = body,
-        None => {
-            return ErrorResponse::from((
-                StatusCode::NOT_FOUND,
-                format!("Client config for uuid {} not found", uuid),
-            ))
-            .into()
+// answers with the checks after the fix so that the caller sees whether it took effect
+async fn post_preflight_fix_sysctls(State(state): State<AppState>) -> Response<Body> {
+    let app_values = state.snapshot();
+    match run_blocking(move || {
+        preflight::fix_sysctls(&app_values.wireguard_data)?;
+        Ok(preflight::run_preflight(
+            &app_values.config,
+            &app_values.wireguard_data,
+            Some(app_values.backend.as_ref()),
+        ))
+    })
+    .await
+    {
+        Ok(report) => {
+            audit("preflight.sysctls_fixed", "admin", None, None);
+            (StatusCode::OK, Json(report)).into_response()
         }
-    }
-
-    match data_manager::save_json_file(&app_values.wireguard_data) {
-        Ok(_) => (StatusCode::OK, String::new()).into_response(),
         Err(error) => ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not save data: {error}"),
+            format!("Could not fix sysctls: {error}"),
         ))
         .into(),
     }
 }
 
-async fn post_wireguard_clients(
-    State(app_values_arc): State<Arc<Mutex<WireGuardAppValues>>>,
-    Json(body): Json<WireGuardOptionalClientData>,
-) -> Response<Body> {
-    let mut app_values = app_values_arc.lock().unwrap();
-    let new_client = match body.to_wireguard_client_data(None, &app_values) {
-        Ok(client) => client,
-        Err(error) => {
+async fn wireguard_restart(State(state): State<AppState>) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) =
+            data_manager::save_wireguard_config(&app_values.wireguard_data, &app_values.config)
+        {
             return ErrorResponse::from((
-                if let AppError::RestAPI(_) = error {
-                    StatusCode::BAD_REQUEST
-                } else {
-                    StatusCode::INTERNAL_SERVER_ERROR
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save config: {error}"),
+            ))
+            .into();
+        };
+        if let Err(error) =
+            wireguard::restart_wireguard(app_values.backend.as_ref(), &app_values.wireguard_data)
+        {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                match error {
+                    RestartWireGuardErrorType::StopFailed(err) => {
+                        format!("{}: {}", "Could not stop WireGuard", err)
+                    }
+                    RestartWireGuardErrorType::StartFailed(err) => {
+                        format!("{}: {}", "Could not start WireGuard", err)
+                    }
                 },
-                format!("Could not create client: {error}"),
             ))
             .into();
         }
+        if let Err(error) = firewall::apply_firewall(&app_values.config, &app_values.wireguard_data)
+        {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not apply firewall rules: {error}"),
+            ))
+            .into();
+        }
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+async fn wireguard_reload(State(state): State<AppState>) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) =
+            data_manager::save_wireguard_config(&app_values.wireguard_data, &app_values.config)
+        {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not save config: {error}"),
+            ))
+            .into();
+        };
+        if let Err(error) = app_values.backend.reload(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("{}: {}", "Could not reload WireGuard", error),
+            ))
+            .into();
+        };
+        if let Err(error) = firewall::apply_firewall(&app_values.config, &app_values.wireguard_data)
+        {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not apply firewall rules: {error}"),
+            ))
+            .into();
+        }
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+async fn wireguard_start(State(state): State<AppState>) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = app_values.backend.start(&app_values.wireguard_data) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not start WireGuard: {error}"),
+            ))
+            .into();
+        }
+        if let Err(error) = firewall::apply_firewall(&app_values.config, &app_values.wireguard_data)
+        {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not apply firewall rules: {error}"),
+            ))
+            .into();
+        }
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+async fn wireguard_stop(State(state): State<AppState>) -> Response<Body> {
+    write(&state, move |app_values| {
+        if let Err(error) = app_values.backend.stop() {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not stop WireGuard: {error}"),
+            ))
+            .into();
+        }
+        if let Err(error) = firewall::remove_firewall(&app_values.config) {
+            return ErrorResponse::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Could not remove firewall rules: {error}"),
+            ))
+            .into();
+        }
+        (StatusCode::OK, String::new()).into_response()
+    })
+    .await
+}
+
+// runs a change on the state writer, see AppState
+// a change that ends in an error response is rolled back, and what it already wrote out is
+// put back to the previous state, so a failed request never becomes live on the next save
+async fn write(
+    state: &AppState,
+    job: impl FnOnce(&mut WireGuardAppValues) -> Response<Body> + Send + 'static,
+) -> Response<Body> {
+    let job = move |app_values: &mut WireGuardAppValues| {
+        let previous = app_values.clone();
+        let response = job(app_values);
+        if response.status().is_client_error() || response.status().is_server_error() {
+            let changed = app_values.wireguard_data.revision != previous.wireguard_data.revision;
+            *app_values = previous;
+            if changed && response.status().is_server_error() {
+                restore_side_effects(app_values);
+            }
+        }
+        response
     };
-    if app_values
-        .wireguard_data
-        .clients
-        .iter()
-        .any(|client| client.uuid == new_client.uuid)
+    match state.write(job).await {
+        Ok(response) => response,
+        Err(error) => {
+            ErrorResponse::from((StatusCode::INTERNAL_SERVER_ERROR, error.to_string())).into()
+        }
+    }
+}
+
+// netlink reads of the interface block, so they run next to the runtime instead of on it
+async fn run_blocking<T: Send + 'static>(
+    read: impl FnOnce() -> Result<T, AppError> + Send + 'static,
+) -> Result<T, AppError> {
+    tokio::task::spawn_blocking(read)
+        .await
+        .map_err(|error| AppError::StateWriter(error.to_string()))?
+}
+
+fn etag(revision: u64) -> String {
+    format!("\"{revision}\"")
+}
+
+// a missing If-Match header always passes, otherwise one of the listed tags has to match
+fn check_if_match(headers: &HeaderMap, revision: u64) -> Result<(), ErrorResponse> {
+    let if_match = match headers.get(IF_MATCH) {
+        Some(if_match) => if_match.to_str().unwrap_or_default(),
+        None => return Ok(()),
+    };
+    let current = etag(revision);
+    if if_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == current)
     {
-        return ErrorResponse::from((
-            StatusCode::CONFLICT,
-            format!("Client with uuid {} already exists", new_client.uuid),
-        ))
-        .into();
+        return Ok(());
     }
-    app_values.wireguard_data.clients.push(new_client.clone());
+    Err(ErrorResponse::from((
+        StatusCode::PRECONDITION_FAILED,
+        format!("Resource has been modified, current ETag is {current}"),
+    )))
+}
 
-    match data_manager::save_json_file(&app_values.wireguard_data) {
-        Ok(_) => (StatusCode::OK, Json(new_client)).into_response(),
-        Err(error) => ErrorResponse::from((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not save data: {error}"),
-        ))
-        .into(),
+fn find_usable_invitation(
+    app_values: &WireGuardAppValues,
+    token: &str,
+) -> Result<usize, ErrorResponse> {
+    let index = app_values
+        .wireguard_data
+        .find_invitation(token)
+        .ok_or_else(|| {
+            ErrorResponse::from((StatusCode::NOT_FOUND, "Invitation not found".to_string()))
+        })?;
+    let invitation = &app_values.wireguard_data.invitations[index];
+    if invitation.redeemed_at.is_some() {
+        return Err(ErrorResponse::from((
+            StatusCode::GONE,
+            "Invitation has already been used".to_string(),
+        )));
+    }
+    if invitation.is_expired() {
+        return Err(ErrorResponse::from((
+            StatusCode::GONE,
+            "Invitation has expired".to_string(),
+        )));
     }
+    Ok(index)
 }
 
-async fn get_wireguard_peers(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> Response<Body> {
-    match wireguard::get_peers(app_values.clone()) {
-        Ok(peers) => (StatusCode::OK, Json(peers)).into_response(),
-        Err(error) => ErrorResponse::from((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not get peers: {error}"),
-        ))
-        .into(),
+fn config_response(config: RenderedConfig) -> Response<Body> {
+    (
+        StatusCode::OK,
+        [
+            (CONTENT_TYPE, config.content_type.to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", config.file_name),
+            ),
+        ],
+        config.body,
+    )
+        .into_response()
+}
+
+// a failed audit write should not undo an action that already happened
+fn audit(action: &str, actor: &str, target: Option<String>, details: Option<String>) {
+    if let Err(error) = audit::record(action, actor, target, details) {
+        warn!("Could not write audit event {action}: {error}");
     }
 }
 
-async fn wireguard_restart(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> Response<Body> {
-    let app_values = app_values.lock().unwrap();
-    if let Err(error) =
-        data_manager::save_wireguard_config(&app_values.wireguard_data, &app_values.config)
-    {
-        return ErrorResponse::from((
+fn find_client_index(app_values: &WireGuardAppValues, uuid: &Uuid) -> Result<usize, ErrorResponse> {
+    app_values
+        .wireguard_data
+        .clients
+        .iter()
+        .position(|client| &client.uuid == uuid)
+        .ok_or_else(|| {
+            ErrorResponse::from((
+                StatusCode::NOT_FOUND,
+                format!("Client config for uuid {} not found", uuid),
+            ))
+        })
+}
+
+fn save_and_refresh_firewall(app_values: &WireGuardAppValues) -> Result<(), ErrorResponse> {
+    if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+        return Err(ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not save config: {error}"),
-        ))
-        .into();
-    };
-    if let Err(error) = wireguard::restart_wireguard(&app_values.config.wireguard_interface) {
-        return ErrorResponse::from((
+            format!("Could not save data: {error}"),
+        )));
+    }
+    if let Err(error) = firewall::refresh_firewall(&app_values.config, &app_values.wireguard_data) {
+        return Err(ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
-            match error {
-                RestartWireGuardErrorType::StopFailed(err) => {
-                    format!("{}: {}", "Could not stop WireGuard", err)
-                }
-                RestartWireGuardErrorType::StartFailed(err) => {
-                    format!("{}: {}", "Could not start WireGuard", err)
-                }
-            },
-        ))
-        .into();
+            format!("Could not apply firewall rules: {error}"),
+        )));
     }
-    (StatusCode::OK, String::new()).into_response()
+    Ok(())
 }
 
-async fn wireguard_reload(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> Response<Body> {
-    let app_values = app_values.lock().unwrap();
+// writes the data, firewall rules and peers of a rolled back state out again
+pub fn restore_side_effects(app_values: &WireGuardAppValues) {
+    if let Err(error) = data_manager::save_json_file(&app_values.wireguard_data) {
+        error!("Could not restore the previous data: {error}");
+    }
+    if let Err(error) = firewall::refresh_firewall(&app_values.config, &app_values.wireguard_data) {
+        error!("Could not restore the previous firewall rules: {error}");
+    }
+    if let Err(error) = apply_to_interface(app_values) {
+        error!(
+            "Could not restore the previous peers: {}",
+            error.error.message
+        );
+    }
+}
+
+// pushes the saved data to a running interface, does nothing while it is down
+fn apply_to_interface(app_values: &WireGuardAppValues) -> Result<(), ErrorResponse> {
+    if !app_values.backend.is_up() {
+        return Ok(());
+    }
     if let Err(error) =
         data_manager::save_wireguard_config(&app_values.wireguard_data, &app_values.config)
     {
-        return ErrorResponse::from((
+        return Err(ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Could not save config: {error}"),
-        ))
-        .into();
-    };
-    if let Err(error) = wireguard::reload_wireguard(&app_values.config.wireguard_interface) {
-        return ErrorResponse::from((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("{}: {}", "Could not reload WireGuard", error),
-        ))
-        .into();
-    };
-    (StatusCode::OK, String::new()).into_response()
-}
-
-async fn wireguard_start(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> Response<Body> {
-    let app_values = app_values.lock().unwrap();
-    if let Err(error) = wireguard::start_wireguard(&app_values.config.wireguard_interface) {
-        return ErrorResponse::from((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not start WireGuard: {error}"),
-        ))
-        .into();
+        )));
     }
-    (StatusCode::OK, String::new()).into_response()
-}
-
-async fn wireguard_stop(
-    State(app_values): State<Arc<Mutex<WireGuardAppValues>>>,
-) -> Response<Body> {
-    let app_values = app_values.lock().unwrap();
-    if let Err(error) = wireguard::stop_wireguard(&app_values.config.wireguard_interface) {
-        return ErrorResponse::from((
+    if let Err(error) = app_values.backend.reload(&app_values.wireguard_data) {
+        return Err(ErrorResponse::from((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Could not stop WireGuard: {error}"),
-        ))
-        .into();
+            format!("Could not reload WireGuard: {error}"),
+        )));
     }
-    (StatusCode::OK, String::new()).into_response()
+    Ok(())
 }
 
 async fn sample() -> impl IntoResponse {
@@ -366,9 +2796,9 @@ async fn sample() -> impl IntoResponse {
                     listen_port: Some(51820),
                     private_key: Some("oL5cNL2cZQVNLYEfg4LIEEfS6KaFN1YSmOlq5rRJjlI=".to_string()),
                     pre_up: None,
-                    post_up: Some("iptables -A FORWARD -i {WIREGUARD_INTERFACE} -j ACCEPT; iptables -t nat -A POSTROUTING -o {NETWORK_INTERFACE} -j MASQUERADE".into()),
+                    post_up: None,
                     pre_down: None,
-                    post_down: Some("iptables -D FORWARD -i {WIREGUARD_INTERFACE} -j ACCEPT; iptables -t nat -D POSTROUTING -o {NETWORK_INTERFACE} -j MASQUERADE".into()),
+                    post_down: None,
                     table: None,
                     mtu: None,
                 }),
@@ -377,14 +2807,20 @@ async fn sample() -> impl IntoResponse {
                         name: Some("Sample Client".into()),
                         uuid: Some(Uuid::new_v4()),
                         enabled: Some(true),
+                        group: None,
+                        tags: Some(vec![]),
+                        expires_at: None,
                         generate_preshared_key: Some(true),
                         preshared_key: Some("KS4xysNuixRcArtY/iNph8dQyhXv/W1rxc0QOiDlhzs=".into()),
                         server_allowed_ips: Some(vec!["10.8.0.2/32".into()]),
                         persistent_keep_alive: None,
                         private_key: Some("qD+418LUGssYC/V6ZHJQz2YQO8PCWv9gmX4QWtKEMHg=".to_string()),
+                        public_key: None,
                         address: Some("10.8.0.2/32".to_string()),
                         client_allowed_ips: Some(vec!["0.0.0.0/0".into()]),
                         dns: Some(vec![]),
+                        mtu: None,
+                        keys_rotated_at: None,
                     }
                 ],
             }
@@ -413,8 +2849,25 @@ impl From<(StatusCode, String)> for ErrorResponse {
     }
 }
 
+impl From<Vec<FieldError>> for ErrorResponse {
+    fn from(errors: Vec<FieldError>) -> Self {
+        ErrorResponse {
+            error: ErrorResponseData {
+                code: StatusCode::BAD_REQUEST.as_u16(),
+                message: RestAPIError::ValidationFailed(errors.clone()).to_string(),
+                errors,
+            },
+        }
+    }
+}
+
 impl IntoResponse for ErrorResponse {
     fn into_response(self) -> axum::response::Response {
+        if self.error.code >= 500 {
+            error!(code = self.error.code, "{}", self.error.message);
+        } else {
+            warn!(code = self.error.code, "{}", self.error.message);
+        }
         (StatusCode::from_u16(self.error.code).unwrap(), Json(self)).into_response()
     }
 }
@@ -429,6 +2882,8 @@ impl From<ErrorResponse> for Response<Body> {
 struct ErrorResponseData {
     pub code: u16,
     pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
 }
 
 impl From<(u16, String)> for ErrorResponseData {
@@ -436,6 +2891,7 @@ impl From<(u16, String)> for ErrorResponseData {
         ErrorResponseData {
             code: value.0,
             message: value.1,
+            errors: Vec::new(),
         }
     }
 }
@@ -445,6 +2901,7 @@ impl From<(StatusCode, String)> for ErrorResponseData {
         ErrorResponseData {
             code: value.0.as_u16(),
             message: value.1,
+            errors: Vec::new(),
         }
     }
 }
@@ -0,0 +1,72 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use tokio::sync::{oneshot, watch};
+
+use crate::error::AppError;
+use crate::WireGuardAppValues;
+
+// the reply is only sent once the change is published, so a caller always reads its own write
+type Job = Box<dyn FnOnce(&mut WireGuardAppValues) -> Reply + Send>;
+type Reply = Box<dyn FnOnce() + Send>;
+
+// reads use the latest published snapshot and never wait, changes run one after another on a
+// single writer thread that owns the state, which keeps file IO and wg-quick off the runtime
+#[derive(Clone)]
+pub struct AppState {
+    snapshot: watch::Receiver<Arc<WireGuardAppValues>>,
+    jobs: mpsc::Sender<Job>,
+}
+
+impl AppState {
+    pub fn new(app_values: WireGuardAppValues) -> Result<Self, AppError> {
+        let (publisher, snapshot) = watch::channel(Arc::new(app_values.clone()));
+        let (jobs, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("state-writer".to_string())
+            .spawn(move || run_writer(app_values, receiver, publisher))?;
+        Ok(AppState { snapshot, jobs })
+    }
+
+    pub fn snapshot(&self) -> Arc<WireGuardAppValues> {
+        self.snapshot.borrow().clone()
+    }
+
+    pub async fn write<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut WireGuardAppValues) -> T + Send + 'static,
+    ) -> Result<T, AppError> {
+        let (sender, receiver) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |app_values| {
+                let result = job(app_values);
+                Box::new(move || {
+                    let _ = sender.send(result);
+                })
+            }))
+            .map_err(|_| AppError::StateWriter("the writer has stopped".to_string()))?;
+        receiver.await.map_err(|_| {
+            AppError::StateWriter("the change panicked and was rolled back".to_string())
+        })
+    }
+}
+
+fn run_writer(
+    mut app_values: WireGuardAppValues,
+    receiver: mpsc::Receiver<Job>,
+    publisher: watch::Sender<Arc<WireGuardAppValues>>,
+) {
+    for job in receiver {
+        // a panicking change leaves the last published state in place instead of poisoning it
+        let reply = match catch_unwind(AssertUnwindSafe(|| job(&mut app_values))) {
+            Ok(reply) => reply,
+            Err(_) => {
+                app_values = publisher.borrow().as_ref().clone();
+                continue;
+            }
+        };
+        publisher.send_replace(Arc::new(app_values.clone()));
+        reply();
+    }
+}
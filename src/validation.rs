@@ -0,0 +1,429 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use serde::Serialize;
+use wireguard_keys::Privkey;
+
+use crate::data::access_policy::{AccessPolicy, AccessTarget};
+use crate::data::wireguard_client::WireGuardClientData;
+use crate::data::wireguard_data::WireGuardData;
+use crate::data::wireguard_group::WireGuardGroupData;
+use crate::data::wireguard_server::WireGuardServerData;
+
+const KEY_LENGTH: usize = 32;
+
+// an address or CIDR that has been parsed, it is rendered back in canonical form so that the
+// raw input never ends up in a firewall command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    pub ip: IpAddr,
+    pub prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn parse(value: &str) -> Option<IpNetwork> {
+        parse_network(value).map(|(ip, prefix)| IpNetwork { ip, prefix })
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        self.ip.is_ipv6()
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}/{}", self.ip, self.prefix)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+pub fn validate_server(server: &WireGuardServerData) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if let Err(message) = parse_endpoint(&server.endpoint) {
+        errors.push(FieldError::new("endpoint", message));
+    }
+    if server.address.is_empty() {
+        errors.push(FieldError::new(
+            "address",
+            "At least one address is required",
+        ));
+    }
+    for (index, address) in server.address.iter().enumerate() {
+        if parse_network(address).is_none() {
+            errors.push(FieldError::new(
+                format!("address[{index}]"),
+                format!("'{address}' is not a valid IP address or CIDR"),
+            ));
+        }
+    }
+    validate_dns(&server.dns, "dns", &mut errors);
+    if server.listen_port == 0 {
+        errors.push(FieldError::new(
+            "listen_port",
+            "Port must be between 1 and 65535",
+        ));
+    }
+    validate_key(&server.private_key, "private_key", &mut errors);
+    validate_key(&server.public_key, "public_key", &mut errors);
+    errors
+}
+
+// checks a single client, including conflicts with every other client in `data`
+pub fn validate_client(client: &WireGuardClientData, data: &WireGuardData) -> Vec<FieldError> {
+    let others: Vec<&WireGuardClientData> = data
+        .clients
+        .iter()
+        .filter(|other| other.uuid != client.uuid)
+        .collect();
+    validate_client_against(client, &others, data.server.as_ref(), "")
+}
+
+// checks a full replacement client list against itself
+pub fn validate_clients(
+    clients: &[WireGuardClientData],
+    server: Option<&WireGuardServerData>,
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    for (index, client) in clients.iter().enumerate() {
+        let others: Vec<&WireGuardClientData> = clients
+            .iter()
+            .enumerate()
+            .filter(|(other_index, _)| *other_index != index)
+            .map(|(_, other)| other)
+            .collect();
+        errors.extend(validate_client_against(
+            client,
+            &others,
+            server,
+            &format!("[{index}]."),
+        ));
+    }
+    errors
+}
+
+fn validate_client_against(
+    client: &WireGuardClientData,
+    others: &[&WireGuardClientData],
+    server: Option<&WireGuardServerData>,
+    prefix: &str,
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let field = |name: &str| format!("{prefix}{name}");
+
+    if client.name.trim().is_empty() {
+        errors.push(FieldError::new(field("name"), "Name must not be empty"));
+    }
+    if others.iter().any(|other| other.uuid == client.uuid) {
+        errors.push(FieldError::new(
+            field("uuid"),
+            format!("UUID {} is used by another client", client.uuid),
+        ));
+    }
+
+    validate_key(&client.public_key, &field("public_key"), &mut errors);
+    if let Some(private_key) = &client.private_key {
+        validate_key(private_key, &field("private_key"), &mut errors);
+        // a full replacement sends both, the server would never see a handshake from the client
+        if let Ok(private_key) = Privkey::parse(private_key) {
+            if private_key.pubkey().to_base64() != client.public_key {
+                errors.push(FieldError::new(
+                    field("public_key"),
+                    "Public key does not belong to the private key",
+                ));
+            }
+        }
+    }
+    if let Some(preshared_key) = &client.preshared_key {
+        validate_key(preshared_key, &field("preshared_key"), &mut errors);
+    }
+    if let Some(other) = others
+        .iter()
+        .find(|other| other.public_key == client.public_key)
+    {
+        errors.push(FieldError::new(
+            field("public_key"),
+            format!("Public key is already used by client '{}'", other.name),
+        ));
+    }
+
+    match parse_network(&client.address) {
+        None => errors.push(FieldError::new(
+            field("address"),
+            format!("'{}' is not a valid IP address or CIDR", client.address),
+        )),
+        Some((ip, _)) => {
+            if let Some(other) = others.iter().find(|other| {
+                parse_network(&other.address).is_some_and(|(other_ip, _)| other_ip == ip)
+            }) {
+                errors.push(FieldError::new(
+                    field("address"),
+                    format!("Address {ip} is already used by client '{}'", other.name),
+                ));
+            }
+            let server_networks: Vec<(IpAddr, u8)> = server
+                .map(|server| server.address.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|address| server_network(address))
+                .collect();
+            let host_prefix = if ip.is_ipv4() { 32 } else { 128 };
+            if server_networks
+                .iter()
+                .any(|(server_ip, _)| *server_ip == ip)
+            {
+                errors.push(FieldError::new(
+                    field("address"),
+                    format!("Address {ip} is used by the server"),
+                ));
+            } else if !server_networks.is_empty()
+                && !server_networks
+                    .iter()
+                    .any(|network| network_contains(*network, (ip, host_prefix)))
+            {
+                errors.push(FieldError::new(
+                    field("address"),
+                    format!("Address {ip} is outside the server network"),
+                ));
+            }
+        }
+    }
+
+    for (index, allowed_ip) in client.server_allowed_ips.iter().enumerate() {
+        let network = match parse_network(allowed_ip) {
+            Some(network) => network,
+            None => {
+                errors.push(FieldError::new(
+                    field(&format!("server_allowed_ips[{index}]")),
+                    format!("'{allowed_ip}' is not a valid IP address or CIDR"),
+                ));
+                continue;
+            }
+        };
+        let overlapping = others.iter().find(|other| {
+            other
+                .server_allowed_ips
+                .iter()
+                .filter_map(|other_ip| parse_network(other_ip))
+                .any(|other_network| networks_overlap(network, other_network))
+        });
+        if let Some(other) = overlapping {
+            errors.push(FieldError::new(
+                field(&format!("server_allowed_ips[{index}]")),
+                format!(
+                    "'{allowed_ip}' overlaps with the allowed IPs of client '{}'",
+                    other.name
+                ),
+            ));
+        }
+    }
+
+    validate_networks(
+        &client.client_allowed_ips,
+        &field("client_allowed_ips"),
+        &mut errors,
+    );
+    validate_dns(&client.dns, &field("dns"), &mut errors);
+    errors
+}
+
+// group defaults end up in the configs of their clients, so they are checked the same way
+pub fn validate_group(group: &WireGuardGroupData) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if group.name.trim().is_empty() {
+        errors.push(FieldError::new("name", "Name must not be empty"));
+    }
+    if let Some(client_allowed_ips) = &group.client_allowed_ips {
+        validate_networks(client_allowed_ips, "client_allowed_ips", &mut errors);
+    }
+    if let Some(dns) = &group.dns {
+        validate_dns(dns, "dns", &mut errors);
+    }
+    errors
+}
+
+// rules only open up traffic between peers that isolation would otherwise drop, everything else
+// leaving the tunnel is already forwarded, so targets outside the peer networks are rejected
+pub fn validate_access_policy(policy: &AccessPolicy, data: &WireGuardData) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let peer_networks = server_networks(data);
+    for (index, rule) in policy.rules.iter().enumerate() {
+        for (name, target) in [("source", &rule.source), ("destination", &rule.destination)] {
+            let field = format!("rules[{index}].{name}");
+            match target {
+                AccessTarget::Client(uuid) => {
+                    if !data.clients.iter().any(|client| &client.uuid == uuid) {
+                        errors.push(FieldError::new(
+                            field,
+                            format!("Client {uuid} does not exist"),
+                        ));
+                    }
+                }
+                AccessTarget::Group(group) => {
+                    if data.get_group(group).is_none()
+                        && !data
+                            .clients
+                            .iter()
+                            .any(|client| client.group.as_ref() == Some(group))
+                    {
+                        errors.push(FieldError::new(
+                            field,
+                            format!("Group '{group}' does not exist"),
+                        ));
+                    }
+                }
+                AccessTarget::Cidr(cidr) => match parse_network(cidr) {
+                    None => errors.push(FieldError::new(
+                        field,
+                        format!("'{cidr}' is not a valid IP address or CIDR"),
+                    )),
+                    Some(network) => {
+                        if !peer_networks
+                            .iter()
+                            .any(|peer_network| network_contains(*peer_network, network))
+                        {
+                            errors.push(FieldError::new(
+                                field,
+                                format!(
+                                    "'{cidr}' is outside the server network, rules only apply to traffic between clients"
+                                ),
+                            ));
+                        }
+                    }
+                },
+            }
+        }
+    }
+    errors
+}
+
+fn validate_key(key: &str, field: &str, errors: &mut Vec<FieldError>) {
+    match BASE64_STANDARD.decode(key) {
+        Ok(bytes) if bytes.len() == KEY_LENGTH => {}
+        Ok(bytes) => errors.push(FieldError::new(
+            field,
+            format!("Key must be {KEY_LENGTH} bytes, got {}", bytes.len()),
+        )),
+        Err(_) => errors.push(FieldError::new(field, "Key is not valid base64")),
+    }
+}
+
+fn validate_networks(networks: &[String], field: &str, errors: &mut Vec<FieldError>) {
+    for (index, network) in networks.iter().enumerate() {
+        if parse_network(network).is_none() {
+            errors.push(FieldError::new(
+                format!("{field}[{index}]"),
+                format!("'{network}' is not a valid IP address or CIDR"),
+            ));
+        }
+    }
+}
+
+fn validate_dns(dns: &[String], field: &str, errors: &mut Vec<FieldError>) {
+    for (index, server) in dns.iter().enumerate() {
+        if IpAddr::from_str(server).is_err() && !is_valid_hostname(server) {
+            errors.push(FieldError::new(
+                format!("{field}[{index}]"),
+                format!("'{server}' is not a valid IP address or hostname"),
+            ));
+        }
+    }
+}
+
+// accepts "ip" or "ip/prefix", a bare ip is treated as a single host
+pub fn parse_network(value: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = match value.split_once('/') {
+        Some((ip, prefix)) => (IpAddr::from_str(ip).ok()?, Some(prefix.parse::<u8>().ok()?)),
+        None => (IpAddr::from_str(value).ok()?, None),
+    };
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max_prefix);
+    (prefix <= max_prefix).then_some((ip, prefix))
+}
+
+// the network that client addresses are handed out of for one server address, a bare or
+// point-to-point address carries no subnet, so the usual /24 or /64 is assumed
+pub fn server_network(address: &str) -> Option<(IpAddr, u8)> {
+    parse_network(address).map(|(ip, prefix)| match ip {
+        IpAddr::V4(_) if prefix >= 31 => (ip, 24),
+        IpAddr::V6(_) if prefix >= 127 => (ip, 64),
+        _ => (ip, prefix),
+    })
+}
+
+// the networks that client addresses are handed out of
+pub fn server_networks(data: &WireGuardData) -> Vec<(IpAddr, u8)> {
+    data.server
+        .iter()
+        .flat_map(|server| &server.address)
+        .filter_map(|address| server_network(address))
+        .collect()
+}
+
+pub fn network_contains(network: (IpAddr, u8), inner: (IpAddr, u8)) -> bool {
+    inner.1 >= network.1 && networks_overlap(network, inner)
+}
+
+pub fn networks_overlap(a: (IpAddr, u8), b: (IpAddr, u8)) -> bool {
+    let prefix = a.1.min(b.1);
+    match (a.0, b.0) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(a) & mask == u32::from(b) & mask
+        }
+        (IpAddr::V6(a), IpAddr::V6(b)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(a) & mask == u128::from(b) & mask
+        }
+        _ => false,
+    }
+}
+
+fn parse_endpoint(endpoint: &str) -> Result<(), String> {
+    let (host, port) = endpoint
+        .rsplit_once(':')
+        .ok_or_else(|| format!("'{endpoint}' must be in the form host:port"))?;
+    match port.parse::<u16>() {
+        Ok(port) if port != 0 => {}
+        _ => return Err(format!("'{port}' is not a valid port")),
+    }
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if IpAddr::from_str(host).is_err() && !is_valid_hostname(host) {
+        return Err(format!("'{host}' is not a valid IP address or hostname"));
+    }
+    Ok(())
+}
+
+fn is_valid_hostname(hostname: &str) -> bool {
+    let hostname = hostname.strip_suffix('.').unwrap_or(hostname);
+    !hostname.is_empty()
+        && hostname.len() <= 253
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '-')
+        })
+}
@@ -1,20 +1,25 @@
-use std::io;
+use std::collections::HashMap;
 use std::process::Command;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use std::{env, fs, io};
 
 use defguard_wireguard_rs::key::Key;
-use defguard_wireguard_rs::WireguardInterfaceApi;
+use tracing::{debug, warn};
 
-use crate::data::wireguard_peer::WireGuardPeer;
+use crate::backend::WireGuardBackend;
+use crate::data::wireguard_data::WireGuardData;
+use crate::data::wireguard_peer::{PeerUsage, WireGuardPeer};
 use crate::error::AppError;
+use crate::validation::{network_contains, parse_network};
 use crate::WireGuardAppValues;
 
-pub fn get_peers(
-    app_values: Arc<Mutex<WireGuardAppValues>>,
-) -> Result<Vec<WireGuardPeer>, AppError> {
-    let app_values = app_values.lock().unwrap();
-    let raw_peers = &app_values.wg_api.read_interface_data()?.peers;
+// where wg-quick looks up the config of an interface given by name
+const WG_QUICK_CONFIG_DIR: &str = "/etc/wireguard";
+const USERSPACE_IMPLEMENTATION_VARIABLE: &str = "WG_QUICK_USERSPACE_IMPLEMENTATION";
+
+pub fn get_peers(app_values: &WireGuardAppValues) -> Result<Vec<WireGuardPeer>, AppError> {
+    let raw_peers = &app_values.backend.read_interface_data()?.peers;
     let mut peers = Vec::<WireGuardPeer>::new();
 
     for client in &app_values.wireguard_data.clients {
@@ -33,6 +38,10 @@ pub fn get_peers(
             peers.push(WireGuardPeer {
                 name: client.name.clone(),
                 uuid: client.uuid,
+                public_key: client.public_key.clone(),
+                enabled: client.enabled,
+                group: client.group.clone(),
+                tags: client.tags.clone(),
                 server_allowed_ips: raw_peer.allowed_ips.clone(),
                 address: client.address.clone(),
                 protocol_version: raw_peer.protocol_version,
@@ -48,43 +57,275 @@ pub fn get_peers(
     Ok(peers)
 }
 
-pub fn restart_wireguard(interface: &String) -> Result<(), RestartWireGuardErrorType> {
-    if let Err(error) = stop_wireguard(interface) {
+pub fn get_last_handshakes(
+    backend: &dyn WireGuardBackend,
+) -> Result<HashMap<String, SystemTime>, AppError> {
+    Ok(backend
+        .read_interface_data()?
+        .peers
+        .into_values()
+        .filter_map(|peer| {
+            peer.last_handshake
+                .map(|last_handshake| (peer.public_key.to_string(), last_handshake))
+        })
+        .collect())
+}
+
+pub fn get_peer_usage(
+    backend: &dyn WireGuardBackend,
+) -> Result<HashMap<String, PeerUsage>, AppError> {
+    Ok(backend
+        .read_interface_data()?
+        .peers
+        .into_values()
+        .map(|peer| {
+            (
+                peer.public_key.to_string(),
+                PeerUsage {
+                    transmitted_bytes: peer.tx_bytes,
+                    received_bytes: peer.rx_bytes,
+                    last_handshake: peer.last_handshake,
+                },
+            )
+        })
+        .collect())
+}
+
+pub fn get_listen_port(backend: &dyn WireGuardBackend) -> Result<u16, AppError> {
+    Ok(backend.read_interface_data()?.listen_port)
+}
+
+pub fn restart_wireguard(
+    backend: &dyn WireGuardBackend,
+    data: &WireGuardData,
+) -> Result<(), RestartWireGuardErrorType> {
+    if let Err(error) = backend.stop() {
         return Err(RestartWireGuardErrorType::StopFailed(error));
     };
-    match start_wireguard(interface) {
+    match backend.start(data) {
         Err(error) => Err(RestartWireGuardErrorType::StartFailed(error)),
         _ => Ok(()),
     }
 }
 
-pub fn reload_wireguard(interface: &String) -> Result<(), io::Error> {
-    match Command::new("sudo")
-        .arg("systemctl")
-        .arg("reload")
-        .arg(format!("wg-quick@{}", interface))
-        .status()
-    {
-        Err(error) => Err(error),
-        Ok(_) => Ok(())
+pub fn start_wireguard(interface: &String) -> Result<(), io::Error> {
+    run_logged(Command::new("wg-quick").arg("up").arg(interface))
+}
+
+// wg-quick only falls back to a userspace implementation when `ip link add` fails, which loads the
+// kernel module wherever it is installed, so the interface is brought up the way wg-quick does it
+// from the same config file, with the userspace implementation from the start
+pub fn start_wireguard_userspace(interface: &str) -> Result<(), io::Error> {
+    let config = fs::read_to_string(format!("{WG_QUICK_CONFIG_DIR}/{interface}.conf"))?;
+    let settings = InterfaceSettings::parse(&config);
+    for hook in &settings.pre_up {
+        run_hook(hook, interface)?;
+    }
+    let implementation =
+        env::var(USERSPACE_IMPLEMENTATION_VARIABLE).unwrap_or_else(|_| "wireguard-go".to_string());
+    run_logged(Command::new(implementation).arg(interface))?;
+    if let Err(error) = configure_userspace_interface(interface, &settings) {
+        if let Err(error) =
+            run_logged(Command::new("ip").args(["link", "delete", "dev", interface]))
+        {
+            warn!("Could not remove the half configured interface {interface}: {error}");
+        }
+        return Err(error);
     }
+    Ok(())
 }
 
-pub fn start_wireguard(interface: &String) -> Result<(), io::Error> {
-    match Command::new("wg-quick").arg("up").arg(interface).output() {
-        Err(error) => Err(error),
-        _ => Ok(()),
+fn configure_userspace_interface(
+    interface: &str,
+    settings: &InterfaceSettings,
+) -> Result<(), io::Error> {
+    run_logged(Command::new("bash").args([
+        "-c",
+        r#"wg setconf "$1" <(wg-quick strip "$1")"#,
+        "bash",
+        interface,
+    ]))?;
+    for address in &settings.addresses {
+        run_logged(Command::new("ip").args([
+            ip_family(address),
+            "address",
+            "add",
+            address,
+            "dev",
+            interface,
+        ]))?;
+    }
+    let mut link = Command::new("ip");
+    link.args(["link", "set"]);
+    if let Some(mtu) = &settings.mtu {
+        link.args(["mtu", mtu]);
+    }
+    run_logged(link.args(["up", "dev", interface]))?;
+
+    // like wg-quick, routes to what the peers route through the tunnel unless Table is off
+    let table = settings.table.as_deref().unwrap_or("auto");
+    if table != "off" {
+        let own_networks: Vec<_> = settings
+            .addresses
+            .iter()
+            .filter_map(|address| parse_network(address))
+            .collect();
+        for allowed_ip in &settings.allowed_ips {
+            let network = match parse_network(allowed_ip) {
+                Some(network) => network,
+                None => continue,
+            };
+            if own_networks
+                .iter()
+                .any(|own_network| network_contains(*own_network, network))
+            {
+                continue;
+            }
+            // wg-quick sets up policy routing for these, which a server interface has no use for
+            if network.1 == 0 {
+                warn!("Not routing {allowed_ip} through {interface} in userspace mode");
+                continue;
+            }
+            let mut route = Command::new("ip");
+            route.args([
+                ip_family(allowed_ip),
+                "route",
+                "replace",
+                allowed_ip,
+                "dev",
+                interface,
+            ]);
+            if table != "auto" {
+                route.args(["table", table]);
+            }
+            run_logged(&mut route)?;
+        }
+    }
+    if !settings.dns.is_empty() {
+        warn!("DNS of {interface} is not applied in userspace mode");
+    }
+    for hook in &settings.post_up {
+        run_hook(hook, interface)?;
+    }
+    Ok(())
+}
+
+// what wg-quick reads from a config besides the settings `wg setconf` takes
+#[derive(Debug, Default, PartialEq)]
+struct InterfaceSettings {
+    addresses: Vec<String>,
+    dns: Vec<String>,
+    mtu: Option<String>,
+    table: Option<String>,
+    pre_up: Vec<String>,
+    post_up: Vec<String>,
+    allowed_ips: Vec<String>,
+}
+
+impl InterfaceSettings {
+    fn parse(config: &str) -> InterfaceSettings {
+        let mut settings = InterfaceSettings::default();
+        let mut in_interface = false;
+        for line in config.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_interface = line.eq_ignore_ascii_case("[Interface]");
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) if !key.trim_start().starts_with('#') => {
+                    (key.trim().to_ascii_lowercase(), value.trim().to_string())
+                }
+                _ => continue,
+            };
+            let list = || value.split(',').map(|item| item.trim().to_string());
+            match (in_interface, key.as_str()) {
+                (true, "address") => settings.addresses.extend(list()),
+                (true, "dns") => settings.dns.extend(list()),
+                (true, "mtu") => settings.mtu = Some(value),
+                (true, "table") => settings.table = Some(value),
+                (true, "preup") => settings.pre_up.push(value),
+                (true, "postup") => settings.post_up.push(value),
+                (false, "allowedips") => settings.allowed_ips.extend(list()),
+                _ => {}
+            }
+        }
+        settings
+    }
+}
+
+fn run_hook(hook: &str, interface: &str) -> Result<(), io::Error> {
+    run_logged(Command::new("bash").args(["-c", &hook.replace("%i", interface)]))
+}
+
+fn ip_family(address: &str) -> &'static str {
+    match address.contains(':') {
+        true => "-6",
+        false => "-4",
     }
 }
 
 pub fn stop_wireguard(interface: &String) -> Result<(), io::Error> {
-    match Command::new("wg-quick").arg("down").arg(interface).output() {
-        Err(error) => Err(error),
-        _ => Ok(()),
+    run_logged(Command::new("wg-quick").arg("down").arg(interface))
+}
+
+// a command that exits unsuccessfully is an error carrying its output
+fn run_logged(command: &mut Command) -> Result<(), io::Error> {
+    let program = format!("{:?}", command);
+    let output = command.output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stdout.trim().is_empty() {
+        debug!(command = %program, "{}", stdout.trim_end());
+    }
+    if !output.status.success() {
+        warn!(command = %program, status = %output.status, "{}", stderr.trim_end());
+        return Err(io::Error::other(format!(
+            "{program} exited with {}: {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    if !stderr.trim().is_empty() {
+        debug!(command = %program, "{}", stderr.trim_end());
     }
+    Ok(())
 }
 
 pub enum RestartWireGuardErrorType {
     StopFailed(io::Error),
     StartFailed(io::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interface_settings_are_read_like_wg_quick_does() {
+        let config = "# Generated from WireGuard UI\n\
+                      [Interface]\n\
+                      Address = 10.8.0.1/24,fd00::1/64\n\
+                      ListenPort = 51820\n\
+                      PrivateKey = YJFW40qeR+g7MVHDvtuJMhI2qctXy1mPSvLR0j6MF2g=\n\
+                      \n\
+                      MTU = 1420\n\
+                      PostUp = iptables -A FORWARD -i %i -j ACCEPT; echo a=b\n\
+                      \n\
+                      [Peer]\n\
+                      PublicKey = 1IEkwtnf2eQjj9+cxAYLNr9C0z8mNLTSOErAPeGExHA=\n\
+                      AllowedIPs = 10.8.0.2/32, 192.168.10.0/24\n\
+                      \n\
+                      # [Peer]\n\
+                      # AllowedIPs = 10.8.0.3/32\n";
+        assert_eq!(
+            InterfaceSettings::parse(config),
+            InterfaceSettings {
+                addresses: vec!["10.8.0.1/24".into(), "fd00::1/64".into()],
+                mtu: Some("1420".into()),
+                post_up: vec!["iptables -A FORWARD -i %i -j ACCEPT; echo a=b".into()],
+                allowed_ips: vec!["10.8.0.2/32".into(), "192.168.10.0/24".into()],
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,718 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use wireguard_ui_backend::data::data_manager;
+
+use common::{TestApp, ADMIN_TOKEN};
+
+#[tokio::test]
+async fn admin_routes_require_the_admin_token() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .send(Method::GET, "/wireguard/clients", None, &[], None)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .send(Method::GET, "/wireguard/clients", Some("wrong"), &[], None)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .send(
+            Method::GET,
+            "/wireguard/clients",
+            Some(ADMIN_TOKEN),
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = app.send(Method::GET, "/healthz", None, &[], None).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admin_routes_are_open_without_require_admin_token() {
+    let app = TestApp::with_config("require_admin_token: false").await;
+
+    let response = app
+        .send(Method::GET, "/wireguard/clients", None, &[], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn enabled_clients_become_peers_of_the_interface() {
+    let app = TestApp::with_server().await;
+    let phone = app.create_client("phone").await;
+    let laptop = app.create_client("laptop").await;
+    assert_ne!(phone["server_allowed_ips"], laptop["server_allowed_ips"]);
+
+    let mut peers = app.peer_names().await;
+    peers.sort();
+    assert_eq!(peers, ["laptop", "phone"]);
+
+    let response = app.admin(Method::GET, "/wireguard/peers", None).await;
+    assert!(response.body.as_array().unwrap().iter().all(|peer| {
+        // the memory backend completes a handshake as soon as a peer is added
+        !peer["last_handshake"].is_null()
+    }));
+
+    let uuid = phone["uuid"].as_str().unwrap();
+    let response = app
+        .admin(
+            Method::PATCH,
+            &format!("/wireguard/clients/{uuid}"),
+            Some(json!({ "enabled": false })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.peer_names().await, ["laptop"]);
+
+    let uuid = laptop["uuid"].as_str().unwrap();
+    let response = app
+        .admin(Method::DELETE, &format!("/wireguard/clients/{uuid}"), None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert!(app.peer_names().await.is_empty());
+}
+
+#[tokio::test]
+async fn stale_if_match_is_rejected() {
+    let app = TestApp::with_server().await;
+    let client = app.create_client("phone").await;
+    let uuid = client["uuid"].as_str().unwrap();
+    let uri = format!("/wireguard/clients/{uuid}");
+
+    let response = app.admin(Method::GET, &uri, None).await;
+    let etag = response.etag();
+    let response = app
+        .send(
+            Method::PATCH,
+            &uri,
+            Some(ADMIN_TOKEN),
+            &[("If-Match", &etag)],
+            Some(json!({ "name": "tablet" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .send(
+            Method::PATCH,
+            &uri,
+            Some(ADMIN_TOKEN),
+            &[("If-Match", &etag)],
+            Some(json!({ "name": "watch" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::PRECONDITION_FAILED);
+    let response = app.admin(Method::GET, &uri, None).await;
+    assert_eq!(response.body["name"], "tablet");
+}
+
+#[tokio::test]
+async fn invalid_clients_are_rejected_with_field_errors() {
+    let app = TestApp::with_server().await;
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients",
+            Some(json!({ "name": "phone", "server_allowed_ips": ["not an address"] })),
+        )
+        .await;
+    assert!(response.status.is_client_error(), "{}", response.status);
+    let response = app.admin(Method::GET, "/wireguard/clients", None).await;
+    assert!(response.body.as_array().unwrap().is_empty());
+
+    let mut client = app.create_client("phone").await;
+    let uri = format!("/wireguard/clients/{}", client["uuid"].as_str().unwrap());
+    client["public_key"] = json!("BqvHrNdoiKzcmUEVvH4xC6huaxY2wRnKfVdW+0Uw61c=");
+    let response = app.admin(Method::PUT, &uri, Some(client.clone())).await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        response.body
+    );
+    assert!(response.body.to_string().contains("public_key"));
+    let response = app
+        .admin(Method::PUT, "/wireguard/clients", Some(json!([client])))
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        response.body
+    );
+}
+
+#[tokio::test]
+async fn portal_cannot_enable_clients_disabled_by_an_admin() {
+    let app = TestApp::with_server().await;
+    let client = app.create_client("phone").await;
+    let uuid = client["uuid"].as_str().unwrap();
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/portal-tokens",
+            Some(json!({ "name": "alice", "clients": [uuid] })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let portal_token = response.body["token"].as_str().unwrap().to_string();
+
+    let enable = format!("/portal/clients/{uuid}/enable");
+    let disable = format!("/portal/clients/{uuid}/disable");
+    let response = app
+        .send(Method::POST, &disable, Some(&portal_token), &[], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .send(Method::POST, &enable, Some(&portal_token), &[], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .admin(
+            Method::PATCH,
+            &format!("/wireguard/clients/{uuid}"),
+            Some(json!({ "enabled": false })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .send(Method::POST, &enable, Some(&portal_token), &[], None)
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    // the token only reaches its own clients
+    let other = app.create_client("laptop").await;
+    let other_uuid = other["uuid"].as_str().unwrap();
+    let response = app
+        .send(
+            Method::POST,
+            &format!("/portal/clients/{other_uuid}/disable"),
+            Some(&portal_token),
+            &[],
+            None,
+        )
+        .await;
+    assert!(response.status.is_client_error(), "{}", response.status);
+    assert!(app.peer_names().await.contains(&"laptop".to_string()));
+}
+
+#[tokio::test]
+async fn changes_that_fail_to_save_are_rolled_back() {
+    let app = TestApp::with_server().await;
+    let client = app.create_client("phone").await;
+    let uuid = client["uuid"].as_str().unwrap();
+
+    // a directory in place of the temporary file makes every save fail
+    let blocker = data_manager::data_file().with_extension("json.tmp");
+    std::fs::create_dir_all(&blocker).unwrap();
+    let created = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients",
+            Some(json!({ "name": "laptop", "enabled": true })),
+        )
+        .await;
+    let deleted = app
+        .admin(Method::DELETE, &format!("/wireguard/clients/{uuid}"), None)
+        .await;
+    std::fs::remove_dir(&blocker).unwrap();
+    assert_eq!(created.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(deleted.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let response = app.admin(Method::GET, "/wireguard/clients", None).await;
+    let names: Vec<&str> = response
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|client| client["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["phone"]);
+}
+
+#[tokio::test]
+async fn portal_can_enable_clients_created_disabled() {
+    let app = TestApp::with_server().await;
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients",
+            Some(json!({ "name": "phone" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["enabled"], false);
+    assert_eq!(response.body["admin_disabled"], false);
+    let uuid = response.body["uuid"].as_str().unwrap();
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/portal-tokens",
+            Some(json!({ "name": "alice", "clients": [uuid] })),
+        )
+        .await;
+    let portal_token = response.body["token"].as_str().unwrap().to_string();
+
+    let response = app
+        .send(
+            Method::POST,
+            &format!("/portal/clients/{uuid}/enable"),
+            Some(&portal_token),
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.peer_names().await, ["phone"]);
+}
+
+#[tokio::test]
+async fn access_rules_only_name_existing_peers() {
+    let app = TestApp::with_server().await;
+    let client = app.create_client("phone").await;
+    let uuid = client["uuid"].as_str().unwrap();
+
+    for (source, destination) in [
+        (json!({ "client": uuid }), json!({ "cidr": "10.8.0.0/28" })),
+        (json!({ "client": uuid }), json!({ "cidr": "10.8.0.5" })),
+    ] {
+        let response = app
+            .admin(
+                Method::PUT,
+                "/wireguard/policy",
+                Some(json!({
+                    "isolation": "isolate_all",
+                    "rules": [{ "source": source, "destination": destination }],
+                })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    for (source, destination) in [
+        (
+            json!({ "client": uuid }),
+            json!({ "cidr": "192.168.1.0/24" }),
+        ),
+        (json!({ "group": "missing" }), json!({ "client": uuid })),
+        (
+            json!({ "client": "00000000-0000-0000-0000-000000000000" }),
+            json!({ "client": uuid }),
+        ),
+    ] {
+        let response = app
+            .admin(
+                Method::PUT,
+                "/wireguard/policy",
+                Some(json!({
+                    "isolation": "isolate_all",
+                    "rules": [{ "source": source, "destination": destination }],
+                })),
+            )
+            .await;
+        assert_eq!(
+            response.status,
+            StatusCode::BAD_REQUEST,
+            "{}",
+            response.body
+        );
+    }
+}
+
+#[tokio::test]
+async fn group_defaults_and_client_addresses_are_validated() {
+    let app = TestApp::with_server().await;
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/groups",
+            Some(json!({ "name": "staff", "dns": ["not a server!"], "client_allowed_ips": ["nope"] })),
+        )
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        response.body
+    );
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/groups",
+            Some(json!({ "name": "staff", "dns": ["1.1.1.1"], "client_allowed_ips": ["10.8.0.0/24"] })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .admin(
+            Method::PUT,
+            "/wireguard/groups/staff",
+            Some(json!({ "name": "staff", "client_allowed_ips": ["10.8.0.0/33"] })),
+        )
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        response.body
+    );
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients",
+            Some(json!({ "name": "phone", "address": "192.168.1.2/32" })),
+        )
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        response.body
+    );
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients",
+            Some(json!({ "name": "phone", "address": "10.8.0.2/32" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+}
+
+#[tokio::test]
+async fn reload_applies_a_new_private_key_and_listen_port() {
+    let app = TestApp::with_server().await;
+    app.create_client("phone").await;
+    let private_key = "GOb2/TDoBE2zgJpyJaQSzvZGmIUiH7HZlXBUx+Xgo1c=";
+
+    // changed behind the back of the API, like an edit of data.json
+    app.state
+        .write(|app_values| {
+            let server = app_values.wireguard_data.server.as_mut().unwrap();
+            server.private_key = private_key.to_string();
+            server.listen_port = 51821;
+        })
+        .await
+        .unwrap();
+    assert_ne!(app.interface_identity().0, private_key);
+
+    let response = app.admin(Method::POST, "/wireguard/reload", None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.interface_identity(), (private_key.to_string(), 51821));
+    assert_eq!(app.peer_names().await, ["phone"]);
+}
+
+#[tokio::test]
+async fn server_changes_reach_the_interface() {
+    let app = TestApp::with_server().await;
+    app.create_client("phone").await;
+    let private_key = "GOb2/TDoBE2zgJpyJaQSzvZGmIUiH7HZlXBUx+Xgo1c=";
+
+    let response = app
+        .admin(
+            Method::PATCH,
+            "/wireguard/server",
+            Some(json!({ "private_key": private_key, "listen_port": 51821 })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.interface_identity(), (private_key.to_string(), 51821));
+    assert_eq!(app.peer_names().await, ["phone"]);
+
+    let response = app
+        .admin(
+            Method::PUT,
+            "/wireguard/server",
+            Some(json!({
+                "endpoint": "vpn.example.com:51820",
+                "address": ["10.8.0.1/24"],
+                "listen_port": 51822,
+                "private_key": private_key,
+            })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.interface_identity(), (private_key.to_string(), 51822));
+
+    let response = app.admin(Method::DELETE, "/wireguard/server", None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert!(!app.state.snapshot().backend.is_up());
+}
+
+#[tokio::test]
+async fn activating_a_server_key_rotation_rekeys_the_interface() {
+    let app = TestApp::with_server().await;
+    let (old_key, _) = app.interface_identity();
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/server/rotate-keys",
+            Some(json!({ "grace_period_hours": 24 })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.interface_identity().0, old_key);
+
+    let response = app
+        .admin(Method::POST, "/wireguard/server/rotate-keys/activate", None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let server = app.state.snapshot().wireguard_data.server.clone().unwrap();
+    assert_ne!(server.private_key, old_key);
+    assert_eq!(app.interface_identity().0, server.private_key);
+}
+
+#[tokio::test]
+async fn clients_of_a_bare_server_address_get_addresses_from_its_subnet() {
+    let app = TestApp::new().await;
+    let response = app
+        .admin(
+            Method::PUT,
+            "/wireguard/server",
+            Some(json!({
+                "endpoint": "vpn.example.com:51820",
+                "address": ["10.8.0.1"],
+                "listen_port": 51820,
+            })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let client = app.create_client("phone").await;
+    assert_eq!(client["address"], "10.8.0.2/32");
+    // without a group default the client routes everything through the tunnel
+    assert_eq!(client["client_allowed_ips"], json!(["0.0.0.0/0"]));
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients",
+            Some(json!({ "name": "laptop", "address": "10.8.0.3/32" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients",
+            Some(json!({ "name": "tablet", "address": "10.9.0.2/32" })),
+        )
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        response.body
+    );
+}
+
+#[tokio::test]
+async fn bulk_operations_are_all_or_nothing() {
+    let app = TestApp::with_server().await;
+    let phone = app.create_client("phone").await;
+    let laptop = app.create_client("laptop").await;
+    let before = app.admin(Method::GET, "/wireguard/clients", None).await;
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients/bulk",
+            Some(json!({
+                "operations": [
+                    { "action": "disable", "uuid": phone["uuid"] },
+                    { "action": "delete", "uuid": laptop["uuid"] },
+                    { "action": "create", "client": { "name": "tablet", "enabled": true } },
+                    {
+                        "action": "create",
+                        "client": { "name": "watch", "server_allowed_ips": ["not an address"] },
+                    },
+                ],
+            })),
+        )
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        response.body
+    );
+    assert_eq!(response.body["applied"], false);
+    let results = response.body["results"].as_array().unwrap();
+    let succeeded: Vec<bool> = results
+        .iter()
+        .map(|result| result["success"].as_bool().unwrap())
+        .collect();
+    assert_eq!(succeeded, [true, true, true, false]);
+    assert_eq!(results[3]["errors"][0]["field"], "server_allowed_ips[0]");
+
+    let after = app.admin(Method::GET, "/wireguard/clients", None).await;
+    assert_eq!(after.body, before.body);
+    assert_eq!(after.etag(), before.etag());
+    let mut peers = app.peer_names().await;
+    peers.sort();
+    assert_eq!(peers, ["laptop", "phone"]);
+}
+
+#[tokio::test]
+async fn dry_run_imports_are_not_saved() {
+    let app = TestApp::with_server().await;
+    let import = json!([{ "name": "phone" }, { "name": "laptop" }]);
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/clients/import?dry_run=true",
+            Some(import.clone()),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["dry_run"], true);
+    assert_eq!(response.body["applied"], false);
+    assert_eq!(response.body["results"].as_array().unwrap().len(), 2);
+    let response = app.admin(Method::GET, "/wireguard/clients", None).await;
+    assert!(response.body.as_array().unwrap().is_empty());
+    let saved = std::fs::read_to_string(data_manager::data_file()).unwrap();
+    assert!(!saved.contains("laptop"));
+
+    let response = app
+        .admin(Method::POST, "/wireguard/clients/import", Some(import))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["applied"], true);
+    let saved = std::fs::read_to_string(data_manager::data_file()).unwrap();
+    assert!(saved.contains("laptop"));
+}
+
+#[tokio::test]
+async fn invitations_redeem_exactly_once() {
+    let app = TestApp::with_server().await;
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/groups",
+            Some(json!({ "name": "staff" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/invitations",
+            Some(json!({ "target": { "group": "staff" }, "label": "New laptop" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let id = response.body["invitation"]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let invite = format!("/invite/{}", response.body["token"].as_str().unwrap());
+
+    let response = app.send(Method::GET, &invite, None, &[], None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["label"], "New laptop");
+
+    let response = app
+        .send(
+            Method::POST,
+            &invite,
+            None,
+            &[],
+            Some(json!({ "name": "laptop" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.peer_names().await, ["laptop"]);
+
+    let response = app
+        .send(
+            Method::POST,
+            &invite,
+            None,
+            &[],
+            Some(json!({ "name": "tablet" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::GONE, "{}", response.body);
+    let response = app.send(Method::GET, &invite, None, &[], None).await;
+    assert_eq!(response.status, StatusCode::GONE, "{}", response.body);
+    assert_eq!(app.peer_names().await, ["laptop"]);
+
+    let response = app.admin(Method::GET, "/wireguard/invitations", None).await;
+    let invitation = &response.body[0];
+    assert!(invitation["redeemed_at"].is_u64());
+    let client = &app.state.snapshot().wireguard_data.clients[0];
+    assert_eq!(invitation["client"], client.uuid.to_string());
+
+    let response = app.admin(Method::GET, "/wireguard/audit", None).await;
+    let redeemed: Vec<_> = response
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|event| event["details"] == format!("invitation {id}"))
+        .filter(|event| event["action"] == "invitation.redeemed")
+        .collect();
+    assert_eq!(redeemed.len(), 1);
+    assert_eq!(redeemed[0]["target"], format!("client:{}", client.uuid));
+}
+
+#[tokio::test]
+async fn expired_and_revoked_invitations_are_refused() {
+    let app = TestApp::with_server().await;
+    let client = app.create_client("phone").await;
+    let target = json!({ "client": client["uuid"] });
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/invitations",
+            Some(json!({ "target": target, "expires_in_hours": 0 })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let expired = format!("/invite/{}", response.body["token"].as_str().unwrap());
+    let response = app.send(Method::POST, &expired, None, &[], None).await;
+    assert_eq!(response.status, StatusCode::GONE, "{}", response.body);
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/invitations",
+            Some(json!({ "target": target })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let id = response.body["invitation"]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let revoked = format!("/invite/{}", response.body["token"].as_str().unwrap());
+    let response = app
+        .admin(
+            Method::DELETE,
+            &format!("/wireguard/invitations/{id}"),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app.send(Method::POST, &revoked, None, &[], None).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND, "{}", response.body);
+
+    let response = app.admin(Method::GET, "/wireguard/invitations", None).await;
+    let invitations = response.body.as_array().unwrap();
+    assert_eq!(invitations.len(), 1);
+    assert!(invitations[0]["redeemed_at"].is_null());
+}
@@ -0,0 +1,173 @@
+#![allow(dead_code)]
+use std::path::PathBuf;
+use std::sync::{Arc, Once};
+
+use axum::body::Body;
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, ETAG};
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tokio::sync::{Mutex, MutexGuard};
+use tower::ServiceExt;
+
+use wireguard_ui_backend::backend::MemoryBackend;
+use wireguard_ui_backend::data::config::AppConfig;
+use wireguard_ui_backend::data::data_manager::{self, DataPaths};
+use wireguard_ui_backend::data::wireguard_data::WireGuardData;
+use wireguard_ui_backend::server;
+use wireguard_ui_backend::state::AppState;
+use wireguard_ui_backend::WireGuardAppValues;
+
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+// every app of a test binary saves into the same data directory, so they run one at a time
+static RUNNING: Mutex<()> = Mutex::const_new(());
+static PATHS: Once = Once::new();
+
+pub struct TestApp {
+    pub state: AppState,
+    router: Router,
+    _running: MutexGuard<'static, ()>,
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+impl TestResponse {
+    pub fn etag(&self) -> String {
+        self.headers[ETAG].to_str().unwrap().to_string()
+    }
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with_config("{}").await
+    }
+
+    // the yaml is merged over a config for the memory backend with a known admin token
+    pub async fn with_config(yaml: &str) -> Self {
+        let running = RUNNING.lock().await;
+        PATHS.call_once(|| {
+            let data_dir: PathBuf = std::env::temp_dir()
+                .join(format!("wireguard-ui-backend-tests-{}", std::process::id()));
+            std::fs::create_dir_all(&data_dir).unwrap();
+            data_manager::set_paths(DataPaths {
+                config: data_dir.join("config.yaml"),
+                data_dir,
+            });
+        });
+        let mut config: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        let config_map = config.as_mapping_mut().unwrap();
+        for (key, value) in [
+            ("wireguard_backend", "memory"),
+            ("admin_token", ADMIN_TOKEN),
+        ] {
+            if !config_map.contains_key(key) {
+                config_map.insert(key.into(), value.into());
+            }
+        }
+        let config: AppConfig = serde_yaml::from_value(config).unwrap();
+        let state = AppState::new(WireGuardAppValues {
+            backend: Arc::new(MemoryBackend::default()),
+            config,
+            wireguard_data: WireGuardData::default(),
+        })
+        .unwrap();
+        TestApp {
+            router: server::router(state.clone()),
+            state,
+            _running: running,
+        }
+    }
+
+    // a server with an address range for clients and the interface brought up
+    pub async fn with_server() -> Self {
+        let app = Self::new().await;
+        let response = app
+            .admin(
+                Method::PUT,
+                "/wireguard/server",
+                Some(json!({
+                    "endpoint": "vpn.example.com:51820",
+                    "address": ["10.8.0.1/24"],
+                    "listen_port": 51820,
+                })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app.admin(Method::POST, "/wireguard/start", None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        app
+    }
+
+    pub async fn admin(&self, method: Method, uri: &str, body: Option<Value>) -> TestResponse {
+        self.send(method, uri, Some(ADMIN_TOKEN), &[], body).await
+    }
+
+    pub async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> TestResponse {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = match body {
+            Some(body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        TestResponse {
+            status,
+            headers,
+            body: serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        }
+    }
+
+    pub async fn create_client(&self, name: &str) -> Value {
+        let response = self
+            .admin(
+                Method::POST,
+                "/wireguard/clients",
+                Some(json!({ "name": name, "enabled": true })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        response.body
+    }
+
+    // the private key and listen port the interface currently has
+    pub fn interface_identity(&self) -> (String, u16) {
+        let host = self.state.snapshot().backend.read_interface_data().unwrap();
+        (host.private_key.unwrap().to_string(), host.listen_port)
+    }
+
+    pub async fn peer_names(&self) -> Vec<String> {
+        let response = self.admin(Method::GET, "/wireguard/peers", None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        response
+            .body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|peer| peer["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+}
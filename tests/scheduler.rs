@@ -0,0 +1,110 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use wireguard_ui_backend::data::data_manager;
+use wireguard_ui_backend::scheduler;
+
+use common::TestApp;
+
+#[tokio::test]
+async fn due_server_keys_are_activated_on_the_interface() {
+    let app = TestApp::with_server().await;
+    let (old_key, _) = app.interface_identity();
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/server/rotate-keys",
+            Some(json!({ "grace_period_hours": 24 })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    scheduler::run_scheduled_tasks(&app.state).await;
+    assert_eq!(app.interface_identity().0, old_key);
+
+    app.state
+        .write(|app_values| {
+            let server = app_values.wireguard_data.server.as_mut().unwrap();
+            server.pending_key.as_mut().unwrap().activate_at = 0;
+        })
+        .await
+        .unwrap();
+    scheduler::run_scheduled_tasks(&app.state).await;
+    let server = app.state.snapshot().wireguard_data.server.clone().unwrap();
+    assert!(server.pending_key.is_none());
+    assert_eq!(app.interface_identity().0, server.private_key);
+}
+
+#[tokio::test]
+async fn expired_clients_are_removed_from_the_interface() {
+    let app = TestApp::with_server().await;
+    app.create_client("phone").await;
+    app.create_client("laptop").await;
+
+    scheduler::run_scheduled_tasks(&app.state).await;
+    let mut peers = app.peer_names().await;
+    peers.sort();
+    assert_eq!(peers, ["laptop", "phone"]);
+
+    // the expiry passes without any change that would apply the peers
+    let revision = app
+        .state
+        .write(|app_values| {
+            let client = app_values
+                .wireguard_data
+                .clients
+                .iter_mut()
+                .find(|client| client.name == "laptop")
+                .unwrap();
+            client.expires_at = Some(1);
+            app_values.wireguard_data.revision
+        })
+        .await
+        .unwrap();
+    let mut peers = app.peer_names().await;
+    peers.sort();
+    assert_eq!(peers, ["laptop", "phone"]);
+
+    scheduler::run_scheduled_tasks(&app.state).await;
+    assert_eq!(app.peer_names().await, ["phone"]);
+    assert_eq!(app.state.snapshot().wireguard_data.revision, revision);
+}
+
+#[tokio::test]
+async fn scheduled_changes_that_fail_to_apply_are_rolled_back() {
+    let app = TestApp::with_server().await;
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/users",
+            Some(json!({ "name": "alice", "transfer_quota": 0 })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/users/alice/devices",
+            Some(json!({ "enabled": true })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let revision = app.state.snapshot().wireguard_data.revision;
+
+    // a directory in place of the temporary file makes every save fail
+    let blocker = data_manager::data_file().with_extension("json.tmp");
+    std::fs::create_dir_all(&blocker).unwrap();
+    scheduler::run_scheduled_tasks(&app.state).await;
+    std::fs::remove_dir(&blocker).unwrap();
+
+    let data = &app.state.snapshot().wireguard_data;
+    assert_eq!(data.revision, revision);
+    assert!(data.clients[0].enabled);
+    assert_eq!(app.peer_names().await, ["alice-1"]);
+
+    scheduler::run_scheduled_tasks(&app.state).await;
+    assert!(!app.state.snapshot().wireguard_data.clients[0].enabled);
+    assert!(app.peer_names().await.is_empty());
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use std::collections::HashMap;
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use wireguard_ui_backend::scheduler;
+
+use common::{TestApp, ADMIN_TOKEN};
+
+#[tokio::test]
+async fn devices_share_the_user_limits() {
+    let app = TestApp::with_server().await;
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/users",
+            Some(json!({ "name": "alice", "expires_at": 4102444800000u64, "max_devices": 1 })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/users/alice/devices",
+            Some(json!({ "enabled": true })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["user"], "alice");
+    assert_eq!(response.body["expires_at"], 4102444800000u64);
+
+    let response = app
+        .admin(Method::POST, "/wireguard/users/alice/devices", None)
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    let response = app.admin(Method::GET, "/wireguard/users/alice", None).await;
+    assert_eq!(response.body["devices"].as_array().unwrap().len(), 1);
+    assert_eq!(app.peer_names().await, ["alice-1"]);
+}
+
+#[tokio::test]
+async fn device_changes_check_if_match() {
+    let app = TestApp::with_server().await;
+    let response = app
+        .admin(
+            Method::POST,
+            "/wireguard/users",
+            Some(json!({ "name": "alice" })),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let etag = app
+        .admin(Method::GET, "/wireguard/users/alice", None)
+        .await
+        .etag();
+
+    let response = app
+        .send(
+            Method::POST,
+            "/wireguard/users/alice/devices",
+            Some(ADMIN_TOKEN),
+            &[("If-Match", &etag)],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let uuid = response.body["uuid"].as_str().unwrap().to_string();
+
+    let response = app
+        .send(
+            Method::DELETE,
+            &format!("/wireguard/users/alice/devices/{uuid}"),
+            Some(ADMIN_TOKEN),
+            &[("If-Match", &etag)],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::PRECONDITION_FAILED);
+
+    let etag = app
+        .admin(Method::GET, "/wireguard/users/alice", None)
+        .await
+        .etag();
+    let response = app
+        .send(
+            Method::DELETE,
+            &format!("/wireguard/users/alice/devices/{uuid}"),
+            Some(ADMIN_TOKEN),
+            &[("If-Match", &etag)],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+}
+
+#[tokio::test]
+async fn users_over_their_quota_lose_their_devices() {
+    let app = TestApp::with_server().await;
+    for (name, transfer_quota) in [("alice", Some(0)), ("bob", None)] {
+        let response = app
+            .admin(
+                Method::POST,
+                "/wireguard/users",
+                Some(json!({ "name": name, "transfer_quota": transfer_quota })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app
+            .admin(
+                Method::POST,
+                &format!("/wireguard/users/{name}/devices"),
+                Some(json!({ "enabled": true })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    let over_quota = app
+        .state
+        .write(|app_values| {
+            app_values
+                .wireguard_data
+                .enforce_transfer_quotas(&HashMap::new())
+        })
+        .await
+        .unwrap();
+    assert_eq!(over_quota, ["alice"]);
+
+    let response = app.admin(Method::GET, "/wireguard/users/alice", None).await;
+    let device = &response.body["devices"][0];
+    assert_eq!(device["enabled"], false);
+    assert_eq!(device["admin_disabled"], true);
+    let response = app.admin(Method::GET, "/wireguard/users/bob", None).await;
+    assert_eq!(response.body["devices"][0]["enabled"], true);
+}
+
+#[tokio::test]
+async fn expired_users_lose_their_devices_on_the_next_scheduler_pass() {
+    let app = TestApp::with_server().await;
+    for name in ["alice", "bob"] {
+        let response = app
+            .admin(
+                Method::POST,
+                "/wireguard/users",
+                Some(json!({ "name": name, "expires_at": 4102444800000u64 })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let response = app
+            .admin(
+                Method::POST,
+                &format!("/wireguard/users/{name}/devices"),
+                Some(json!({ "enabled": true })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+
+    // the expiry of alice passes without any change that would apply the peers
+    app.state
+        .write(|app_values| {
+            let data = &mut app_values.wireguard_data;
+            let user = data
+                .users
+                .iter_mut()
+                .find(|user| user.name == "alice")
+                .unwrap();
+            user.expires_at = Some(1);
+            for device in data
+                .clients
+                .iter_mut()
+                .filter(|device| device.user.as_deref() == Some("alice"))
+            {
+                user.apply_to_device(device);
+            }
+        })
+        .await
+        .unwrap();
+    let mut peers = app.peer_names().await;
+    peers.sort();
+    assert_eq!(peers, ["alice-1", "bob-1"]);
+
+    scheduler::run_scheduled_tasks(&app.state).await;
+    assert_eq!(app.peer_names().await, ["bob-1"]);
+}